@@ -0,0 +1,65 @@
+use num::complex::Complex64;
+use crate::constants::{ONE, TOL, ZERO};
+use super::{Circuit, Gate, Instruction, Operation};
+
+impl Circuit {
+    /// Returns an equivalent circuit in which every run of two or more unconditioned
+    /// single-qubit gates on the same qubit is replaced by one `U` gate (plus a global
+    /// phase), so that the run costs a single traversal of the DD.
+    ///
+    /// A run ends at the first instruction that touches its qubit; gates on other
+    /// qubits in between commute with it and do not break it.
+    pub fn fuse_single_qubit_gates(&self) -> Circuit {
+        let mut pending: Vec<Vec<Gate>> = vec![Vec::new(); self.num_qubits];
+        let mut fused = Circuit::new(self.num_qubits, self.num_clbits);
+        for inst in &self.instructions {
+            if let (Operation::Gate { gate, qubits }, None) = (&inst.op, &inst.condition) {
+                if gate.num_qubits() == 1 {
+                    pending[qubits[0]].push(*gate);
+                    continue;
+                }
+            }
+            let touched = match &inst.op {
+                Operation::Barrier(qubits) if qubits.is_empty() => (0..self.num_qubits).collect(),
+                _ => inst.qubits(),
+            };
+            for q in touched {
+                flush(&mut fused, q, &mut pending[q]);
+            }
+            fused.instructions.push(inst.clone());
+        }
+        for (q, run) in pending.iter_mut().enumerate() {
+            flush(&mut fused, q, run);
+        }
+        fused
+    }
+}
+
+fn flush(fused: &mut Circuit, qubit: usize, run: &mut Vec<Gate>) {
+    match run.len() {
+        0 => {}
+        1 => {
+            fused.gate(run[0], &[qubit]);
+        }
+        _ => {
+            let m = run.iter().fold([[ONE, ZERO], [ZERO, ONE]], |acc, g| mul(g.matrix().unwrap(), acc));
+            let (gate, alpha) = Gate::from_matrix(m);
+            // Runs such as H H or S Sdg cancel down to a phase.
+            let is_identity = m[0][1].norm() < TOL && m[1][0].norm() < TOL && (m[0][0] - m[1][1]).norm() < TOL;
+            if !is_identity {
+                fused.gate(gate, &[qubit]);
+            }
+            if alpha.abs() > TOL {
+                fused.instructions.push(Instruction::new(Operation::GlobalPhase(alpha)));
+            }
+        }
+    }
+    run.clear();
+}
+
+fn mul(a: [[Complex64; 2]; 2], b: [[Complex64; 2]; 2]) -> [[Complex64; 2]; 2] {
+    [
+        [a[0][0] * b[0][0] + a[0][1] * b[1][0], a[0][0] * b[0][1] + a[0][1] * b[1][1]],
+        [a[1][0] * b[0][0] + a[1][1] * b[1][0], a[1][0] * b[0][1] + a[1][1] * b[1][1]],
+    ]
+}
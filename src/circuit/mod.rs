@@ -0,0 +1,469 @@
+// Circuit IR: a plain record of the program, independent of the DD state.
+// The simulator consumes it through `QuantumCircuit::run`.
+use num::complex::Complex64;
+use crate::constants::{INV_ROOT_TWO, IOTA, MINUS_IOTA, MINUS_ONE, ONE, TOL, ZERO};
+
+mod fuse;
+mod optimize;
+mod random;
+
+pub use random::RandomCircuit;
+
+/// Gates understood by the IR. Every variant maps onto one `apply_*` method of `QuantumCircuit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gate {
+    H,
+    X,
+    Y,
+    Z,
+    S,
+    Sdg,
+    T,
+    Tdg,
+    /// Phase gate diag(1, exp(i*theta)).
+    P(f64),
+    /// U(theta, phi, lambda), same convention as Qiskit's U3.
+    U(f64, f64, f64),
+    /// Controlled-X, qubits are (control, target).
+    CX,
+    /// Controlled-Z, qubits are (control, target).
+    CZ,
+}
+
+impl Gate {
+    /// Number of qubits the gate acts on.
+    pub fn num_qubits(&self) -> usize {
+        match self {
+            Gate::CX | Gate::CZ => 2,
+            _ => 1,
+        }
+    }
+
+    /// Lower-case name, matching the OpenQASM qelib1 spelling.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gate::H => "h",
+            Gate::X => "x",
+            Gate::Y => "y",
+            Gate::Z => "z",
+            Gate::S => "s",
+            Gate::Sdg => "sdg",
+            Gate::T => "t",
+            Gate::Tdg => "tdg",
+            Gate::P(_) => "p",
+            Gate::U(..) => "u",
+            Gate::CX => "cx",
+            Gate::CZ => "cz",
+        }
+    }
+
+    /// Row-major 2x2 matrix of a single-qubit gate, `None` for two-qubit gates.
+    pub fn matrix(&self) -> Option<[[Complex64; 2]; 2]> {
+        let phase = |theta: f64| [[ONE, ZERO], [ZERO, Complex64::from_polar(1.0, theta)]];
+        Some(match *self {
+            Gate::H => [[INV_ROOT_TWO, INV_ROOT_TWO], [INV_ROOT_TWO, -INV_ROOT_TWO]],
+            Gate::X => [[ZERO, ONE], [ONE, ZERO]],
+            Gate::Y => [[ZERO, MINUS_IOTA], [IOTA, ZERO]],
+            Gate::Z => [[ONE, ZERO], [ZERO, MINUS_ONE]],
+            Gate::S => phase(std::f64::consts::FRAC_PI_2),
+            Gate::Sdg => phase(-std::f64::consts::FRAC_PI_2),
+            Gate::T => phase(std::f64::consts::FRAC_PI_4),
+            Gate::Tdg => phase(-std::f64::consts::FRAC_PI_4),
+            Gate::P(theta) => phase(theta),
+            Gate::U(theta, phi, lambda) => {
+                let (cos, sin) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+                [
+                    [Complex64::new(cos, 0.0), -Complex64::from_polar(sin, lambda)],
+                    [Complex64::from_polar(sin, phi), Complex64::from_polar(cos, phi + lambda)],
+                ]
+            }
+            Gate::CX | Gate::CZ => return None,
+        })
+    }
+
+    /// Splits a single-qubit unitary into `U(theta, phi, lambda)` and the global phase
+    /// `alpha` with `matrix == exp(i*alpha) U(theta, phi, lambda)`.
+    pub fn from_matrix(matrix: [[Complex64; 2]; 2]) -> (Gate, f64) {
+        let (a, b, c, d) = (matrix[0][0], matrix[0][1], matrix[1][0], matrix[1][1]);
+        let theta = 2.0 * c.norm().atan2(a.norm());
+        if c.norm() < TOL {
+            let alpha = a.arg();
+            (Gate::U(0.0, 0.0, d.arg() - alpha), alpha)
+        } else if a.norm() < TOL {
+            let alpha = (-b).arg();
+            (Gate::U(theta, c.arg() - alpha, 0.0), alpha)
+        } else {
+            let alpha = a.arg();
+            (Gate::U(theta, c.arg() - alpha, (-b).arg() - alpha), alpha)
+        }
+    }
+
+    /// Angle of a phase gate diag(1, exp(i*theta)); `None` for other gates.
+    pub fn phase_angle(&self) -> Option<f64> {
+        use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+        match *self {
+            Gate::Z => Some(PI),
+            Gate::S => Some(FRAC_PI_2),
+            Gate::Sdg => Some(-FRAC_PI_2),
+            Gate::T => Some(FRAC_PI_4),
+            Gate::Tdg => Some(-FRAC_PI_4),
+            Gate::P(theta) => Some(theta),
+            _ => None,
+        }
+    }
+
+    /// The gate undoing this one.
+    pub fn inverse(&self) -> Gate {
+        match *self {
+            Gate::S => Gate::Sdg,
+            Gate::Sdg => Gate::S,
+            Gate::T => Gate::Tdg,
+            Gate::Tdg => Gate::T,
+            Gate::P(theta) => Gate::P(-theta),
+            Gate::U(theta, phi, lambda) => Gate::U(-theta, -lambda, -phi),
+            other => other,
+        }
+    }
+
+    /// Angle parameters of the gate, empty for fixed gates.
+    pub fn params(&self) -> Vec<f64> {
+        match *self {
+            Gate::P(theta) => vec![theta],
+            Gate::U(theta, phi, lambda) => vec![theta, phi, lambda],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Classical condition under which an instruction runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The bits in `clbits`, read little-endian (clbits[0] is the least significant bit), equal `value`.
+    Equals { clbits: Vec<usize>, value: u64 },
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    pub fn new(clbits: &[usize], value: u64) -> Self {
+        Condition::Equals { clbits: clbits.to_vec(), value }
+    }
+
+    pub fn negate(self) -> Self {
+        match self {
+            Condition::Not(inner) => *inner,
+            other => Condition::Not(Box::new(other)),
+        }
+    }
+
+    pub fn and(self, other: Condition) -> Self {
+        Condition::And(Box::new(self), Box::new(other))
+    }
+
+    /// Every clbit the condition reads.
+    pub fn clbits(&self) -> Vec<usize> {
+        match self {
+            Condition::Equals { clbits, .. } => clbits.clone(),
+            Condition::Not(inner) => inner.clbits(),
+            Condition::And(a, b) => {
+                let mut bits = a.clbits();
+                bits.extend(b.clbits());
+                bits
+            }
+        }
+    }
+
+    /// Evaluates the condition against a classical register.
+    pub fn is_satisfied(&self, clbits: &[bool]) -> bool {
+        match self {
+            // Bit by bit, so that registers wider than `value` compare their extra bits to 0.
+            Condition::Equals { clbits: bits, value } => bits.iter().enumerate().all(|(i, &c)| {
+                clbits[c] == (value.checked_shr(i as u32).unwrap_or(0) & 1 == 1)
+            }),
+            Condition::Not(inner) => !inner.is_satisfied(clbits),
+            Condition::And(a, b) => a.is_satisfied(clbits) && b.is_satisfied(clbits),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    Gate { gate: Gate, qubits: Vec<usize> },
+    /// Single-qubit `gate` on `target`, applied only where every qubit in `controls`
+    /// is |1⟩ and every qubit in `neg_controls` is |0⟩.
+    Controlled { gate: Gate, controls: Vec<usize>, neg_controls: Vec<usize>, target: usize },
+    /// Multiplies the whole state by exp(i*gamma).
+    GlobalPhase(f64),
+    Measure { qubit: usize, clbit: usize },
+    /// Puts the qubit back into |0⟩.
+    Reset(usize),
+    /// No effect on the state, kept so passes and exporters see the program as written.
+    Barrier(Vec<usize>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub op: Operation,
+    pub condition: Option<Condition>,
+}
+
+impl Instruction {
+    pub fn new(op: Operation) -> Self {
+        Instruction { op, condition: None }
+    }
+
+    /// Qubits touched by the instruction.
+    pub fn qubits(&self) -> Vec<usize> {
+        match &self.op {
+            Operation::Gate { qubits, .. } => qubits.clone(),
+            Operation::Controlled { controls, neg_controls, target, .. } => {
+                let mut qubits = controls.clone();
+                qubits.extend(neg_controls);
+                qubits.push(*target);
+                qubits
+            }
+            Operation::GlobalPhase(_) => Vec::new(),
+            Operation::Measure { qubit, .. } => vec![*qubit],
+            Operation::Reset(qubit) => vec![*qubit],
+            Operation::Barrier(qubits) => qubits.clone(),
+        }
+    }
+}
+
+/// A quantum program: an ordered list of instructions over `num_qubits` qubits
+/// and `num_clbits` classical bits.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Circuit {
+    pub num_qubits: usize,
+    pub num_clbits: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Circuit {
+    pub fn new(num_qubits: usize, num_clbits: usize) -> Self {
+        Circuit { num_qubits, num_clbits, instructions: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Appends an instruction, checking its qubit and clbit indices.
+    pub fn push(&mut self, inst: Instruction) -> &mut Self {
+        for q in inst.qubits() {
+            assert!(q < self.num_qubits, "Qubit {} out of range for {} qubits", q, self.num_qubits);
+        }
+        if let Operation::Gate { gate, qubits } = &inst.op {
+            assert_eq!(gate.num_qubits(), qubits.len(), "Gate {} expects {} qubits", gate.name(), gate.num_qubits());
+            if qubits.len() == 2 {
+                assert_ne!(qubits[0], qubits[1], "Control and target must differ");
+            }
+        }
+        if let Operation::Controlled { gate, .. } = &inst.op {
+            assert_eq!(gate.num_qubits(), 1, "Controlled gates take a single-qubit gate");
+            let qubits = inst.qubits();
+            for (i, q) in qubits.iter().enumerate() {
+                assert!(!qubits[..i].contains(q), "Qubit {} used twice in a controlled gate", q);
+            }
+        }
+        if let Operation::Measure { clbit, .. } = &inst.op {
+            assert!(*clbit < self.num_clbits, "Clbit {} out of range for {} clbits", clbit, self.num_clbits);
+        }
+        if let Some(cond) = &inst.condition {
+            for c in cond.clbits() {
+                assert!(c < self.num_clbits, "Clbit {} out of range for {} clbits", c, self.num_clbits);
+            }
+        }
+        self.instructions.push(inst);
+        self
+    }
+
+    pub fn gate(&mut self, gate: Gate, qubits: &[usize]) -> &mut Self {
+        self.push(Instruction::new(Operation::Gate { gate, qubits: qubits.to_vec() }))
+    }
+
+    pub fn h(&mut self, q: usize) -> &mut Self { self.gate(Gate::H, &[q]) }
+    pub fn x(&mut self, q: usize) -> &mut Self { self.gate(Gate::X, &[q]) }
+    pub fn y(&mut self, q: usize) -> &mut Self { self.gate(Gate::Y, &[q]) }
+    pub fn z(&mut self, q: usize) -> &mut Self { self.gate(Gate::Z, &[q]) }
+    pub fn s(&mut self, q: usize) -> &mut Self { self.gate(Gate::S, &[q]) }
+    pub fn sdg(&mut self, q: usize) -> &mut Self { self.gate(Gate::Sdg, &[q]) }
+    pub fn t(&mut self, q: usize) -> &mut Self { self.gate(Gate::T, &[q]) }
+    pub fn tdg(&mut self, q: usize) -> &mut Self { self.gate(Gate::Tdg, &[q]) }
+    pub fn p(&mut self, q: usize, theta: f64) -> &mut Self { self.gate(Gate::P(theta), &[q]) }
+    pub fn u(&mut self, q: usize, theta: f64, phi: f64, lambda: f64) -> &mut Self {
+        self.gate(Gate::U(theta, phi, lambda), &[q])
+    }
+    pub fn cx(&mut self, control: usize, target: usize) -> &mut Self { self.gate(Gate::CX, &[control, target]) }
+    pub fn cz(&mut self, control: usize, target: usize) -> &mut Self { self.gate(Gate::CZ, &[control, target]) }
+
+    /// Single-qubit `gate` on `target` with positive `controls` and negative `neg_controls`.
+    pub fn controlled(&mut self, gate: Gate, controls: &[usize], neg_controls: &[usize], target: usize) -> &mut Self {
+        self.push(Instruction::new(Operation::Controlled {
+            gate,
+            controls: controls.to_vec(),
+            neg_controls: neg_controls.to_vec(),
+            target,
+        }))
+    }
+
+    pub fn global_phase(&mut self, gamma: f64) -> &mut Self {
+        self.push(Instruction::new(Operation::GlobalPhase(gamma)))
+    }
+
+    pub fn measure(&mut self, qubit: usize, clbit: usize) -> &mut Self {
+        self.push(Instruction::new(Operation::Measure { qubit, clbit }))
+    }
+
+    pub fn reset(&mut self, qubit: usize) -> &mut Self {
+        self.push(Instruction::new(Operation::Reset(qubit)))
+    }
+
+    pub fn barrier(&mut self, qubits: &[usize]) -> &mut Self {
+        self.push(Instruction::new(Operation::Barrier(qubits.to_vec())))
+    }
+
+    /// Conditions the most recently added instruction on `clbits == value`.
+    pub fn c_if(&mut self, clbits: &[usize], value: u64) -> &mut Self {
+        for &c in clbits {
+            assert!(c < self.num_clbits, "Clbit {} out of range for {} clbits", c, self.num_clbits);
+        }
+        let last = self.instructions.last_mut().expect("c_if called on an empty circuit");
+        last.condition = Some(Condition::new(clbits, value));
+        self
+    }
+
+    /// Number of gate instructions (measurements and barriers excluded).
+    pub fn gate_count(&self) -> usize {
+        self.instructions.iter().filter(|i| matches!(i.op, Operation::Gate { .. })).count()
+    }
+}
+
+/// Adds `controls` (all positive or all negative) to every operation.
+pub(crate) fn add_controls(ops: Vec<Operation>, controls: &[usize], positive: bool) -> Vec<Operation> {
+    let mut out = Vec::new();
+    for op in ops {
+        let (gate, mut pos, mut neg, target) = match op {
+            Operation::Gate { gate: Gate::CX, qubits } => (Gate::X, vec![qubits[0]], Vec::new(), qubits[1]),
+            Operation::Gate { gate: Gate::CZ, qubits } => (Gate::Z, vec![qubits[0]], Vec::new(), qubits[1]),
+            Operation::Gate { gate, qubits } => (gate, Vec::new(), Vec::new(), qubits[0]),
+            Operation::Controlled { gate, controls, neg_controls, target } => (gate, controls, neg_controls, target),
+            Operation::GlobalPhase(gamma) => {
+                // A controlled global phase is a phase gate on one of the controls.
+                let mut pos = if positive { controls.to_vec() } else { Vec::new() };
+                let mut neg = if positive { Vec::new() } else { controls.to_vec() };
+                if let Some(target) = pos.pop() {
+                    out.push(simplify(Gate::P(gamma), pos, neg, target));
+                } else {
+                    let target = neg.pop().expect("at least one control");
+                    out.push(Operation::Gate { gate: Gate::X, qubits: vec![target] });
+                    out.push(simplify(Gate::P(gamma), pos, neg, target));
+                    out.push(Operation::Gate { gate: Gate::X, qubits: vec![target] });
+                }
+                continue;
+            }
+            other => {
+                out.push(other);
+                continue;
+            }
+        };
+        if positive {
+            pos.splice(0..0, controls.iter().cloned());
+        } else {
+            neg.splice(0..0, controls.iter().cloned());
+        }
+        out.push(simplify(gate, pos, neg, target));
+    }
+    out
+}
+
+/// Uses the plain gate, or CX/CZ, when the controls allow it.
+fn simplify(gate: Gate, controls: Vec<usize>, neg_controls: Vec<usize>, target: usize) -> Operation {
+    match (gate, controls.len(), neg_controls.len()) {
+        (_, 0, 0) => Operation::Gate { gate, qubits: vec![target] },
+        (Gate::X, 1, 0) => Operation::Gate { gate: Gate::CX, qubits: vec![controls[0], target] },
+        (Gate::Z, 1, 0) => Operation::Gate { gate: Gate::CZ, qubits: vec![controls[0], target] },
+        _ => Operation::Controlled { gate, controls, neg_controls, target },
+    }
+}
+
+/// The sequence undoing `ops`: reversed, with every gate inverted.
+pub(crate) fn invert(ops: Vec<Operation>) -> Vec<Operation> {
+    ops.into_iter().rev().map(|op| match op {
+        Operation::Gate { gate, qubits } => Operation::Gate { gate: gate.inverse(), qubits },
+        Operation::Controlled { gate, controls, neg_controls, target } => {
+            Operation::Controlled { gate: gate.inverse(), controls, neg_controls, target }
+        }
+        Operation::GlobalPhase(gamma) => Operation::GlobalPhase(-gamma),
+        other => other,
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_records_instructions() {
+        let mut circ = Circuit::new(3, 1);
+        circ.h(2).cx(2, 1).measure(1, 0).x(0).c_if(&[0], 1);
+
+        assert_eq!(circ.len(), 4);
+        assert_eq!(circ.gate_count(), 3);
+        assert_eq!(circ.instructions[1].op, Operation::Gate { gate: Gate::CX, qubits: vec![2, 1] });
+        assert_eq!(circ.instructions[3].condition, Some(Condition::new(&[0], 1)));
+    }
+
+    #[test]
+    fn test_condition_is_little_endian() {
+        let cond = Condition::new(&[0, 2], 0b10);
+        assert!(cond.is_satisfied(&[false, true, true]));
+        assert!(!cond.is_satisfied(&[true, false, true]));
+        let both = cond.negate().and(Condition::new(&[1], 1));
+        assert!(both.is_satisfied(&[true, true, false]));
+        assert!(!both.is_satisfied(&[false, true, true]));
+    }
+
+    #[test]
+    fn test_random_circuit() {
+        let mut config = RandomCircuit::new(5, 3);
+        let circ = Circuit::random(&config, 42);
+        assert_eq!(circ, Circuit::random(&config, 42));
+        assert_ne!(circ, Circuit::random(&config, 43));
+        // Five single-qubit gates and two pairs per layer.
+        assert_eq!(circ.instructions.len(), 3 * (5 + 2));
+
+        config.single_qubit_gates = vec![Gate::H];
+        config.two_qubit_gates = vec![Gate::CZ];
+        let circ = Circuit::random(&config, 42);
+        assert!(circ.instructions.iter().all(|inst| matches!(&inst.op, Operation::Gate { gate: Gate::H | Gate::CZ, .. })));
+    }
+
+    #[test]
+    fn test_fuse_single_qubit_gates() {
+        let mut circ = Circuit::new(3, 1);
+        circ.h(0).t(0).x(1).s(0).cx(0, 2).h(2).h(2).z(1).measure(1, 0).x(0).c_if(&[0], 1);
+        let fused = circ.fuse_single_qubit_gates();
+
+        // H T S on q0 become one U before the CX; H H on q2 cancels to nothing;
+        // X Z on q1 fuse before the measurement; the conditioned X is left alone.
+        let ops: Vec<&Operation> = fused.instructions.iter().map(|i| &i.op).collect();
+        assert!(matches!(ops[0], Operation::Gate { gate: Gate::U(..), qubits } if qubits == &vec![0]));
+        assert_eq!(ops[1], &Operation::Gate { gate: Gate::CX, qubits: vec![0, 2] });
+        assert!(matches!(ops[2], Operation::Gate { gate: Gate::U(..), qubits } if qubits == &vec![1]));
+        assert!(ops[3..].iter().all(|op| !matches!(op, Operation::Gate { qubits, .. } if qubits == &vec![2])));
+        assert_eq!(fused.gate_count(), 4);
+        assert_eq!(fused.instructions.last().unwrap().condition, Some(Condition::new(&[0], 1)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_range_qubit_panics() {
+        Circuit::new(2, 0).h(2);
+    }
+}
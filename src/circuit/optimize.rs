@@ -0,0 +1,271 @@
+use std::f64::consts::{FRAC_PI_2, PI};
+use crate::constants::TOL;
+use super::{simplify, Circuit, Gate, Instruction, Operation};
+
+impl Circuit {
+    /// Runs the peephole passes (`cancel_and_merge`, `remove_zero_state_gates`) until
+    /// the circuit stops shrinking. Assumes the circuit starts from |0...0⟩.
+    pub fn optimize(&self) -> Circuit {
+        let mut current = self.clone();
+        loop {
+            let next = current.remove_zero_state_gates().cancel_and_merge();
+            if next.len() == current.len() {
+                return next;
+            }
+            current = next;
+        }
+    }
+
+    /// Cancels gates against their inverse (H H, CX CX, S Sdg, ...) and merges phase
+    /// gates and same-axis rotations into one gate. A gate is matched with an earlier
+    /// gate on the same qubits as long as everything in between commutes with it:
+    /// gates on other qubits, diagonal gates among themselves, diagonal gates on the
+    /// controls of a controlled gate, and X on the target of a controlled X.
+    pub fn cancel_and_merge(&self) -> Circuit {
+        let mut out: Vec<Instruction> = Vec::with_capacity(self.instructions.len());
+        'next: for inst in &self.instructions {
+            let mergeable = inst.condition.is_none()
+                && matches!(inst.op, Operation::Gate { .. } | Operation::Controlled { .. });
+            if mergeable {
+                for j in (0..out.len()).rev() {
+                    if out[j].condition.is_none() {
+                        if let Some(merged) = combine(&out[j].op, &inst.op) {
+                            match merged {
+                                Some(op) => out[j].op = op,
+                                None => {
+                                    out.remove(j);
+                                }
+                            }
+                            continue 'next;
+                        }
+                    }
+                    if !commutes(&out[j], &inst.op) {
+                        break;
+                    }
+                }
+            }
+            out.push(inst.clone());
+        }
+        Circuit { num_qubits: self.num_qubits, num_clbits: self.num_clbits, instructions: out }
+    }
+
+    /// Drops gates that cannot change the state because of qubits known to be |0⟩:
+    /// phase gates and CZs on such a qubit, controlled gates with such a control, and
+    /// resets of it. Negative controls on such a qubit always hold and are removed.
+    /// Every qubit starts as |0⟩ and stops being known once a non-diagonal gate
+    /// touches it; a reset makes it known again.
+    pub fn remove_zero_state_gates(&self) -> Circuit {
+        let mut zero = vec![true; self.num_qubits];
+        let mut out = Vec::with_capacity(self.instructions.len());
+        for inst in &self.instructions {
+            let op = match &inst.op {
+                Operation::Gate { gate: Gate::CZ, qubits } if qubits.iter().any(|&q| zero[q]) => continue,
+                Operation::Gate { gate: Gate::CX, qubits } if zero[qubits[0]] => continue,
+                Operation::Gate { gate, qubits } if gate.phase_angle().is_some() && zero[qubits[0]] => continue,
+                Operation::Controlled { gate, controls, neg_controls, target } => {
+                    if controls.iter().any(|&c| zero[c]) || (gate.phase_angle().is_some() && zero[*target]) {
+                        continue;
+                    }
+                    let neg: Vec<usize> = neg_controls.iter().cloned().filter(|&c| !zero[c]).collect();
+                    simplify(*gate, controls.clone(), neg, *target)
+                }
+                Operation::Reset(q) if zero[*q] => continue,
+                op => op.clone(),
+            };
+            match &op {
+                Operation::Reset(q) => zero[*q] = inst.condition.is_none() || zero[*q],
+                Operation::Gate { .. } | Operation::Controlled { .. } if !op.is_diagonal() => {
+                    let target = match &op {
+                        Operation::Controlled { target, .. } => vec![*target],
+                        Operation::Gate { gate: Gate::CX, qubits } => vec![qubits[1]],
+                        other => Instruction::new(other.clone()).qubits(),
+                    };
+                    for q in target {
+                        zero[q] = false;
+                    }
+                }
+                _ => {}
+            }
+            out.push(Instruction { op, condition: inst.condition.clone() });
+        }
+        Circuit { num_qubits: self.num_qubits, num_clbits: self.num_clbits, instructions: out }
+    }
+}
+
+/// What `first` followed by `second` reduces to when they act on the same qubits:
+/// `Some(None)` if they cancel, `Some(Some(op))` if they merge into `op`, and `None`
+/// if they do not combine.
+fn combine(first: &Operation, second: &Operation) -> Option<Option<Operation>> {
+    let (a, b, rebuild): (Gate, Gate, Box<dyn Fn(Gate) -> Operation>) = match (first, second) {
+        (Operation::Gate { gate: a, qubits: qa }, Operation::Gate { gate: b, qubits: qb }) => {
+            let same = qa == qb || (*a == Gate::CZ && *b == Gate::CZ && qa.iter().rev().eq(qb.iter()));
+            if !same {
+                return None;
+            }
+            let qubits = qa.clone();
+            (*a, *b, Box::new(move |gate| Operation::Gate { gate, qubits: qubits.clone() }))
+        }
+        (
+            Operation::Controlled { gate: a, controls: ca, neg_controls: na, target: ta },
+            Operation::Controlled { gate: b, controls: cb, neg_controls: nb, target: tb },
+        ) => {
+            if ta != tb || !same_set(ca, cb) || !same_set(na, nb) {
+                return None;
+            }
+            let (controls, neg_controls, target) = (ca.clone(), na.clone(), *ta);
+            (*a, *b, Box::new(move |gate| Operation::Controlled {
+                gate,
+                controls: controls.clone(),
+                neg_controls: neg_controls.clone(),
+                target,
+            }))
+        }
+        _ => return None,
+    };
+
+    if let (Some(x), Some(y)) = (a.phase_angle(), b.phase_angle()) {
+        let theta = wrap(x + y, 2.0 * PI);
+        return Some((theta.abs() > TOL).then(|| rebuild(Gate::P(theta))));
+    }
+    if same_gate(b, a.inverse()) {
+        return Some(None);
+    }
+    // RX and RY: U(t, -pi/2, pi/2) and U(t, 0, 0) add their angles; 4*pi is the identity.
+    if let (Gate::U(t1, p1, l1), Gate::U(t2, p2, l2)) = (a, b) {
+        let axis = (p1, l1);
+        let same_axis = (p1 - p2).abs() < TOL && (l1 - l2).abs() < TOL;
+        let rotation = [(0.0, 0.0), (-FRAC_PI_2, FRAC_PI_2)].iter()
+            .any(|&(p, l)| (axis.0 - p).abs() < TOL && (axis.1 - l).abs() < TOL);
+        if same_axis && rotation {
+            let theta = wrap(t1 + t2, 4.0 * PI);
+            return Some((theta.abs() > TOL).then(|| rebuild(Gate::U(theta, p1, l1))));
+        }
+    }
+    None
+}
+
+/// Whether moving `op` (unconditioned) in front of `earlier` leaves the result unchanged.
+fn commutes(earlier: &Instruction, op: &Operation) -> bool {
+    let (qa, qb) = (earlier.qubits(), Instruction::new(op.clone()).qubits());
+    if matches!(earlier.op, Operation::GlobalPhase(_)) {
+        return true;
+    }
+    if matches!(earlier.op, Operation::Barrier(ref q) if q.is_empty()) {
+        return false;
+    }
+    if qa.iter().all(|q| !qb.contains(q)) {
+        return true;
+    }
+    if earlier.condition.is_some() || matches!(earlier.op, Operation::Measure { .. } | Operation::Reset(_) | Operation::Barrier(_)) {
+        return false;
+    }
+    if earlier.op.is_diagonal() && op.is_diagonal() {
+        return true;
+    }
+    passes_through(&earlier.op, op) || passes_through(op, &earlier.op)
+}
+
+/// Whether `a` commutes with the controlled gate `b` by acting only on its controls
+/// (if diagonal) or only on its target (if `a` is X and `b` a controlled X).
+fn passes_through(a: &Operation, b: &Operation) -> bool {
+    let (controls, target, gate) = match b {
+        Operation::Gate { gate: Gate::CX, qubits } => (vec![qubits[0]], qubits[1], Gate::X),
+        Operation::Controlled { gate, controls, neg_controls, target } => {
+            ([controls.as_slice(), neg_controls.as_slice()].concat(), *target, *gate)
+        }
+        _ => return false,
+    };
+    let qubits = Instruction::new(a.clone()).qubits();
+    if a.is_diagonal() && qubits.iter().all(|q| controls.contains(q)) {
+        return true;
+    }
+    gate == Gate::X && *a == (Operation::Gate { gate: Gate::X, qubits: vec![target] })
+}
+
+fn same_set(a: &[usize], b: &[usize]) -> bool {
+    a.len() == b.len() && a.iter().all(|x| b.contains(x))
+}
+
+/// Equal up to rounding in the angles.
+fn same_gate(a: Gate, b: Gate) -> bool {
+    std::mem::discriminant(&a) == std::mem::discriminant(&b)
+        && a.params().iter().zip(b.params()).all(|(x, y)| (x - y).abs() < TOL)
+}
+
+/// `x` reduced into (-period/2, period/2].
+fn wrap(x: f64, period: f64) -> f64 {
+    let r = x.rem_euclid(period);
+    if r > period / 2.0 { r - period } else { r }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QuantumCircuit;
+
+    fn assert_same_state(a: &Circuit, b: &Circuit) {
+        let (mut x, mut y) = (QuantumCircuit::new(a.num_qubits), QuantumCircuit::new(b.num_qubits));
+        x.run(a);
+        y.run(b);
+        for (u, v) in x.get_state_vector().iter().zip(y.get_state_vector()) {
+            assert!((u - v).norm() < 1e-9, "{} != {}", u, v);
+        }
+    }
+
+    #[test]
+    fn test_cancels_self_inverse_pairs() {
+        // The pattern of test_h_and_cx_gates in lib.rs reduces to nothing.
+        let mut circ = Circuit::new(3, 0);
+        circ.h(2).cx(2, 0).cx(2, 0).h(2);
+        assert!(circ.cancel_and_merge().is_empty());
+
+        // T on the control commutes through the CX and cancels with Tdg; CZ is symmetric
+        // and commutes with the T between the pair.
+        let mut circ = Circuit::new(3, 0);
+        circ.h(0).h(1).t(0).cx(0, 1).tdg(0).cz(1, 2).t(1).cz(2, 1).s(2).sdg(2);
+        let out = circ.cancel_and_merge();
+        assert_eq!(out.gate_count(), 4);
+        assert_same_state(&circ, &out);
+    }
+
+    #[test]
+    fn test_merges_rotations() {
+        let mut circ = Circuit::new(2, 1);
+        circ.h(0).h(1).p(0, 0.3).t(0).p(0, -0.1).u(1, 0.4, 0.0, 0.0).u(1, 0.5, 0.0, 0.0)
+            .controlled(Gate::P(0.2), &[0], &[], 1).controlled(Gate::P(-0.2), &[0], &[], 1);
+        let out = circ.cancel_and_merge();
+        let ops: Vec<&Operation> = out.instructions.iter().map(|i| &i.op).collect();
+        assert_eq!(ops.len(), 4);
+        assert!(matches!(ops[2], Operation::Gate { gate: Gate::P(theta), .. } if (theta - (0.2 + PI / 4.0)).abs() < 1e-12));
+        assert!(matches!(ops[3], Operation::Gate { gate: Gate::U(theta, ..), .. } if (theta - 0.9).abs() < 1e-12));
+        assert_same_state(&circ, &out);
+
+        // A measurement blocks merging on its qubit.
+        let mut circ = Circuit::new(1, 1);
+        circ.h(0).measure(0, 0).h(0);
+        assert_eq!(circ.cancel_and_merge().len(), 3);
+    }
+
+    #[test]
+    fn test_removes_gates_on_zero_state() {
+        let mut circ = Circuit::new(4, 1);
+        circ.t(0).cx(1, 2).cz(0, 3).controlled(Gate::X, &[3], &[1], 2).reset(0)
+            .h(1).controlled(Gate::X, &[1], &[0], 3).s(1);
+        let out = circ.remove_zero_state_gates();
+        let ops: Vec<&Operation> = out.instructions.iter().map(|i| &i.op).collect();
+        assert_eq!(ops, [
+            &Operation::Gate { gate: Gate::H, qubits: vec![1] },
+            &Operation::Gate { gate: Gate::CX, qubits: vec![1, 3] },
+            &Operation::Gate { gate: Gate::S, qubits: vec![1] },
+        ]);
+        assert_same_state(&circ, &out);
+    }
+
+    #[test]
+    fn test_optimize_to_fixed_point() {
+        // Cancelling the inner H pair exposes X X, then the CX controlled by a |0⟩ qubit.
+        let mut circ = Circuit::new(2, 0);
+        circ.x(0).h(0).h(0).x(0).cx(0, 1).h(1).z(1).z(1).h(1);
+        assert!(circ.optimize().is_empty());
+    }
+}
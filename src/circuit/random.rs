@@ -0,0 +1,67 @@
+use std::f64::consts::PI;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use super::{Circuit, Gate};
+
+/// Settings for `Circuit::random`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomCircuit {
+    pub num_qubits: usize,
+    /// Number of layers.
+    pub depth: usize,
+    /// Single-qubit gates to draw from. `P` and `U` get fresh uniformly random angles
+    /// every time they are drawn; the angles given here are ignored.
+    pub single_qubit_gates: Vec<Gate>,
+    /// Two-qubit gates (CX, CZ) to draw from for the entangling pairs.
+    pub two_qubit_gates: Vec<Gate>,
+}
+
+impl RandomCircuit {
+    /// `depth` layers over `num_qubits` qubits with H, S, T, X, Y, Z, P and U gates
+    /// and CX/CZ pairs.
+    pub fn new(num_qubits: usize, depth: usize) -> Self {
+        RandomCircuit {
+            num_qubits,
+            depth,
+            single_qubit_gates: vec![Gate::H, Gate::S, Gate::T, Gate::X, Gate::Y, Gate::Z, Gate::P(0.0), Gate::U(0.0, 0.0, 0.0)],
+            two_qubit_gates: vec![Gate::CX, Gate::CZ],
+        }
+    }
+}
+
+impl Circuit {
+    /// A random circuit for benchmarks and randomized tests, the same for the same
+    /// `seed`.
+    ///
+    /// Every layer applies a random gate from `single_qubit_gates` to each qubit, then
+    /// pairs up the qubits in a random order and applies a random gate from
+    /// `two_qubit_gates` to each pair. Either list may be empty to leave that part out.
+    pub fn random(config: &RandomCircuit, seed: u64) -> Circuit {
+        assert!(config.two_qubit_gates.iter().all(|g| g.num_qubits() == 2), "two_qubit_gates must be CX or CZ");
+        assert!(config.single_qubit_gates.iter().all(|g| g.num_qubits() == 1), "single_qubit_gates must act on one qubit");
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut circ = Circuit::new(config.num_qubits, 0);
+        let mut order: Vec<usize> = (0..config.num_qubits).collect();
+        for _ in 0..config.depth {
+            if !config.single_qubit_gates.is_empty() {
+                for q in 0..config.num_qubits {
+                    let gate = match config.single_qubit_gates[rng.random_range(0..config.single_qubit_gates.len())] {
+                        Gate::P(_) => Gate::P(rng.random_range(-PI..PI)),
+                        Gate::U(..) => Gate::U(rng.random_range(0.0..PI), rng.random_range(-PI..PI), rng.random_range(-PI..PI)),
+                        gate => gate,
+                    };
+                    circ.gate(gate, &[q]);
+                }
+            }
+            if !config.two_qubit_gates.is_empty() {
+                order.shuffle(&mut rng);
+                for pair in order.chunks_exact(2) {
+                    let gate = config.two_qubit_gates[rng.random_range(0..config.two_qubit_gates.len())];
+                    circ.gate(gate, pair);
+                }
+            }
+        }
+        circ
+    }
+}
@@ -61,7 +61,7 @@ impl QuantumCircuit {
                     // let zero_ptr = Rc::as_ptr(&zero.1) as usize;
                     // let one_ptr = Rc::as_ptr(&one.1) as usize;
                     // format!("Q{}_{:x}_{:x}", qubit, zero_ptr, one_ptr)
-                    name = format!("L{:02}_Q{}", level, qubit)
+                    name = format!("L{:02}_Q{}", level, self.qubit_at(*qubit))
                 }
 
                 *level += 1;
@@ -17,7 +17,7 @@ impl QuantumCircuit {
         let mut visited: HashMap<usize, String> = HashMap::new();
         // Traverse starting from the root. This function returns Some(unique_id)
         // if the node is non-terminal (Terminal nodes are omitted).
-        let root_id = traverse_dd(&self.root, &mut visited, &mut dot);
+        let root_id = traverse_dd(&self.root, &self.qubits, &mut visited, &mut dot);
         if let Some(rid) = root_id {
             dot.push_str(&format!("  root -> {};\n", rid));
         }
@@ -27,11 +27,13 @@ impl QuantumCircuit {
 }
 
 /// Recursively traverses the decision diagram and emits DOT node and edge definitions.
+/// - `qubits`: logical qubit at each level, used in the node labels.
 /// - `visited`: maps pointer addresses to unique DOT ids.
 /// - `dot`: accumulates DOT output.
 /// Returns Some(unique_id) for nonterminal nodes, or None if the node is Terminal (which we omit).
 fn traverse_dd(
     node: &NodePtr,
+    qubits: &[usize],
     visited: &mut HashMap<usize, String>,
     dot: &mut String,
 ) -> Option<String> {
@@ -63,11 +65,11 @@ fn traverse_dd(
     <TD PORT="zero" ALIGN="CENTER"><FONT POINT-SIZE="12">0</FONT></TD>
     <TD PORT="one" ALIGN="CENTER"><FONT POINT-SIZE="12">1</FONT></TD>
   </TR>
-</TABLE>>"#, qubit);
+</TABLE>>"#, qubits[*qubit]);
                 dot.push_str(&format!("  {} [label={}];\n", id, label));
 
             }
-            if let Some(child_id) = traverse_dd(&zero.1, visited, dot) {
+            if let Some(child_id) = traverse_dd(&zero.1, qubits, visited, dot) {
                 let (edge_color, edge_width) = edge_style(zero.0);
                 let label_str = format_weight(zero.0).unwrap_or_default();
                 dot.push_str(&format!(
@@ -75,7 +77,7 @@ fn traverse_dd(
                     id, child_id, label_str, edge_color, edge_width
                 ));
             }
-            if let Some(child_id) = traverse_dd(&one.1, visited, dot) {
+            if let Some(child_id) = traverse_dd(&one.1, qubits, visited, dot) {
                 let (edge_color, edge_width) = edge_style(one.0);
                 let label_str = format_weight(one.0).unwrap_or_default();
                 dot.push_str(&format!(
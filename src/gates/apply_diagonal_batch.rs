@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::rc::Rc;
+use num::complex::Complex64;
+use crate::{DdNode, Gate, NodePtr, Operation, QuantumCircuit};
+
+/// A phase exp(i*theta) applied to the basis states where every `(qubit, value)`
+/// condition holds.
+struct Term {
+    conditions: Vec<(usize, bool)>,
+    phase: Complex64,
+}
+
+impl Term {
+    /// The term for a diagonal operation, or `None` if `op` is not diagonal.
+    fn from_operation(op: &Operation) -> Option<Term> {
+        let (conditions, theta) = match op {
+            Operation::Gate { gate: Gate::CZ, qubits } => (vec![(qubits[0], true), (qubits[1], true)], PI),
+            Operation::Gate { gate, qubits } => (vec![(qubits[0], true)], gate.phase_angle()?),
+            Operation::Controlled { gate, controls, neg_controls, target } => {
+                let mut conditions: Vec<(usize, bool)> = controls.iter().map(|&c| (c, true)).collect();
+                conditions.extend(neg_controls.iter().map(|&c| (c, false)));
+                conditions.push((*target, true));
+                (conditions, gate.phase_angle()?)
+            }
+            Operation::GlobalPhase(gamma) => (Vec::new(), *gamma),
+            _ => return None,
+        };
+        Some(Term { conditions, phase: Complex64::from_polar(1.0, theta) })
+    }
+}
+
+impl Operation {
+    /// Whether the operation only multiplies basis states by phases (Z, S, T, P, CZ,
+    /// controlled phases and global phases), so that it commutes with every other
+    /// diagonal operation.
+    pub fn is_diagonal(&self) -> bool {
+        Term::from_operation(self).is_some()
+    }
+}
+
+impl QuantumCircuit {
+    /// Applies a batch of diagonal operations (see `Operation::is_diagonal`) in a
+    /// single depth-first pass over the DD, instead of one traversal per gate.
+    ///
+    /// Every gate is a phase on the basis states matching its conditions, so the pass
+    /// tracks which gates are still satisfied along the current path and puts each
+    /// gate's phase on the arm where its lowest qubit is decided. A node is copied once
+    /// per distinct set of gates still satisfied when reaching it, and sub-diagrams
+    /// below the lowest qubit of the batch are shared as they are.
+    ///
+    /// Panics if an operation is not diagonal.
+    pub fn apply_diagonal_batch(&mut self, ops: &[Operation]) {
+        let terms: Vec<Term> = ops.iter()
+            .map(|op| Term::from_operation(op).unwrap_or_else(|| panic!("{:?} is not a diagonal operation", op)))
+            .collect();
+
+        let mut global = Complex64::new(1.0, 0.0);
+        let mut batch = DiagonalBatch {
+            conditions_at: vec![Vec::new(); self.num_qubits],
+            ending_at: vec![Vec::new(); self.num_qubits],
+            open_at: vec![Vec::new(); self.num_qubits],
+            phases: Vec::new(),
+            lowest: usize::MAX,
+            rebuilt: HashMap::new(),
+        };
+        for mut term in terms {
+            for condition in &mut term.conditions {
+                condition.0 = self.level_of(condition.0);
+            }
+            let Some(low) = term.conditions.iter().map(|c| c.0).min() else {
+                global *= term.phase;
+                continue;
+            };
+            let high = term.conditions.iter().map(|c| c.0).max().unwrap();
+            let index = batch.phases.len();
+            for &(qubit, value) in &term.conditions {
+                batch.conditions_at[qubit].push((index, value));
+            }
+            batch.ending_at[low].push(index);
+            // Levels strictly between the highest and lowest qubit see the term half decided.
+            for level in low..high {
+                batch.open_at[level].push(index);
+            }
+            batch.phases.push(term.phase);
+            batch.lowest = batch.lowest.min(low);
+        }
+
+        if !batch.phases.is_empty() {
+            let alive = vec![true; batch.phases.len()];
+            self.root = batch.rebuild(&self.root, &alive);
+        }
+        if global != Complex64::new(1.0, 0.0) {
+            self.apply_global_phase(global.arg());
+        }
+        self.check_norm("diagonal_batch");
+    }
+}
+
+struct DiagonalBatch {
+    /// Per level: the terms with a condition on that level, and the value they need.
+    conditions_at: Vec<Vec<(usize, bool)>>,
+    /// Per level: the terms whose lowest qubit it is.
+    ending_at: Vec<Vec<usize>>,
+    /// Per level: the terms decided partly above it and partly at or below it.
+    open_at: Vec<Vec<usize>>,
+    phases: Vec<Complex64>,
+    /// Lowest qubit any term depends on.
+    lowest: usize,
+    rebuilt: HashMap<(*const RefCell<DdNode>, Vec<bool>), NodePtr>,
+}
+
+impl DiagonalBatch {
+    /// Copies `node` with the phases of the still `alive` terms applied.
+    fn rebuild(&mut self, node: &NodePtr, alive: &[bool]) -> NodePtr {
+        let (level, zero, one) = match &*node.borrow() {
+            DdNode::NonTerminal { qubit, zero, one } if *qubit != usize::MAX && *qubit >= self.lowest => {
+                (*qubit, zero.clone(), one.clone())
+            }
+            _ => return node.clone(),
+        };
+        let open: Vec<bool> = self.open_at[level].iter().map(|&t| alive[t]).collect();
+        let key = (Rc::as_ptr(node), open);
+        if let Some(done) = self.rebuilt.get(&key) {
+            return done.clone();
+        }
+
+        let mut arms = Vec::with_capacity(2);
+        for (value, (weight, child)) in [(false, zero), (true, one)] {
+            if weight.norm() == 0.0 || matches!(&*child.borrow(), DdNode::Terminal(_)) {
+                arms.push((weight, child));
+                continue;
+            }
+            let mut branch_alive = alive.to_vec();
+            for &(t, needed) in &self.conditions_at[level] {
+                branch_alive[t] &= needed == value;
+            }
+            let mut weight = weight;
+            for &t in &self.ending_at[level] {
+                if branch_alive[t] {
+                    weight *= self.phases[t];
+                }
+            }
+            arms.push((weight, self.rebuild(&child, &branch_alive)));
+        }
+        let one = arms.pop().unwrap();
+        let zero = arms.pop().unwrap();
+        let new_node = Rc::new(RefCell::new(DdNode::NonTerminal { qubit: level, zero, one }));
+        self.rebuilt.insert(key, new_node.clone());
+        new_node
+    }
+}
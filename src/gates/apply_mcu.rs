@@ -0,0 +1,254 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use num::complex::Complex64;
+use crate::{DdNode, NodePtr, QuantumCircuit};
+use crate::constants::{ONE, TOL, ZERO};
+
+/// A weighted edge, as stored in the arms of a NonTerminal node.
+pub(crate) type Edge = (Complex64, NodePtr);
+
+type WeightKey = (u64, u64);
+/// Level, then each arm's child and weight rounded to 1e-10.
+type NodeKey = (usize, usize, (i64, i64), usize, (i64, i64));
+
+impl QuantumCircuit {
+    /// Apply a single-qubit unitary `matrix` (row-major) to `target`, only where every
+    /// qubit in `controls` is |1⟩ and every qubit in `neg_controls` is |0⟩.
+    ///
+    /// Nodes above the target are copied only along paths where the controls above the
+    /// target can still be satisfied; below the target the two arms are combined
+    /// level by level, so untouched sub-diagrams keep being shared.
+    pub fn apply_mcu(&mut self, controls: &[usize], neg_controls: &[usize], target: usize, matrix: [[Complex64; 2]; 2]) {
+        self.apply_controlled(controls, neg_controls, target, matrix);
+        self.check_norm("mcu");
+    }
+
+    /// `apply_mcu` without the norm check, for gate methods that report under their own
+    /// name.
+    pub(crate) fn apply_controlled(&mut self, controls: &[usize], neg_controls: &[usize], target: usize, matrix: [[Complex64; 2]; 2]) {
+        // Everything below works on DD levels rather than logical qubits.
+        let mut polarity = HashMap::new();
+        for &c in controls {
+            polarity.insert(self.level_of(c), true);
+        }
+        for &c in neg_controls {
+            assert!(polarity.insert(self.level_of(c), false).is_none(), "Qubit {} used as control twice", c);
+        }
+        assert!(!polarity.contains_key(&self.level_of(target)), "Target {} is also a control", target);
+        let target = self.level_of(target);
+
+        let lowest_control = polarity.keys().filter(|&&c| c < target).min().cloned();
+        let mut apply = McuApply {
+            polarity,
+            target,
+            matrix,
+            lowest_control,
+            rebuilt: HashMap::new(),
+            combined: HashMap::new(),
+            sums: HashMap::new(),
+        };
+        self.root = apply.rebuild(&self.root);
+    }
+}
+
+struct McuApply {
+    polarity: HashMap<usize, bool>,
+    target: usize,
+    matrix: [[Complex64; 2]; 2],
+    lowest_control: Option<usize>,
+    rebuilt: HashMap<*const RefCell<DdNode>, NodePtr>,
+    combined: HashMap<(usize, usize, WeightKey, WeightKey, usize), Edge>,
+    sums: HashMap<(usize, usize, WeightKey, WeightKey), Edge>,
+}
+
+impl McuApply {
+    /// Copies the part of the DD above the target, applying the gate at the target level.
+    fn rebuild(&mut self, node: &NodePtr) -> NodePtr {
+        let key = Rc::as_ptr(node);
+        if let Some(done) = self.rebuilt.get(&key) {
+            return done.clone();
+        }
+        let new_node = match &*node.borrow() {
+            DdNode::NonTerminal { qubit, zero, one } if *qubit != usize::MAX && *qubit >= self.target => {
+                let (new_zero, new_one) = if *qubit == self.target {
+                    let m = self.matrix;
+                    (self.combine(zero, one, m[0][0], m[0][1], 0), self.combine(zero, one, m[1][0], m[1][1], 1))
+                } else {
+                    match self.polarity.get(qubit) {
+                        Some(true) => (zero.clone(), self.rebuild_edge(one)),
+                        Some(false) => (self.rebuild_edge(zero), one.clone()),
+                        None => (self.rebuild_edge(zero), self.rebuild_edge(one)),
+                    }
+                };
+                Rc::new(RefCell::new(DdNode::NonTerminal { qubit: *qubit, zero: new_zero, one: new_one }))
+            }
+            // Below the target (or a Terminal): nothing to do.
+            _ => node.clone(),
+        };
+        self.rebuilt.insert(key, new_node.clone());
+        new_node
+    }
+
+    fn rebuild_edge(&mut self, edge: &Edge) -> Edge {
+        if is_zero(edge) {
+            return edge.clone();
+        }
+        (edge.0, self.rebuild(&edge.1))
+    }
+
+    /// Builds `alpha * a + beta * b` on the paths where the controls below the target hold,
+    /// and keeps `a` (row 0) or `b` (row 1) unchanged everywhere else.
+    fn combine(&mut self, a: &Edge, b: &Edge, alpha: Complex64, beta: Complex64, row: usize) -> Edge {
+        let level = match pair_level(a, b) {
+            Some(level) if self.lowest_control.is_some_and(|c| c <= level) => level,
+            _ => return self.add(&scale(a, alpha), &scale(b, beta)),
+        };
+        let key = (edge_key(a), edge_key(b), weight_key(a.0), weight_key(b.0), row);
+        if let Some(done) = self.combined.get(&key) {
+            return done.clone();
+        }
+        let (a0, a1) = children_at(a, level);
+        let (b0, b1) = children_at(b, level);
+        let keep = |x: Edge, y: Edge| if row == 0 { x } else { y };
+        let (new_zero, new_one) = match self.polarity.get(&level) {
+            Some(true) => (keep(a0, b0), self.combine(&a1, &b1, alpha, beta, row)),
+            Some(false) => (self.combine(&a0, &b0, alpha, beta, row), keep(a1, b1)),
+            None => (self.combine(&a0, &b0, alpha, beta, row), self.combine(&a1, &b1, alpha, beta, row)),
+        };
+        let result = make_node(level, new_zero, new_one);
+        self.combined.insert(key, result.clone());
+        result
+    }
+
+    fn add(&mut self, a: &Edge, b: &Edge) -> Edge {
+        add_edges(a, b, &mut self.sums)
+    }
+}
+
+/// Adds the states represented by two edges, sharing results through `memo`.
+pub(crate) fn add_edges(a: &Edge, b: &Edge, memo: &mut HashMap<(usize, usize, WeightKey, WeightKey), Edge>) -> Edge {
+    if is_zero(a) {
+        return b.clone();
+    }
+    if is_zero(b) {
+        return a.clone();
+    }
+    let level = match pair_level(a, b) {
+        Some(level) => level,
+        None => {
+            // Both edges end at the sink.
+            let w = a.0 + b.0;
+            return if w.norm() < TOL { zero_edge() } else { (w, a.1.clone()) };
+        }
+    };
+    if Rc::ptr_eq(&a.1, &b.1) {
+        let w = a.0 + b.0;
+        return if w.norm() < TOL { zero_edge() } else { (w, a.1.clone()) };
+    }
+    let key = (edge_key(a), edge_key(b), weight_key(a.0), weight_key(b.0));
+    if let Some(done) = memo.get(&key) {
+        return done.clone();
+    }
+    let (a0, a1) = children_at(a, level);
+    let (b0, b1) = children_at(b, level);
+    let new_zero = add_edges(&a0, &b0, memo);
+    let new_one = add_edges(&a1, &b1, memo);
+    let result = make_node(level, new_zero, new_one);
+    memo.insert(key, result.clone());
+    result
+}
+
+pub(crate) fn zero_edge() -> Edge {
+    (ZERO, Rc::new(RefCell::new(DdNode::Terminal(ZERO))))
+}
+
+pub(crate) fn is_zero(edge: &Edge) -> bool {
+    edge.0.norm() < TOL || matches!(&*edge.1.borrow(), DdNode::Terminal(_))
+}
+
+pub(crate) fn scale(edge: &Edge, factor: Complex64) -> Edge {
+    (edge.0 * factor, edge.1.clone())
+}
+
+/// Qubit of the node an edge points to; `None` for zero edges and the sink.
+fn level_of(edge: &Edge) -> Option<usize> {
+    if is_zero(edge) {
+        return None;
+    }
+    match &*edge.1.borrow() {
+        DdNode::NonTerminal { qubit, .. } if *qubit != usize::MAX => Some(*qubit),
+        _ => None,
+    }
+}
+
+fn pair_level(a: &Edge, b: &Edge) -> Option<usize> {
+    match (level_of(a), level_of(b)) {
+        (Some(x), Some(y)) => Some(x.max(y)),
+        (x, y) => x.or(y),
+    }
+}
+
+/// The two arms of `edge` at `level`, with the edge weight pushed into them.
+/// An edge that skips `level` stands for the same sub-diagram on both arms.
+pub(crate) fn children_at(edge: &Edge, level: usize) -> (Edge, Edge) {
+    if is_zero(edge) {
+        return (zero_edge(), zero_edge());
+    }
+    match &*edge.1.borrow() {
+        DdNode::NonTerminal { qubit, zero, one } if *qubit == level => {
+            (scale(zero, edge.0), scale(one, edge.0))
+        }
+        _ => (edge.clone(), edge.clone()),
+    }
+}
+
+pub(crate) fn make_node(qubit: usize, zero: Edge, one: Edge) -> Edge {
+    if is_zero(&zero) && is_zero(&one) {
+        return zero_edge();
+    }
+    let zero = if is_zero(&zero) { zero_edge() } else { zero };
+    let one = if is_zero(&one) { zero_edge() } else { one };
+    (ONE, Rc::new(RefCell::new(DdNode::NonTerminal { qubit, zero, one })))
+}
+
+/// Nodes built so far, keyed by level and normalized arms, so that equal sub-diagrams
+/// are only built once.
+#[derive(Default)]
+pub(crate) struct UniqueTable {
+    nodes: HashMap<NodeKey, NodePtr>,
+}
+
+impl UniqueTable {
+    /// A node with the first non-zero arm weight factored out into the returned edge,
+    /// shared with any identical node built through this table.
+    pub(crate) fn make_node(&mut self, qubit: usize, zero: Edge, one: Edge) -> Edge {
+        let zero = if is_zero(&zero) { zero_edge() } else { zero };
+        let one = if is_zero(&one) { zero_edge() } else { one };
+        let factor = match (is_zero(&zero), is_zero(&one)) {
+            (true, true) => return zero_edge(),
+            (false, _) => zero.0,
+            (true, false) => one.0,
+        };
+        let (zero, one) = (scale(&zero, factor.inv()), scale(&one, factor.inv()));
+        let arm_key = |edge: &Edge| if is_zero(edge) { 0 } else { edge_key(edge) };
+        let key = (qubit, arm_key(&zero), rounded(zero.0), arm_key(&one), rounded(one.0));
+        let node = self.nodes.entry(key).or_insert_with(|| {
+            Rc::new(RefCell::new(DdNode::NonTerminal { qubit, zero, one }))
+        });
+        (factor, node.clone())
+    }
+}
+
+/// Weight rounded to 1e-10, so that weights differing only by rounding errors match.
+fn rounded(w: Complex64) -> (i64, i64) {
+    ((w.re / 1e-10).round() as i64, (w.im / 1e-10).round() as i64)
+}
+
+fn edge_key(edge: &Edge) -> usize {
+    Rc::as_ptr(&edge.1) as usize
+}
+
+fn weight_key(w: Complex64) -> WeightKey {
+    (w.re.to_bits(), w.im.to_bits())
+}
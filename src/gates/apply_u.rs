@@ -9,7 +9,7 @@ use crate::{DdNode, QuantumCircuit, QubitIter};
 
 impl QuantumCircuit {
     pub fn apply_u(&mut self, target_qubit: usize, theta: f64, phi: f64, lambda: f64) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         // The U gate is defined, up to global phase, as:
         // U(theta, phi, lambda) = [[cos(theta/2), -exp(i*lambda)*sin(theta/2)],
         //                           [exp(i*phi)*sin(theta/2), exp(i*(phi+lambda))*cos(theta/2)]]
@@ -31,5 +31,6 @@ impl QuantumCircuit {
                 // with exp(i*phi)
             }
         }
+        self.check_norm("u");
     }
 }
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+use num::complex::Complex64;
+use crate::{DdNode, QuantumCircuit, QubitIter};
+use super::apply_mcu::{add_edges, scale};
+
+impl QuantumCircuit {
+    /// Apply an arbitrary single-qubit unitary `matrix` (row-major) to `target`
+    /// in a single `QubitIter` traversal.
+    ///
+    /// A gate on `target` only mixes the two arms of each node at that level, so
+    /// every such node is rewritten in place to `(m00*zero + m01*one, m10*zero + m11*one)`.
+    /// Nodes above the target are untouched and the sums share one memo, so common
+    /// sub-diagrams below the target stay shared.
+    pub fn apply_unitary(&mut self, target: usize, matrix: [[Complex64; 2]; 2]) {
+        let mut sums = HashMap::new();
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target));
+        for node in iter {
+            let mut node_ref = node.borrow_mut();
+            if let DdNode::NonTerminal { zero, one, .. } = &mut *node_ref {
+                let new_zero = add_edges(&scale(zero, matrix[0][0]), &scale(one, matrix[0][1]), &mut sums);
+                let new_one = add_edges(&scale(zero, matrix[1][0]), &scale(one, matrix[1][1]), &mut sums);
+                *zero = new_zero;
+                *one = new_one;
+            }
+        }
+        self.check_norm("unitary");
+    }
+}
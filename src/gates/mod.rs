@@ -6,10 +6,13 @@ mod apply_h;
 mod apply_cnot;
 mod apply_u;
 mod apply_cz;
+pub(crate) mod apply_mcu;
+mod apply_unitary;
+mod apply_diagonal_batch;
 impl QuantumCircuit {
     /// Apply Pauli-X gate (bit flip)
     pub fn apply_x(&mut self, target_qubit: usize) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         for node in iter {
             let mut node_ref = node.borrow_mut();
             // println!("{}", node_ref);
@@ -18,11 +21,12 @@ impl QuantumCircuit {
                 std::mem::swap(zero, one);
             }
         }
+        self.check_norm("x");
     }
 
     /// Z gate: |0⟩ unchanged, |1⟩ gets a -1 phase.
     pub fn apply_z(&mut self, target_qubit: usize) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         let z_phase = MINUS_ONE;
         for node in iter {
             let mut node_ref = node.borrow_mut();
@@ -30,26 +34,28 @@ impl QuantumCircuit {
                 one.0 = one.0 * z_phase;
             }
         }
+        self.check_norm("z");
     }
     /// Y gate: swaps arms with phases (Y = [[0, -i], [i, 0]]).
     pub fn apply_y(&mut self, target_qubit: usize) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         let i_phase = Complex64::new(0.0, 1.0);
         let minus_i_phase = Complex64::new(0.0, -1.0);
         for node in iter {
             let mut node_ref = node.borrow_mut();
             if let DdNode::NonTerminal { zero, one, .. } = &mut *node_ref {
-                let new_zero = (one.0 * i_phase, one.1.clone());
-                let new_one  = (zero.0 * minus_i_phase, zero.1.clone());
+                let new_zero = (one.0 * minus_i_phase, one.1.clone());
+                let new_one  = (zero.0 * i_phase, zero.1.clone());
                 *zero = new_zero;
                 *one  = new_one;
             }
         }
+        self.check_norm("y");
     }
 
     /// S gate: applies a π/2 phase (i) on the |1⟩ branch.
     pub fn apply_s(&mut self, target_qubit: usize) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         let s_phase = Complex64::new(0.0, 1.0);
         for node in iter {
             let mut node_ref = node.borrow_mut();
@@ -57,11 +63,12 @@ impl QuantumCircuit {
                 one.0 = one.0 * s_phase;
             }
         }
+        self.check_norm("s");
     }
 
     /// Apply Sdg gate: phase of -i on |1⟩ branch.
     pub fn apply_sdg(&mut self, target_qubit: usize) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         let s_dg_phase = Complex64::new(0.0, -1.0);
         for node in iter {
             let mut node_ref = node.borrow_mut();
@@ -69,11 +76,12 @@ impl QuantumCircuit {
                 one.0 *= s_dg_phase;
             }
         }
+        self.check_norm("sdg");
     }
 
     /// Apply T gate: multiplies the |1⟩ branch by exp(i*pi/4).
     pub fn apply_t(&mut self, target_qubit: usize) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         let t_phase = Complex64::from_polar(1.0, std::f64::consts::PI / 4.0);
         for node in iter {
             let mut node_ref = node.borrow_mut();
@@ -81,11 +89,12 @@ impl QuantumCircuit {
                 one.0 *= t_phase;
             }
         }
+        self.check_norm("t");
     }
 
     /// Apply Tdg gate: multiplies the |1⟩ branch by exp(-i*pi/4).
     pub fn apply_tdg(&mut self, target_qubit: usize) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         let tdg_phase = Complex64::from_polar(1.0, -std::f64::consts::PI / 4.0);
         for node in iter {
             let mut node_ref = node.borrow_mut();
@@ -93,12 +102,24 @@ impl QuantumCircuit {
                 one.0 *= tdg_phase;
             }
         }
+        self.check_norm("tdg");
+    }
+
+    /// Multiplies the whole state by exp(i*gamma). The root has no incoming edge,
+    /// so the phase goes on both of its arms.
+    pub fn apply_global_phase(&mut self, gamma: f64) {
+        let phase = Complex64::from_polar(1.0, gamma);
+        if let DdNode::NonTerminal { zero, one, .. } = &mut *self.root.borrow_mut() {
+            zero.0 *= phase;
+            one.0 *= phase;
+        }
+        self.check_norm("global_phase");
     }
 
     /// Apply P gate: corresponds to the phase gate. Parameter theta.
     /// In Qiskit, U1(theta) is equivalent to P(theta).
     pub fn apply_p(&mut self, target_qubit: usize, theta: f64) {
-        let iter = QubitIter::new(self.root.clone(), target_qubit);
+        let iter = QubitIter::new(self.root.clone(), self.level_of(target_qubit));
         // The global phase convention from Qiskit is that:
         // U1(theta) = P(theta) = diag(1, exp(i*theta))
         let phase = Complex64::from_polar(1.0, theta);
@@ -108,6 +129,29 @@ impl QuantumCircuit {
                 one.0 *= phase;
             }
         }
+        self.check_norm("p");
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Gate, QuantumCircuit};
+
+    #[test]
+    fn test_y_matches_matrix() {
+        // Both amplitudes are nonzero, so a sign flip on either off-diagonal entry shows.
+        let mut sim = QuantumCircuit::new(1);
+        sim.apply_h(0);
+        sim.apply_t(0);
+        let before = sim.get_state_vector();
+        sim.apply_y(0);
+        let after = sim.get_state_vector();
+
+        let y = Gate::Y.matrix().unwrap();
+        for (row, amp) in y.iter().zip(&after) {
+            let expected = row[0] * before[0] + row[1] * before[1];
+            assert!((amp - expected).norm() < 1e-9, "{} != {}", amp, expected);
+        }
+    }
+}
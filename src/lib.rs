@@ -3,6 +3,18 @@ mod gates;
 mod draw;
 mod dd_node;
 mod constants;
+mod circuit;
+mod qasm;
+mod revlib;
+mod stim;
+mod quil;
+
+pub use circuit::{Circuit, Condition, Gate, Instruction, Operation, RandomCircuit};
+pub use qasm::{parse_qasm2, parse_qasm3, ExportError, QasmError};
+pub use revlib::{parse_real, RealCircuit, RealError};
+pub use stim::{parse_stim, NoiseChannel, StimCircuit, StimError, StimOp, StimSample};
+pub use quil::{parse_quil, QuilError, QuilOp, QuilProgram};
+pub use quantum_circuit::{ApproximationPolicy, DdLoadError, DriftAction, EntanglementEntropy, NormMonitor, Pauli, PauliError, PauliString, StateError};
 
 // use lazy_static::lazy_static;
 // lazy_static! { // --> doesn't work
@@ -21,6 +33,7 @@ use num::complex::Complex64;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashSet;
+use rand::rngs::StdRng;
 
 type NodePtr = Rc<RefCell<DdNode>>;
 #[derive(Debug)]
@@ -36,6 +49,17 @@ pub enum DdNode {
 pub struct QuantumCircuit {
     root: NodePtr,
     pub num_qubits: usize,
+    /// Source of randomness for measurements and noise; see `set_seed`.
+    rng: StdRng,
+    /// DD level of each logical qubit, and the logical qubit at each level. Both are the
+    /// identity until `reorder` or `swap_levels` changes the variable order.
+    levels: Vec<usize>,
+    qubits: Vec<usize>,
+    /// Automatic approximation settings and the fidelity kept so far; see `approximate`.
+    approximation: Option<ApproximationPolicy>,
+    fidelity: f64,
+    /// Norm check run after every gate; see `set_norm_monitor`.
+    norm_monitor: Option<NormMonitor>,
 }
 pub struct QubitIter {
     stack: Vec<NodePtr>,
@@ -60,6 +84,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_h_cx_cz_on_shared_branches() {
+        // H on both halves of a Bell pair mixes arms that point at shared nodes.
+        let mut sim = QuantumCircuit::new(3);
+        sim.apply_h(0);
+        sim.apply_cnot(0, 2);
+        sim.apply_h(1);
+        sim.apply_cz(1, 2);
+        sim.apply_h(0);
+        sim.apply_h(2);
+        sim.apply_cnot(1, 0);
+
+        // The same gates on a dense vector.
+        let mut dense = vec![ZERO; 8];
+        dense[0] = ONE;
+        let h = |v: &mut Vec<Complex64>, q: usize| {
+            for i in (0..8).filter(|i| i >> q & 1 == 0) {
+                let (a, b) = (v[i], v[i | 1 << q]);
+                v[i] = (a + b) * constants::INV_ROOT_TWO;
+                v[i | 1 << q] = (a - b) * constants::INV_ROOT_TWO;
+            }
+        };
+        h(&mut dense, 0);
+        for i in (0..8).filter(|i| i & 1 == 1 && i & 4 == 0) {
+            dense.swap(i, i | 4);
+        }
+        h(&mut dense, 1);
+        for i in (0..8).filter(|i| i & 6 == 6) {
+            dense[i] = -dense[i];
+        }
+        h(&mut dense, 0);
+        h(&mut dense, 2);
+        for i in (0..8).filter(|i| i & 2 == 2 && i & 1 == 0) {
+            dense.swap(i, i | 1);
+        }
+        for (a, b) in sim.get_state_vector().iter().zip(&dense) {
+            assert_complex_eq(*a, *b);
+        }
+        assert!(approx_eq!(f64, sim.norm(), 1.0, epsilon = TOL));
+    }
+
     #[test]
     fn test_h_and_cx_gates() {
         // |0⟩ -> H -> |+⟩
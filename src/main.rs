@@ -1,69 +1,429 @@
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::process::Command;
-use qdd_rs::QuantumCircuit;
 use std::io::Write;
+use std::process::{Command, ExitCode};
+use std::time::{Duration, Instant};
+use num::complex::Complex64;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use qdd_rs::{parse_qasm2, parse_qasm3, parse_quil, parse_real, Circuit, Operation, QuantumCircuit, QuilProgram};
 
-fn main() -> std::io::Result<()> {
-
-    let mut sim = QuantumCircuit::new(6);
-    // sim.apply_h(4);
-    // sim.apply_cnot(4,2);
-    // sim.apply_h(6);
-    // sim.apply_cnot(6,0);
-    // sim.apply_t(6);
-    // sim.apply_cnot(4,0);
-    // sim.apply_cnot(6,3);
-
-    for q in 0..6 {
-        sim.apply_h(q);
-    }
-    sim.apply_cz(5,4);
-    sim.apply_cz(5,2);
-    sim.apply_cz(5,0);
-    //
-    sim.apply_cz(4,3);
-    sim.apply_cz(4,1);
-    // //
-    sim.apply_cz(3,2);
-    println!("========== last gate =======>");
-    sim.apply_cz(3,0);
-    //
-    sim.apply_cz(2,1);
-    // //
-    sim.apply_cz(1,0);
-
-
-    // println!("\nState vector:");
-    // let state_vector = sim.get_state_vector();
-    // // println!("{:?}", state_vector);
-    // let threshold = 1e-6; // Set your threshold value
-    // for (i, amp) in state_vector.iter().enumerate() {
-    //     if amp.norm() > threshold {
-    //         println!("|{:0width$b}⟩: {:.3}", i, amp, width = sim.num_qubits);
-    //     }
-    // }
-
-    // sim.print_adjacency_list();
-    // let dot = sim.to_graphviz_dot();
-    // let output_image = "graph.png";
-    // draw_dot(&dot, output_image)?;
-    // println!("Graph image saved as {}", output_image);
-    // println!("Number of Terminal and NonTerminal Nodes in DD: {:?}", sim.count_nodes());
+mod repl;
+
+const USAGE: &str = "\
+Usage: qdd_rs run <circuit> [options]
+       qdd_rs repl <num_qubits | circuit> [--seed <n>]
+
+The input format follows the extension: .quil for Quil, .real for RevLib and
+OpenQASM 2 or 3 (from the version header) otherwise.
+
+Options:
+  --shots <n>        shots to sample for counts (default 1024)
+  --seed <n>         seed for measurements and sampling
+  --output <kind>    counts | statevector | sparse | dot | png (default counts)
+  --format <fmt>     text | json (default text)
+  --png <path>       image written by --output png (default circuit.png)
+  --stats            report node counts and timing
+  -h, --help         print this help";
+
+/// Amplitudes below this norm are left out of the sparse output.
+const SPARSE_THRESHOLD: f64 = 1e-12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    Counts,
+    StateVector,
+    Sparse,
+    Dot,
+    Png,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    path: String,
+    shots: usize,
+    seed: Option<u64>,
+    output: Output,
+    format: Format,
+    png_path: String,
+    stats: bool,
+}
+
+enum Program {
+    Circuit(Circuit),
+    Quil(QuilProgram),
+}
+
+impl Program {
+    fn num_qubits(&self) -> usize {
+        match self {
+            Program::Circuit(c) => c.num_qubits,
+            Program::Quil(p) => p.num_qubits,
+        }
+    }
+
+    /// Whether the program measures anything, so that each shot has to be simulated
+    /// on its own instead of sampling the final state.
+    fn measures(&self) -> bool {
+        match self {
+            Program::Circuit(c) => c.instructions.iter().any(|i| matches!(i.op, Operation::Measure { .. })),
+            Program::Quil(p) => p.num_clbits > 0,
+        }
+    }
+
+    fn run(&self, sim: &mut QuantumCircuit) -> Vec<bool> {
+        match self {
+            Program::Circuit(c) => sim.run(c),
+            Program::Quil(p) => sim.run_quil(p),
+        }
+    }
+}
+
+/// What a run produced, ready to be printed in either format.
+enum Report {
+    Counts(BTreeMap<String, usize>),
+    StateVector(Vec<Complex64>),
+    Sparse(Vec<(String, Complex64)>),
+    Dot(String),
+    Png(String),
+}
+
+struct Stats {
+    terminal_nodes: usize,
+    nonterminal_nodes: usize,
+    parse_time: Duration,
+    simulate_time: Duration,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("repl") {
+        return repl::main(&args[1..]);
+    }
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    match args.next().map(String::as_str) {
+        Some("run") => {}
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+        None => return Err("missing command".to_string()),
+    }
+    let mut options = Options {
+        path: String::new(),
+        shots: 1024,
+        seed: None,
+        output: Output::Counts,
+        format: Format::Text,
+        png_path: "circuit.png".to_string(),
+        stats: false,
+    };
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--shots" => options.shots = value()?.parse().map_err(|_| "--shots takes a positive integer".to_string())?,
+            "--seed" => options.seed = Some(value()?.parse().map_err(|_| "--seed takes an unsigned integer".to_string())?),
+            "--output" => {
+                options.output = match value()?.as_str() {
+                    "counts" => Output::Counts,
+                    "statevector" => Output::StateVector,
+                    "sparse" => Output::Sparse,
+                    "dot" => Output::Dot,
+                    "png" => Output::Png,
+                    other => return Err(format!("unknown output '{}'", other)),
+                }
+            }
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format '{}'", other)),
+                }
+            }
+            "--png" => options.png_path = value()?.clone(),
+            "--stats" => options.stats = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            file if path.is_none() => path = Some(file.to_string()),
+            extra => return Err(format!("unexpected argument '{}'", extra)),
+        }
+    }
+    options.path = path.ok_or("missing circuit file")?;
+    if options.shots == 0 {
+        return Err("--shots takes a positive integer".to_string());
+    }
+    Ok(options)
+}
+
+fn load(path: &str) -> Result<Program, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let in_file = |e: &dyn std::fmt::Display| format!("{}:{}", path, e);
+    match path.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("quil") => parse_quil(&source).map(Program::Quil).map_err(|e| in_file(&e)),
+        Some("real") => parse_real(&source).map(|r| Program::Circuit(r.circuit)).map_err(|e| in_file(&e)),
+        _ if source.contains("OPENQASM 3") => parse_qasm3(&source).map(Program::Circuit).map_err(|e| in_file(&e)),
+        _ => parse_qasm2(&source).map(Program::Circuit).map_err(|e| in_file(&e)),
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let start = Instant::now();
+    let program = load(&options.path)?;
+    let parse_time = start.elapsed();
+
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+    let n = program.num_qubits();
+    let start = Instant::now();
+    let mut sim = QuantumCircuit::new(n);
+    sim.set_seed(rng.random());
+    let report = match options.output {
+        Output::Counts if program.measures() => {
+            let mut counts = BTreeMap::new();
+            for shot in 0..options.shots {
+                if shot > 0 {
+                    sim = QuantumCircuit::new(n);
+                    sim.set_seed(rng.random());
+                }
+                let clbits = program.run(&mut sim);
+                *counts.entry(bitstring(&clbits)).or_insert(0) += 1;
+            }
+            Report::Counts(counts)
+        }
+        Output::Counts => {
+            program.run(&mut sim);
+            Report::Counts(sim.sample(options.shots).into_iter()
+                .map(|(bits, count)| (bitstring(&bits), count))
+                .collect())
+        }
+        output => {
+            program.run(&mut sim);
+            match output {
+                Output::StateVector => Report::StateVector(sim.get_state_vector()),
+                Output::Sparse => Report::Sparse(sparse_state(&sim)),
+                Output::Dot => Report::Dot(sim.to_graphviz_dot()),
+                _ => {
+                    draw_dot(&sim.to_graphviz_dot(), &options.png_path)
+                        .map_err(|e| format!("could not render {}: {}", options.png_path, e))?;
+                    Report::Png(options.png_path.clone())
+                }
+            }
+        }
+    };
+    let (terminal_nodes, nonterminal_nodes) = sim.count_nodes();
+    let stats = options.stats.then(|| Stats {
+        terminal_nodes,
+        nonterminal_nodes,
+        parse_time,
+        simulate_time: start.elapsed(),
+    });
+
+    let text = match options.format {
+        Format::Text => to_text(&report, n, stats.as_ref()),
+        Format::Json => to_json(&report, n, stats.as_ref()),
+    };
+    println!("{}", text);
     Ok(())
 }
 
+/// Classical bits as a string, highest index first.
+fn bitstring(bits: &[bool]) -> String {
+    bits.iter().rev().map(|&b| if b { '1' } else { '0' }).collect()
+}
+
+fn basis_label(index: usize, n: usize) -> String {
+    format!("{:0width$b}", index, width = n)
+}
+
+/// Basis states with amplitude above `SPARSE_THRESHOLD`, labelled and in index order.
+/// Walks the DD with `top_k` rather than expanding the state vector.
+fn sparse_state(sim: &QuantumCircuit) -> Vec<(String, Complex64)> {
+    let mut amps: Vec<(String, Complex64)> = sim.top_k(usize::MAX).into_iter()
+        .filter(|(_, amp)| amp.norm() > SPARSE_THRESHOLD)
+        .map(|(bits, amp)| (bitstring(&bits), amp))
+        .collect();
+    // Labels all have the same length, so text order is index order.
+    amps.sort_by(|a, b| a.0.cmp(&b.0));
+    amps
+}
+
+fn to_text(report: &Report, n: usize, stats: Option<&Stats>) -> String {
+    let mut lines = Vec::new();
+    match report {
+        Report::Counts(counts) => {
+            for (bits, count) in counts {
+                lines.push(format!("{}: {}", bits, count));
+            }
+        }
+        Report::StateVector(state) => {
+            for (i, amp) in state.iter().enumerate() {
+                lines.push(format!("|{}⟩: {:.6}", basis_label(i, n), amp));
+            }
+        }
+        Report::Sparse(amps) => {
+            for (label, amp) in amps {
+                lines.push(format!("|{}⟩: {:.6}", label, amp));
+            }
+        }
+        Report::Dot(dot) => lines.push(dot.trim_end().to_string()),
+        Report::Png(path) => lines.push(format!("Graph image saved as {}", path)),
+    }
+    if let Some(s) = stats {
+        lines.push(format!("nodes: {} terminal, {} non-terminal", s.terminal_nodes, s.nonterminal_nodes));
+        lines.push(format!("parse: {:.3} ms", s.parse_time.as_secs_f64() * 1e3));
+        lines.push(format!("simulate: {:.3} ms", s.simulate_time.as_secs_f64() * 1e3));
+    }
+    lines.join("\n")
+}
+
+fn to_json(report: &Report, n: usize, stats: Option<&Stats>) -> String {
+    let complex = |z: &Complex64| format!("[{}, {}]", z.re, z.im);
+    let mut fields = vec![format!("\"num_qubits\": {}", n)];
+    fields.push(match report {
+        Report::Counts(counts) => {
+            let entries: Vec<String> = counts.iter().map(|(bits, c)| format!("\"{}\": {}", bits, c)).collect();
+            format!("\"counts\": {{{}}}", entries.join(", "))
+        }
+        Report::StateVector(state) => {
+            let entries: Vec<String> = state.iter().map(complex).collect();
+            format!("\"statevector\": [{}]", entries.join(", "))
+        }
+        Report::Sparse(amps) => {
+            let entries: Vec<String> = amps.iter()
+                .map(|(label, amp)| format!("\"{}\": {}", label, complex(amp)))
+                .collect();
+            format!("\"amplitudes\": {{{}}}", entries.join(", "))
+        }
+        Report::Dot(dot) => format!("\"dot\": {}", json_string(dot)),
+        Report::Png(path) => format!("\"png\": {}", json_string(path)),
+    });
+    if let Some(s) = stats {
+        fields.push(format!(
+            "\"stats\": {{\"terminal_nodes\": {}, \"nonterminal_nodes\": {}, \"parse_ms\": {}, \"simulate_ms\": {}}}",
+            s.terminal_nodes, s.nonterminal_nodes, s.parse_time.as_secs_f64() * 1e3, s.simulate_time.as_secs_f64() * 1e3,
+        ));
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Renders a DOT graph to PNG with Graphviz's `dot`, which must be on the PATH.
 fn draw_dot(dot: &str, output_path: &str) -> std::io::Result<()> {
-    // Write the DOT string to a temporary file.
-    let temp_dot = "temp.dot";
-    let mut file = File::create(temp_dot)?;
+    let temp_dot = std::env::temp_dir().join(format!("qdd_rs_{}.dot", std::process::id()));
+    let mut file = File::create(&temp_dot)?;
     file.write_all(dot.as_bytes())?;
 
-    // Use Graphviz to generate a PNG file.
-    Command::new("dot")
-        .args(&["-Tpng","-Gdpi=600", temp_dot, "-o", output_path])
-        .output()?;
-
-    // Optionally, remove the temporary DOT file.
-    // std::fs::remove_file(temp_dot)?;
+    let status = Command::new("dot")
+        .arg("-Tpng")
+        .arg("-Gdpi=600")
+        .arg(&temp_dot)
+        .args(["-o", output_path])
+        .status();
+    std::fs::remove_file(&temp_dot)?;
+    let status = status.map_err(|e| std::io::Error::new(e.kind(), format!("failed to run Graphviz `dot`: {}", e)))?;
+    if !status.success() {
+        return Err(std::io::Error::other("dot exited with an error"));
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args("run bell.qasm --shots 10 --seed 7 --output sparse --format json --stats")).unwrap();
+        assert_eq!(options, Options {
+            path: "bell.qasm".to_string(),
+            shots: 10,
+            seed: Some(7),
+            output: Output::Sparse,
+            format: Format::Json,
+            png_path: "circuit.png".to_string(),
+            stats: true,
+        });
+        assert_eq!(parse_args(&args("run")).unwrap_err(), "missing circuit file");
+        assert_eq!(parse_args(&args("run a.qasm --output svg")).unwrap_err(), "unknown output 'svg'");
+        assert_eq!(parse_args(&args("run a.qasm --shots")).unwrap_err(), "--shots needs a value");
+    }
+
+    #[test]
+    fn test_sample_is_seeded() {
+        let bell = |seed| {
+            let mut sim = QuantumCircuit::new(2);
+            sim.set_seed(seed);
+            sim.apply_h(0);
+            sim.apply_cnot(0, 1);
+            sim.sample(200)
+        };
+        let counts = bell(3);
+        assert_eq!(counts.len(), 2);
+        assert!(counts.contains_key(&vec![false, false]) && counts.contains_key(&vec![true, true]));
+        assert_eq!(counts.values().sum::<usize>(), 200);
+        assert_eq!(counts, bell(3));
+    }
+
+    #[test]
+    fn test_sparse_state() {
+        let mut sim = QuantumCircuit::new(3);
+        sim.apply_h(0);
+        sim.apply_cnot(0, 2);
+        let amps = sparse_state(&sim);
+        assert_eq!(amps.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>(), ["000", "101"]);
+        assert_eq!(sparse_state(&QuantumCircuit::new(65))[0].0, "0".repeat(65));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let mut counts = BTreeMap::new();
+        counts.insert("01".to_string(), 3);
+        assert_eq!(to_json(&Report::Counts(counts), 2, None), "{\"num_qubits\": 2, \"counts\": {\"01\": 3}}");
+        assert_eq!(json_string("a\"b\n"), "\"a\\\"b\\n\"");
+    }
+}
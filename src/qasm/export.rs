@@ -0,0 +1,372 @@
+// OpenQASM back end: writes a `Circuit` out as program text, for feeding circuits
+// built with the qdd_rs API into other simulators.
+use std::fmt;
+use num::complex::Complex64;
+use crate::{Circuit, Condition, Gate, Operation};
+use crate::constants::{ONE, TOL, ZERO};
+
+type Matrix = [[Complex64; 2]; 2];
+
+/// Error from writing a circuit in a format that cannot express one of its instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportError {
+    pub message: String,
+}
+
+impl ExportError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        ExportError { message: message.into() }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// How classical bits are declared in OpenQASM 2.0 output.
+#[derive(Clone, Copy, PartialEq)]
+enum Registers {
+    /// One `creg c[m]`; every condition compares the whole register.
+    Whole,
+    /// One `creg cK[1]` per bit; every condition tests a single bit.
+    PerBit,
+}
+
+impl Circuit {
+    /// OpenQASM 2.0 text for the circuit, over `qreg q` and `creg c` with `qelib1.inc` gates.
+    ///
+    /// Controlled gates with one control map onto the controlled gates of qelib1 (and
+    /// `ccx` for two controls on X); larger ones are decomposed into them. Negative
+    /// controls are conjugated with `x`. Global phases cannot be expressed in
+    /// OpenQASM 2.0 and are written as comments.
+    ///
+    /// OpenQASM 2.0 can only compare a whole register with a value. If every condition
+    /// compares all classical bits, they form one `creg c`. If every condition tests a
+    /// single bit, each bit gets its own register `cK[1]` so that `if(cK==v)` can test
+    /// it. Any other mix of conditions is an error.
+    pub fn to_qasm2(&self) -> Result<String, ExportError> {
+        let registers = self.qasm2_registers()?;
+        let mut out = String::from("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n");
+        if self.num_qubits > 0 {
+            out.push_str(&format!("qreg q[{}];\n", self.num_qubits));
+        }
+        match registers {
+            Registers::Whole if self.num_clbits > 0 => out.push_str(&format!("creg c[{}];\n", self.num_clbits)),
+            Registers::Whole => {}
+            Registers::PerBit => {
+                for c in 0..self.num_clbits {
+                    out.push_str(&format!("creg c{}[1];\n", c));
+                }
+            }
+        }
+        let clbit = |c: usize| match registers {
+            Registers::Whole => format!("c[{}]", c),
+            Registers::PerBit => format!("c{}[0]", c),
+        };
+        for inst in &self.instructions {
+            let prefix = match &inst.condition {
+                Some(Condition::Equals { clbits, value }) if registers == Registers::PerBit => format!("if(c{}=={}) ", clbits[0], value),
+                Some(cond) => format!("if(c=={}) ", register_value(cond)),
+                None => String::new(),
+            };
+            let mut lines = Vec::new();
+            match &inst.op {
+                Operation::Gate { gate, qubits } => {
+                    let name = match gate {
+                        Gate::P(_) => "u1",
+                        Gate::U(..) => "u3",
+                        other => other.name(),
+                    };
+                    lines.push(call(name, &gate.params(), qubits));
+                }
+                Operation::Controlled { gate, controls, neg_controls, target } => {
+                    for &q in neg_controls {
+                        lines.push(call("x", &[], &[q]));
+                    }
+                    let mut all = controls.clone();
+                    all.extend(neg_controls);
+                    qasm2_controlled(*gate, &all, *target, &mut lines);
+                    for &q in neg_controls {
+                        lines.push(call("x", &[], &[q]));
+                    }
+                }
+                Operation::GlobalPhase(gamma) => lines.push(format!("// gphase({});", gamma)),
+                Operation::Measure { qubit, clbit: c } => lines.push(format!("measure q[{}] -> {};", qubit, clbit(*c))),
+                Operation::Reset(qubit) => lines.push(format!("reset q[{}];", qubit)),
+                Operation::Barrier(qubits) if qubits.is_empty() => {}
+                Operation::Barrier(qubits) => lines.push(call("barrier", &[], qubits)),
+            }
+            for line in lines {
+                let prefix = if line.starts_with("//") { "" } else { prefix.as_str() };
+                out.push_str(&format!("{}{}\n", prefix, line));
+            }
+        }
+        Ok(out)
+    }
+
+    /// OpenQASM 3 text for the circuit, over `qubit[n] q` and `bit[m] c` with `stdgates.inc`.
+    ///
+    /// Controlled gates are written with `ctrl @`/`negctrl @` modifiers and conditions
+    /// become `if` statements, so `parse_qasm3` reads the output back unchanged.
+    pub fn to_qasm3(&self) -> String {
+        let mut out = String::from("OPENQASM 3.0;\ninclude \"stdgates.inc\";\n");
+        if self.num_qubits > 0 {
+            out.push_str(&format!("qubit[{}] q;\n", self.num_qubits));
+        }
+        if self.num_clbits > 0 {
+            out.push_str(&format!("bit[{}] c;\n", self.num_clbits));
+        }
+        for inst in &self.instructions {
+            let stmt = match &inst.op {
+                Operation::Gate { gate, qubits } => call(qasm3_name(*gate), &gate.params(), qubits),
+                Operation::Controlled { gate, controls, neg_controls, target } => {
+                    let mut modifiers = String::new();
+                    for (count, name) in [(controls.len(), "ctrl"), (neg_controls.len(), "negctrl")] {
+                        match count {
+                            0 => {}
+                            1 => modifiers.push_str(&format!("{} @ ", name)),
+                            n => modifiers.push_str(&format!("{}({}) @ ", name, n)),
+                        }
+                    }
+                    let mut qubits = controls.clone();
+                    qubits.extend(neg_controls);
+                    qubits.push(*target);
+                    modifiers + &call(qasm3_name(*gate), &gate.params(), &qubits)
+                }
+                Operation::GlobalPhase(gamma) => format!("gphase({});", gamma),
+                Operation::Measure { qubit, clbit } => format!("c[{}] = measure q[{}];", clbit, qubit),
+                Operation::Reset(qubit) => format!("reset q[{}];", qubit),
+                Operation::Barrier(qubits) if qubits.is_empty() => continue,
+                Operation::Barrier(qubits) => call("barrier", &[], qubits),
+            };
+            match &inst.condition {
+                Some(cond) => out.push_str(&format!("if ({}) {}\n", self.qasm3_condition(cond), stmt)),
+                None => out.push_str(&format!("{}\n", stmt)),
+            }
+        }
+        out
+    }
+
+    /// The register layout that expresses every condition of the circuit.
+    fn qasm2_registers(&self) -> Result<Registers, ExportError> {
+        let conditions: Vec<&Condition> = self.instructions.iter().filter_map(|i| i.condition.as_ref()).collect();
+        let whole = |cond: &&Condition| matches!(cond, Condition::Equals { clbits, .. }
+            if clbits.len() == self.num_clbits && (0..self.num_clbits).all(|b| clbits.contains(&b)));
+        let single = |cond: &&Condition| matches!(cond, Condition::Equals { clbits, .. } if clbits.len() == 1);
+        if conditions.iter().all(whole) && (self.num_clbits <= 64 || conditions.is_empty()) {
+            Ok(Registers::Whole)
+        } else if conditions.iter().all(single) {
+            Ok(Registers::PerBit)
+        } else {
+            let bad = conditions.iter().find(|c| !single(c)).unwrap();
+            Err(ExportError::new(format!(
+                "OpenQASM 2.0 can only condition on the whole classical register or on single bits, got {:?}", bad
+            )))
+        }
+    }
+
+    fn qasm3_condition(&self, cond: &Condition) -> String {
+        match cond {
+            Condition::Equals { clbits, value } => {
+                if clbits.len() > 1 && clbits.iter().copied().eq(0..self.num_clbits) {
+                    return format!("c == {}", value);
+                }
+                let terms: Vec<String> = clbits.iter().enumerate()
+                    .map(|(i, c)| format!("c[{}] == {}", c, value.checked_shr(i as u32).unwrap_or(0) & 1))
+                    .collect();
+                terms.join(" && ")
+            }
+            Condition::Not(inner) => format!("!({})", self.qasm3_condition(inner)),
+            Condition::And(a, b) => format!("{} && {}", self.qasm3_condition(a), self.qasm3_condition(b)),
+        }
+    }
+}
+
+/// Value of the whole classical register that makes `cond` true, for a condition on
+/// all of its (at most 64) bits.
+fn register_value(cond: &Condition) -> u64 {
+    match cond {
+        Condition::Equals { clbits, value } => clbits.iter().enumerate()
+            .filter(|(i, _)| value >> i & 1 == 1)
+            .map(|(_, &c)| 1u64 << c)
+            .sum(),
+        _ => unreachable!("qasm2_registers only accepts comparisons"),
+    }
+}
+
+fn qasm3_name(gate: Gate) -> &'static str {
+    match gate {
+        Gate::U(..) => "U",
+        other => other.name(),
+    }
+}
+
+/// `name(params) q[a], q[b];`
+fn call(name: &str, params: &[f64], qubits: &[usize]) -> String {
+    let mut s = name.to_string();
+    if !params.is_empty() {
+        let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+        s.push_str(&format!("({})", params.join(", ")));
+    }
+    let qubits: Vec<String> = qubits.iter().map(|q| format!("q[{}]", q)).collect();
+    format!("{} {};", s, qubits.join(", "))
+}
+
+/// Emits `gate` on `target` controlled by all of `controls`, using qelib1 gates.
+fn qasm2_controlled(gate: Gate, controls: &[usize], target: usize, lines: &mut Vec<String>) {
+    let qubits: Vec<usize> = controls.iter().copied().chain([target]).collect();
+    match (controls.len(), gate) {
+        (0, _) => lines.push(call(gate.name(), &gate.params(), &[target])),
+        (1, Gate::X | Gate::Y | Gate::Z | Gate::H) => lines.push(call(&format!("c{}", gate.name()), &[], &qubits)),
+        (1, Gate::S | Gate::Sdg | Gate::T | Gate::Tdg | Gate::P(_)) => {
+            let theta = gate.matrix().unwrap()[1][1].arg();
+            lines.push(call("cu1", &[theta], &qubits));
+        }
+        (1, Gate::U(theta, phi, lambda)) => lines.push(call("cu3", &[theta, phi, lambda], &qubits)),
+        (2, Gate::X) => lines.push(call("ccx", &[], &qubits)),
+        _ => controlled_matrix(gate.matrix().expect("controlled gates are single-qubit"), controls, target, lines),
+    }
+}
+
+/// Multi-controlled `m` through the recursion of Barenco et al. (Lemma 7.5), with V = sqrt(m):
+/// C(V) from the last control, flip it with the other controls, C(V†), flip back,
+/// then the remaining controls apply V.
+fn controlled_matrix(m: Matrix, controls: &[usize], target: usize, lines: &mut Vec<String>) {
+    let (&last, rest) = controls.split_last().unwrap();
+    if rest.is_empty() {
+        // m = exp(i*alpha) U3(theta, phi, lambda); the phase becomes a u1 on the control.
+        let (gate, alpha) = Gate::from_matrix(m);
+        if alpha.abs() > TOL {
+            lines.push(call("u1", &[alpha], &[last]));
+        }
+        lines.push(call("cu3", &gate.params(), &[last, target]));
+        return;
+    }
+    let v = sqrt(m);
+    let v_dag = [[v[0][0].conj(), v[1][0].conj()], [v[0][1].conj(), v[1][1].conj()]];
+    controlled_matrix(v, &[last], target, lines);
+    qasm2_controlled(Gate::X, rest, last, lines);
+    controlled_matrix(v_dag, &[last], target, lines);
+    qasm2_controlled(Gate::X, rest, last, lines);
+    controlled_matrix(v, rest, target, lines);
+}
+
+/// A square root of a 2x2 unitary: (m + s*I) / t with s = sqrt(det m), t = sqrt(tr m + 2s).
+fn sqrt(m: Matrix) -> Matrix {
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    let trace = m[0][0] + m[1][1];
+    let mut s = det.sqrt();
+    if (trace + 2.0 * s).norm() < 1e-6 {
+        s = -s;
+    }
+    let t = (trace + 2.0 * s).sqrt();
+    let id = [[ONE, ZERO], [ZERO, ONE]];
+    let mut r = m;
+    for i in 0..2 {
+        for j in 0..2 {
+            r[i][j] = (m[i][j] + s * id[i][j]) / t;
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_qasm2, parse_qasm3, QuantumCircuit};
+
+    /// Runs gates through `apply_mcu` only, which handles every gate exactly.
+    fn simulate(circ: &Circuit) -> Vec<Complex64> {
+        let mut sim = QuantumCircuit::new(circ.num_qubits);
+        for inst in &circ.instructions {
+            match &inst.op {
+                Operation::Gate { gate: Gate::CX, qubits } => sim.apply_mcu(&qubits[..1], &[], qubits[1], Gate::X.matrix().unwrap()),
+                Operation::Gate { gate: Gate::CZ, qubits } => sim.apply_mcu(&qubits[..1], &[], qubits[1], Gate::Z.matrix().unwrap()),
+                Operation::Gate { gate, qubits } => sim.apply_mcu(&[], &[], qubits[0], gate.matrix().unwrap()),
+                Operation::Controlled { gate, controls, neg_controls, target } => {
+                    sim.apply_mcu(controls, neg_controls, *target, gate.matrix().unwrap())
+                }
+                Operation::GlobalPhase(gamma) => sim.apply_global_phase(*gamma),
+                _ => {}
+            }
+        }
+        sim.get_state_vector()
+    }
+
+    fn sample_circuit() -> Circuit {
+        let mut circ = Circuit::new(5, 2);
+        circ.h(0).h(1).h(2).u(3, 0.3, 1.1, -0.4).cx(0, 4).p(4, 0.25).t(2)
+            .controlled(Gate::S, &[1], &[], 3)
+            .controlled(Gate::H, &[0], &[2], 4)
+            .controlled(Gate::X, &[0, 1], &[], 2)
+            .controlled(Gate::U(1.2, -0.5, 0.8), &[0, 2], &[3], 1)
+            .controlled(Gate::Y, &[4, 1, 2, 3], &[], 0)
+            .global_phase(0.5);
+        circ
+    }
+
+    #[test]
+    fn test_qasm3_round_trip() {
+        let mut circ = sample_circuit();
+        circ.barrier(&[0, 1]).measure(0, 0).measure(1, 1).x(2).c_if(&[0, 1], 2).reset(3);
+        circ.z(4).instructions.last_mut().unwrap().condition = Some(Condition::new(&[1], 1).negate());
+        let parsed = parse_qasm3(&circ.to_qasm3()).unwrap();
+        assert_eq!(parsed, circ);
+    }
+
+    #[test]
+    fn test_qasm2_decomposes_controlled_gates() {
+        let circ = sample_circuit();
+        let text = circ.to_qasm2().unwrap();
+        assert!(text.contains("ccx q[0], q[1], q[2];"));
+        assert!(text.contains("// gphase(0.5);"));
+        let parsed = parse_qasm2(&text).unwrap();
+        assert!(parsed.instructions.iter().all(|i| i.qubits().len() <= 2));
+
+        let mut expected = simulate(&circ);
+        // OpenQASM 2.0 drops the global phase.
+        for amp in expected.iter_mut() {
+            *amp *= Complex64::from_polar(1.0, -0.5);
+        }
+        for (a, b) in simulate(&parsed).iter().zip(&expected) {
+            assert!((a - b).norm() < 1e-9, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_qasm2_conditions() {
+        let mut circ = Circuit::new(1, 2);
+        circ.measure(0, 1).x(0).c_if(&[1, 0], 1);
+        let parsed = parse_qasm2(&circ.to_qasm2().unwrap()).unwrap();
+        assert_eq!(parsed.instructions[1].condition, Some(Condition::new(&[0, 1], 2)));
+
+        // Single-bit conditions, as the importers produce, get one register per bit.
+        let mut circ = Circuit::new(1, 2);
+        circ.x(0).measure(0, 0).x(0).c_if(&[0], 1).measure(0, 1).z(0).c_if(&[1], 0);
+        let text = circ.to_qasm2().unwrap();
+        assert!(text.contains("creg c1[1];") && text.contains("measure q[0] -> c1[0];"));
+        let parsed = parse_qasm2(&text).unwrap();
+        assert_eq!(parsed.instructions[2].condition, Some(Condition::new(&[0], 1)));
+        assert_eq!(parsed.instructions[4].condition, Some(Condition::new(&[1], 0)));
+
+        // A comparison of some but not all bits has no OpenQASM 2.0 form.
+        let mut circ = Circuit::new(1, 3);
+        circ.x(0).c_if(&[0, 2], 3);
+        assert!(circ.to_qasm2().is_err());
+    }
+
+    #[test]
+    fn test_conditions_on_many_clbits() {
+        let mut circ = Circuit::new(1, 70);
+        circ.measure(0, 69).x(0).c_if(&[69], 1);
+        assert!(circ.to_qasm2().unwrap().contains("if(c69==1) x q[0];"));
+        circ.x(0).c_if(&(0..70).collect::<Vec<_>>(), 1);
+        assert!(circ.to_qasm2().is_err());
+        let parsed = parse_qasm3(&circ.to_qasm3()).unwrap();
+        assert_eq!(parsed, circ);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use super::lexer::{describe, Cursor, Tok};
+use super::QasmError;
+
+/// Classical expression used for gate parameters (and indices in OpenQASM 3).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Num(f64),
+    /// Identifier with the position it was used at, resolved on evaluation.
+    Var(String, usize, usize),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+    Call(String, Box<Expr>, usize, usize),
+}
+
+impl Expr {
+    pub fn eval(&self, env: &HashMap<String, f64>) -> Result<f64, QasmError> {
+        Ok(match self {
+            Expr::Num(x) => *x,
+            Expr::Var(name, line, col) => match env.get(name) {
+                Some(v) => *v,
+                None => match name.as_str() {
+                    "pi" | "π" => std::f64::consts::PI,
+                    "tau" | "τ" => std::f64::consts::TAU,
+                    "euler" | "ℇ" => std::f64::consts::E,
+                    _ => return Err(QasmError::new(*line, *col, format!("unknown identifier '{}'", name))),
+                },
+            },
+            Expr::Neg(e) => -e.eval(env)?,
+            Expr::Bin(op, a, b) => {
+                let (a, b) = (a.eval(env)?, b.eval(env)?);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Expr::Call(func, arg, line, col) => {
+                let x = arg.eval(env)?;
+                match func.as_str() {
+                    "sin" => x.sin(),
+                    "cos" => x.cos(),
+                    "tan" => x.tan(),
+                    "exp" => x.exp(),
+                    "ln" => x.ln(),
+                    "sqrt" => x.sqrt(),
+                    "arcsin" => x.asin(),
+                    "arccos" => x.acos(),
+                    "arctan" => x.atan(),
+                    _ => return Err(QasmError::new(*line, *col, format!("unknown function '{}'", func))),
+                }
+            }
+        })
+    }
+}
+
+const FUNCTIONS: [&str; 9] = ["sin", "cos", "tan", "exp", "ln", "sqrt", "arcsin", "arccos", "arctan"];
+
+pub(crate) fn parse_expr(cur: &mut Cursor) -> Result<Expr, QasmError> {
+    let mut lhs = parse_term(cur)?;
+    loop {
+        let op = if cur.is_sym("+") { '+' } else if cur.is_sym("-") { '-' } else { break };
+        cur.next();
+        let rhs = parse_term(cur)?;
+        lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_term(cur: &mut Cursor) -> Result<Expr, QasmError> {
+    let mut lhs = parse_unary(cur)?;
+    loop {
+        let op = if cur.is_sym("*") { '*' } else if cur.is_sym("/") { '/' } else { break };
+        cur.next();
+        let rhs = parse_unary(cur)?;
+        lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_unary(cur: &mut Cursor) -> Result<Expr, QasmError> {
+    if cur.eat_sym("-") {
+        return Ok(Expr::Neg(Box::new(parse_unary(cur)?)));
+    }
+    if cur.eat_sym("+") {
+        return parse_unary(cur);
+    }
+    let base = parse_atom(cur)?;
+    if cur.eat_sym("^") {
+        let exp = parse_unary(cur)?;
+        return Ok(Expr::Bin('^', Box::new(base), Box::new(exp)));
+    }
+    Ok(base)
+}
+
+fn parse_atom(cur: &mut Cursor) -> Result<Expr, QasmError> {
+    let (line, col) = cur.here();
+    match cur.peek().clone() {
+        Tok::Int(n) => {
+            cur.next();
+            Ok(Expr::Num(n as f64))
+        }
+        Tok::Real(x) => {
+            cur.next();
+            Ok(Expr::Num(x))
+        }
+        Tok::Ident(name) => {
+            cur.next();
+            if FUNCTIONS.contains(&name.as_str()) {
+                cur.expect_sym("(")?;
+                let arg = parse_expr(cur)?;
+                cur.expect_sym(")")?;
+                Ok(Expr::Call(name, Box::new(arg), line, col))
+            } else {
+                Ok(Expr::Var(name, line, col))
+            }
+        }
+        Tok::Sym("(") => {
+            cur.next();
+            let e = parse_expr(cur)?;
+            cur.expect_sym(")")?;
+            Ok(e)
+        }
+        other => Err(cur.error(format!("expected expression, found {}", describe(&other)))),
+    }
+}
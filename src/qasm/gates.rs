@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use crate::{Gate, Operation};
+use crate::circuit::{add_controls, invert};
+use super::expr::{parse_expr, Expr};
+use super::lexer::{tokenize, Cursor};
+use super::QasmError;
+
+const QELIB1: &str = include_str!("qelib1.inc");
+const STDGATES: &str = include_str!("stdgates.inc");
+
+/// OpenQASM 3 gate modifier, outermost first in a call.
+#[derive(Debug, Clone)]
+pub(crate) enum Modifier {
+    Ctrl(usize),
+    NegCtrl(usize),
+    Inv,
+    Pow(Expr),
+}
+
+/// Modifier with its `pow` exponent evaluated.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ModValue {
+    Ctrl(usize),
+    NegCtrl(usize),
+    Inv,
+    Pow(f64),
+}
+
+impl Modifier {
+    pub fn eval(&self, env: &HashMap<String, f64>) -> Result<ModValue, QasmError> {
+        Ok(match self {
+            Modifier::Ctrl(n) => ModValue::Ctrl(*n),
+            Modifier::NegCtrl(n) => ModValue::NegCtrl(*n),
+            Modifier::Inv => ModValue::Inv,
+            Modifier::Pow(e) => ModValue::Pow(e.eval(env)?),
+        })
+    }
+}
+
+/// One statement inside a `gate` body.
+#[derive(Debug, Clone)]
+pub(crate) struct GateStmt {
+    pub modifiers: Vec<Modifier>,
+    pub name: String,
+    pub params: Vec<Expr>,
+    pub args: Vec<String>,
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct GateDef {
+    pub params: Vec<String>,
+    pub args: Vec<String>,
+    /// `None` for `opaque` declarations.
+    pub body: Option<Vec<GateStmt>>,
+    /// Gates from the standard library map straight onto `QuantumCircuit` methods when possible.
+    pub standard: bool,
+}
+
+/// Gate definitions known to a program, with expansion down to IR gates.
+pub(crate) struct GateTable {
+    defs: HashMap<String, GateDef>,
+    /// OpenQASM 3 mode: modifiers and `gphase` are allowed, `CX` is not a builtin.
+    qasm3: bool,
+}
+
+impl GateTable {
+    pub fn new(qasm3: bool) -> Self {
+        GateTable { defs: HashMap::new(), qasm3 }
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        name == "U" || if self.qasm3 { name == "gphase" } else { name == "CX" }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.is_builtin(name) || self.defs.contains_key(name)
+    }
+
+    /// Loads the embedded `qelib1.inc`.
+    pub fn include_qelib1(&mut self) -> Result<(), QasmError> {
+        self.include_library(QELIB1)
+    }
+
+    /// Loads the embedded OpenQASM 3 `stdgates.inc`.
+    pub fn include_stdgates(&mut self) -> Result<(), QasmError> {
+        self.include_library(STDGATES)
+    }
+
+    fn include_library(&mut self, source: &str) -> Result<(), QasmError> {
+        let mut cur = Cursor::new(tokenize(source)?);
+        while !cur.at_eof() {
+            cur.expect_keyword("gate")?;
+            self.parse_gate_def(&mut cur, true)?;
+        }
+        Ok(())
+    }
+
+    /// Parses `name(params) args { body }`, the `gate` keyword already consumed.
+    pub fn parse_gate_def(&mut self, cur: &mut Cursor, standard: bool) -> Result<(), QasmError> {
+        self.parse_def(cur, standard, false)
+    }
+
+    /// Parses `name(params) args;`, the `opaque` keyword already consumed.
+    pub fn parse_opaque_def(&mut self, cur: &mut Cursor) -> Result<(), QasmError> {
+        self.parse_def(cur, false, true)
+    }
+
+    fn parse_def(&mut self, cur: &mut Cursor, standard: bool, opaque: bool) -> Result<(), QasmError> {
+        let (line, col) = cur.here();
+        let name = cur.expect_ident()?;
+        if self.contains(&name) {
+            return Err(QasmError::new(line, col, format!("gate '{}' is already defined", name)));
+        }
+        let mut params = Vec::new();
+        if cur.eat_sym("(") {
+            if !cur.is_sym(")") {
+                params.push(cur.expect_ident()?);
+                while cur.eat_sym(",") {
+                    params.push(cur.expect_ident()?);
+                }
+            }
+            cur.expect_sym(")")?;
+        }
+        let mut args = vec![cur.expect_ident()?];
+        while cur.eat_sym(",") {
+            args.push(cur.expect_ident()?);
+        }
+        if opaque {
+            cur.expect_sym(";")?;
+            self.defs.insert(name, GateDef { params, args, body: None, standard });
+            return Ok(());
+        }
+
+        cur.expect_sym("{")?;
+        let mut body = Vec::new();
+        while !cur.eat_sym("}") {
+            let (line, col) = cur.here();
+            let modifiers = if self.qasm3 { parse_modifiers(cur)? } else { Vec::new() };
+            let (name_line, name_col) = cur.here();
+            let stmt_name = cur.expect_ident()?;
+            if stmt_name != "barrier" && !self.contains(&stmt_name) {
+                return Err(QasmError::new(name_line, name_col, format!("undefined gate '{}'", stmt_name)));
+            }
+            let mut stmt_params = Vec::new();
+            if cur.eat_sym("(") {
+                if !cur.is_sym(")") {
+                    stmt_params.push(parse_expr(cur)?);
+                    while cur.eat_sym(",") {
+                        stmt_params.push(parse_expr(cur)?);
+                    }
+                }
+                cur.expect_sym(")")?;
+            }
+            let mut stmt_args = Vec::new();
+            while !cur.is_sym(";") {
+                if !stmt_args.is_empty() {
+                    cur.expect_sym(",")?;
+                }
+                let (arg_line, arg_col) = cur.here();
+                let arg = cur.expect_ident()?;
+                if !args.contains(&arg) {
+                    return Err(QasmError::new(arg_line, arg_col, format!("unknown gate argument '{}'", arg)));
+                }
+                stmt_args.push(arg);
+            }
+            cur.expect_sym(";")?;
+            body.push(GateStmt { modifiers, name: stmt_name, params: stmt_params, args: stmt_args, line, col });
+        }
+        self.defs.insert(name, GateDef { params, args, body: Some(body), standard });
+        Ok(())
+    }
+
+    /// Expands a call of `name` on concrete qubits into IR operations appended to `out`.
+    /// `line`/`col` locate the call for error messages.
+    pub fn expand(
+        &self,
+        name: &str,
+        params: &[f64],
+        qubits: &[usize],
+        line: usize,
+        col: usize,
+        out: &mut Vec<Operation>,
+    ) -> Result<(), QasmError> {
+        self.expand_with_modifiers(&[], name, params, qubits, line, col, out)
+    }
+
+    /// Like `expand`, for a call prefixed by `modifiers` (outermost first). Control
+    /// modifiers take their qubits from the front of `qubits`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn expand_with_modifiers(
+        &self,
+        modifiers: &[ModValue],
+        name: &str,
+        params: &[f64],
+        qubits: &[usize],
+        line: usize,
+        col: usize,
+        out: &mut Vec<Operation>,
+    ) -> Result<(), QasmError> {
+        let err = |msg: String| QasmError::new(line, col, msg);
+        let Some((modifier, rest)) = modifiers.split_first() else {
+            return self.expand_plain(name, params, qubits, line, col, out);
+        };
+        let mut inner = Vec::new();
+        match *modifier {
+            ModValue::Ctrl(n) | ModValue::NegCtrl(n) => {
+                if n == 0 || qubits.len() <= n {
+                    return Err(err(format!("not enough qubits for {} control(s) on gate '{}'", n, name)));
+                }
+                self.expand_with_modifiers(rest, name, params, &qubits[n..], line, col, &mut inner)?;
+                let positive = matches!(modifier, ModValue::Ctrl(_));
+                out.extend(add_controls(inner, &qubits[..n], positive));
+            }
+            ModValue::Inv => {
+                self.expand_with_modifiers(rest, name, params, qubits, line, col, &mut inner)?;
+                out.extend(invert(inner));
+            }
+            ModValue::Pow(k) => {
+                self.expand_with_modifiers(rest, name, params, qubits, line, col, &mut inner)?;
+                out.extend(power(inner, k).ok_or_else(|| {
+                    err(format!("pow({}) is only supported for integer powers or phase gates", k))
+                })?);
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_plain(
+        &self,
+        name: &str,
+        params: &[f64],
+        qubits: &[usize],
+        line: usize,
+        col: usize,
+        out: &mut Vec<Operation>,
+    ) -> Result<(), QasmError> {
+        let err = |msg: String| QasmError::new(line, col, msg);
+        let (num_params, num_args) = match name {
+            "U" => (3, 1),
+            "CX" if !self.qasm3 => (0, 2),
+            "gphase" if self.qasm3 => (1, 0),
+            _ => match self.defs.get(name) {
+                Some(def) => (def.params.len(), def.args.len()),
+                None => return Err(err(format!("undefined gate '{}'", name))),
+            },
+        };
+        if params.len() != num_params {
+            return Err(err(format!("gate '{}' takes {} parameter(s), got {}", name, num_params, params.len())));
+        }
+        if qubits.len() != num_args {
+            return Err(err(format!("gate '{}' acts on {} qubit(s), got {}", name, num_args, qubits.len())));
+        }
+        for (i, q) in qubits.iter().enumerate() {
+            if qubits[..i].contains(q) {
+                return Err(err(format!("gate '{}' applied to the same qubit twice", name)));
+            }
+        }
+        if name == "gphase" && self.qasm3 {
+            out.push(Operation::GlobalPhase(params[0]));
+            return Ok(());
+        }
+
+        let def = self.defs.get(name);
+        if self.is_builtin(name) || def.is_some_and(|d| d.standard) {
+            if let Some(gate) = native_gate(name, params) {
+                if let Some(gate) = gate {
+                    out.push(Operation::Gate { gate, qubits: qubits.to_vec() });
+                }
+                return Ok(());
+            }
+        }
+
+        let def = def.expect("builtin gates are always native");
+        let body = match &def.body {
+            Some(body) => body,
+            None => return Err(err(format!("opaque gate '{}' cannot be simulated", name))),
+        };
+        let env: HashMap<String, f64> = def.params.iter().cloned().zip(params.iter().cloned()).collect();
+        let arg_map: HashMap<&str, usize> = def.args.iter().map(|a| a.as_str()).zip(qubits.iter().cloned()).collect();
+        for stmt in body {
+            let stmt_qubits: Vec<usize> = stmt.args.iter().map(|a| arg_map[a.as_str()]).collect();
+            if stmt.name == "barrier" {
+                out.push(Operation::Barrier(stmt_qubits));
+                continue;
+            }
+            let stmt_params = stmt.params.iter().map(|p| p.eval(&env)).collect::<Result<Vec<_>, _>>()?;
+            let modifiers = stmt.modifiers.iter().map(|m| m.eval(&env)).collect::<Result<Vec<_>, _>>()?;
+            self.expand_with_modifiers(&modifiers, &stmt.name, &stmt_params, &stmt_qubits, stmt.line, stmt.col, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a (possibly empty) chain like `ctrl(2) @ inv @ pow(2) @`.
+pub(crate) fn parse_modifiers(cur: &mut Cursor) -> Result<Vec<Modifier>, QasmError> {
+    let mut modifiers = Vec::new();
+    loop {
+        let modifier = if cur.is_ident("ctrl") || cur.is_ident("negctrl") {
+            let negative = cur.is_ident("negctrl");
+            cur.next();
+            let mut n = 1;
+            if cur.eat_sym("(") {
+                n = cur.expect_int()? as usize;
+                cur.expect_sym(")")?;
+            }
+            if negative { Modifier::NegCtrl(n) } else { Modifier::Ctrl(n) }
+        } else if cur.is_ident("inv") {
+            cur.next();
+            Modifier::Inv
+        } else if cur.is_ident("pow") {
+            cur.next();
+            cur.expect_sym("(")?;
+            let k = parse_expr(cur)?;
+            cur.expect_sym(")")?;
+            Modifier::Pow(k)
+        } else {
+            return Ok(modifiers);
+        };
+        cur.expect_sym("@")?;
+        modifiers.push(modifier);
+    }
+}
+
+/// Standard gates with a direct `QuantumCircuit` method. `Some(None)` marks an identity.
+fn native_gate(name: &str, params: &[f64]) -> Option<Option<Gate>> {
+    let gate = match name {
+        "U" | "u3" | "u" => Gate::U(params[0], params[1], params[2]),
+        "u2" => Gate::U(std::f64::consts::FRAC_PI_2, params[0], params[1]),
+        "u1" | "p" | "phase" => Gate::P(params[0]),
+        "CX" | "cx" => Gate::CX,
+        "cz" => Gate::CZ,
+        "h" => Gate::H,
+        "x" => Gate::X,
+        "y" => Gate::Y,
+        "z" => Gate::Z,
+        "s" => Gate::S,
+        "sdg" => Gate::Sdg,
+        "t" => Gate::T,
+        "tdg" => Gate::Tdg,
+        "id" | "u0" => return Some(None),
+        _ => return None,
+    };
+    Some(Some(gate))
+}
+
+/// Integer powers repeat the body; other powers only work for a single phase gate.
+fn power(ops: Vec<Operation>, k: f64) -> Option<Vec<Operation>> {
+    if k.fract() == 0.0 {
+        let body = if k < 0.0 { invert(ops) } else { ops };
+        return Some((0..k.abs() as usize).flat_map(|_| body.clone()).collect());
+    }
+    let scaled = ops.into_iter().filter(|op| !matches!(op, Operation::Barrier(_))).map(|op| match op {
+        Operation::GlobalPhase(gamma) => Some(Operation::GlobalPhase(gamma * k)),
+        Operation::Gate { gate, qubits } => gate.phase_angle().map(|theta| {
+            Operation::Gate { gate: Gate::P(theta * k), qubits }
+        }),
+        Operation::Controlled { gate, controls, neg_controls, target } => gate.phase_angle().map(|theta| {
+            Operation::Controlled { gate: Gate::P(theta * k), controls, neg_controls, target }
+        }),
+        _ => None,
+    }).collect::<Option<Vec<_>>>()?;
+    let gates = scaled.iter().filter(|op| !matches!(op, Operation::GlobalPhase(_))).count();
+    if gates == 1 { Some(scaled) } else { None }
+}
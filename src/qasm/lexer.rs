@@ -0,0 +1,258 @@
+use super::QasmError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Tok {
+    Ident(String),
+    Int(u64),
+    Real(f64),
+    Str(String),
+    /// Punctuation and operators, e.g. ";", "->", "==".
+    Sym(&'static str),
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Token {
+    pub tok: Tok,
+    pub line: usize,
+    pub col: usize,
+}
+
+// Longest symbols first so that "->" wins over "-".
+const SYMBOLS: [&str; 29] = [
+    "->", "==", "!=", "<=", ">=", "&&", "||", "++", "+=", "-=",
+    ";", ",", "(", ")", "[", "]", "{", "}", "+", "-", "*", "/", "^", "@", ":", "=", "<", ">", "!",
+];
+
+/// Splits OpenQASM source into tokens, skipping whitespace and `//` / `/* */` comments.
+pub(crate) fn tokenize(src: &str) -> Result<Vec<Token>, QasmError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut col) = (0, 1, 1);
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            col = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            col += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let (start_line, start_col) = (line, col);
+            i += 2;
+            col += 2;
+            loop {
+                if i >= chars.len() {
+                    return Err(QasmError::new(start_line, start_col, "unterminated block comment"));
+                }
+                if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                    i += 2;
+                    col += 2;
+                    break;
+                }
+                if chars[i] == '\n' {
+                    line += 1;
+                    col = 1;
+                } else {
+                    col += 1;
+                }
+                i += 1;
+            }
+            continue;
+        }
+
+        let (start, start_col) = (i, col);
+        let tok = if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Tok::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let mut is_real = false;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i < chars.len() && chars[i] == '.' {
+                is_real = true;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    is_real = true;
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            if is_real {
+                let value = text.parse::<f64>()
+                    .map_err(|_| QasmError::new(line, start_col, format!("invalid number '{}'", text)))?;
+                Tok::Real(value)
+            } else {
+                let value = text.parse::<u64>()
+                    .map_err(|_| QasmError::new(line, start_col, format!("invalid integer '{}'", text)))?;
+                Tok::Int(value)
+            }
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' && chars[i] != '\n' {
+                i += 1;
+            }
+            if i >= chars.len() || chars[i] != '"' {
+                return Err(QasmError::new(line, start_col, "unterminated string"));
+            }
+            i += 1;
+            Tok::Str(chars[start + 1..i - 1].iter().collect())
+        } else {
+            let sym = SYMBOLS.iter().find(|s| {
+                s.chars().enumerate().all(|(k, sc)| chars.get(i + k) == Some(&sc))
+            });
+            match sym {
+                Some(s) => {
+                    i += s.len();
+                    Tok::Sym(s)
+                }
+                None => return Err(QasmError::new(line, col, format!("unexpected character '{}'", c))),
+            }
+        };
+        col += i - start;
+        tokens.push(Token { tok, line, col: start_col });
+    }
+    tokens.push(Token { tok: Tok::Eof, line, col });
+    Ok(tokens)
+}
+
+/// Cursor over a token list with the helpers shared by the QASM parsers.
+pub(crate) struct Cursor {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Cursor {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Cursor { tokens, pos: 0 }
+    }
+
+    /// Current position, to come back to with `seek` (e.g. to replay a loop body).
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].tok
+    }
+
+    pub fn here(&self) -> (usize, usize) {
+        let t = &self.tokens[self.pos];
+        (t.line, t.col)
+    }
+
+    pub fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].tok.clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    pub fn error(&self, msg: impl Into<String>) -> QasmError {
+        let (line, col) = self.here();
+        QasmError::new(line, col, msg)
+    }
+
+    pub fn is_sym(&self, sym: &str) -> bool {
+        matches!(self.peek(), Tok::Sym(s) if *s == sym)
+    }
+
+    pub fn is_ident(&self, name: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(s) if s == name)
+    }
+
+    /// Consumes `sym` if it is next, returning whether it did.
+    pub fn eat_sym(&mut self, sym: &str) -> bool {
+        if self.is_sym(sym) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect_sym(&mut self, sym: &str) -> Result<(), QasmError> {
+        if self.eat_sym(sym) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}', found {}", sym, describe(self.peek()))))
+        }
+    }
+
+    pub fn expect_ident(&mut self) -> Result<String, QasmError> {
+        match self.peek().clone() {
+            Tok::Ident(name) => {
+                self.next();
+                Ok(name)
+            }
+            other => Err(self.error(format!("expected identifier, found {}", describe(&other)))),
+        }
+    }
+
+    pub fn expect_keyword(&mut self, keyword: &str) -> Result<(), QasmError> {
+        if self.is_ident(keyword) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}', found {}", keyword, describe(self.peek()))))
+        }
+    }
+
+    pub fn expect_int(&mut self) -> Result<u64, QasmError> {
+        match self.peek().clone() {
+            Tok::Int(n) => {
+                self.next();
+                Ok(n)
+            }
+            other => Err(self.error(format!("expected integer, found {}", describe(&other)))),
+        }
+    }
+
+    pub fn at_eof(&self) -> bool {
+        matches!(self.peek(), Tok::Eof)
+    }
+}
+
+/// Human-readable description of a token for error messages.
+pub(crate) fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Ident(s) => format!("'{}'", s),
+        Tok::Int(n) => format!("'{}'", n),
+        Tok::Real(x) => format!("'{}'", x),
+        Tok::Str(s) => format!("\"{}\"", s),
+        Tok::Sym(s) => format!("'{}'", s),
+        Tok::Eof => "end of file".to_string(),
+    }
+}
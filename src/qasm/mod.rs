@@ -0,0 +1,35 @@
+// OpenQASM front end: turns program text into the `Circuit` IR, and back.
+use std::fmt;
+
+mod lexer;
+mod expr;
+mod gates;
+mod qasm2;
+mod qasm3;
+mod export;
+
+pub use qasm2::parse_qasm2;
+pub use qasm3::parse_qasm3;
+pub use export::ExportError;
+
+/// Parse error with the 1-based line and column it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QasmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl QasmError {
+    pub(crate) fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        QasmError { line, column, message: message.into() }
+    }
+}
+
+impl fmt::Display for QasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for QasmError {}
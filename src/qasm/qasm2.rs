@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use crate::{Circuit, Condition, Instruction, Operation};
+use super::expr::parse_expr;
+use super::gates::GateTable;
+use super::lexer::{describe, tokenize, Cursor, Tok};
+use super::QasmError;
+
+/// A declared register: first flat index and size.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Register {
+    pub start: usize,
+    pub size: usize,
+}
+
+/// Operand of a top-level statement: a whole register or a single element, already flattened.
+pub(crate) type Operand = Vec<usize>;
+
+struct Parser {
+    cur: Cursor,
+    gates: GateTable,
+    qregs: HashMap<String, Register>,
+    cregs: HashMap<String, Register>,
+    num_qubits: usize,
+    num_clbits: usize,
+    instructions: Vec<Instruction>,
+}
+
+/// Parses an OpenQASM 2.0 program into a `Circuit`.
+///
+/// Registers are flattened in declaration order, so with `qreg a[2]; qreg b[1];`
+/// `b[0]` becomes qubit 2. Gates from `qelib1.inc` that have a `QuantumCircuit`
+/// method are kept as they are; everything else is expanded through its definition.
+pub fn parse_qasm2(source: &str) -> Result<Circuit, QasmError> {
+    let mut parser = Parser {
+        cur: Cursor::new(tokenize(source)?),
+        gates: GateTable::new(false),
+        qregs: HashMap::new(),
+        cregs: HashMap::new(),
+        num_qubits: 0,
+        num_clbits: 0,
+        instructions: Vec::new(),
+    };
+    parser.parse_header()?;
+    while !parser.cur.at_eof() {
+        parser.parse_statement()?;
+    }
+    Ok(Circuit {
+        num_qubits: parser.num_qubits,
+        num_clbits: parser.num_clbits,
+        instructions: parser.instructions,
+    })
+}
+
+impl Parser {
+    fn parse_header(&mut self) -> Result<(), QasmError> {
+        if !self.cur.is_ident("OPENQASM") {
+            return Ok(());
+        }
+        self.cur.next();
+        let (line, col) = self.cur.here();
+        match self.cur.next() {
+            Tok::Real(v) if (2.0..3.0).contains(&v) => {}
+            Tok::Int(2) => {}
+            other => return Err(QasmError::new(line, col, format!("unsupported OpenQASM version {}", describe(&other)))),
+        }
+        self.cur.expect_sym(";")
+    }
+
+    fn parse_statement(&mut self) -> Result<(), QasmError> {
+        let (line, col) = self.cur.here();
+        let keyword = self.cur.expect_ident()?;
+        match keyword.as_str() {
+            "include" => {
+                let (line, col) = self.cur.here();
+                match self.cur.next() {
+                    Tok::Str(file) if file == "qelib1.inc" => self.gates.include_qelib1()?,
+                    Tok::Str(file) => return Err(QasmError::new(line, col, format!("cannot include \"{}\"", file))),
+                    other => return Err(QasmError::new(line, col, format!("expected file name, found {}", describe(&other)))),
+                }
+                self.cur.expect_sym(";")
+            }
+            "qreg" | "creg" => self.parse_register(keyword == "qreg"),
+            "gate" => self.gates.parse_gate_def(&mut self.cur, false),
+            "opaque" => self.gates.parse_opaque_def(&mut self.cur),
+            "if" => {
+                self.cur.expect_sym("(")?;
+                let (reg_line, reg_col) = self.cur.here();
+                let name = self.cur.expect_ident()?;
+                let reg = *self.cregs.get(&name)
+                    .ok_or_else(|| QasmError::new(reg_line, reg_col, format!("unknown classical register '{}'", name)))?;
+                self.cur.expect_sym("==")?;
+                let value = self.cur.expect_int()?;
+                self.cur.expect_sym(")")?;
+                let clbits: Vec<usize> = (reg.start..reg.start + reg.size).collect();
+                let condition = Condition::new(&clbits, value);
+                let (line, col) = self.cur.here();
+                let keyword = self.cur.expect_ident()?;
+                self.parse_quantum_op(&keyword, line, col, Some(condition))
+            }
+            _ => self.parse_quantum_op(&keyword, line, col, None),
+        }
+    }
+
+    fn parse_register(&mut self, quantum: bool) -> Result<(), QasmError> {
+        let (line, col) = self.cur.here();
+        let name = self.cur.expect_ident()?;
+        if self.qregs.contains_key(&name) || self.cregs.contains_key(&name) {
+            return Err(QasmError::new(line, col, format!("register '{}' is already declared", name)));
+        }
+        self.cur.expect_sym("[")?;
+        let size = self.cur.expect_int()? as usize;
+        self.cur.expect_sym("]")?;
+        self.cur.expect_sym(";")?;
+        if quantum {
+            self.qregs.insert(name, Register { start: self.num_qubits, size });
+            self.num_qubits += size;
+        } else {
+            self.cregs.insert(name, Register { start: self.num_clbits, size });
+            self.num_clbits += size;
+        }
+        Ok(())
+    }
+
+    /// Parses `measure`, `reset`, `barrier` or a gate call whose first word is `keyword`.
+    fn parse_quantum_op(
+        &mut self,
+        keyword: &str,
+        line: usize,
+        col: usize,
+        condition: Option<Condition>,
+    ) -> Result<(), QasmError> {
+        let mut ops = Vec::new();
+        match keyword {
+            "measure" => {
+                let qubits = self.parse_operand(true)?;
+                self.cur.expect_sym("->")?;
+                let clbits = self.parse_operand(false)?;
+                if qubits.len() != clbits.len() {
+                    return Err(QasmError::new(line, col, "measure operands have different sizes"));
+                }
+                for (qubit, clbit) in qubits.into_iter().zip(clbits) {
+                    ops.push(Operation::Measure { qubit, clbit });
+                }
+            }
+            "reset" => {
+                for q in self.parse_operand(true)? {
+                    ops.push(Operation::Reset(q));
+                }
+            }
+            "barrier" => {
+                let operands = self.parse_operand_list()?;
+                ops.push(Operation::Barrier(operands.into_iter().flatten().collect()));
+            }
+            name => {
+                if !self.gates.contains(name) {
+                    return Err(QasmError::new(line, col, format!("undefined gate '{}'", name)));
+                }
+                let mut params = Vec::new();
+                if self.cur.eat_sym("(") {
+                    if !self.cur.is_sym(")") {
+                        params.push(parse_expr(&mut self.cur)?.eval(&HashMap::new())?);
+                        while self.cur.eat_sym(",") {
+                            params.push(parse_expr(&mut self.cur)?.eval(&HashMap::new())?);
+                        }
+                    }
+                    self.cur.expect_sym(")")?;
+                }
+                let operands = self.parse_operand_list()?;
+                for qubits in broadcast(&operands).ok_or_else(|| {
+                    QasmError::new(line, col, "register operands have different sizes")
+                })? {
+                    self.gates.expand(name, &params, &qubits, line, col, &mut ops)?;
+                }
+            }
+        }
+        self.cur.expect_sym(";")?;
+        for op in ops {
+            self.instructions.push(Instruction { op, condition: condition.clone() });
+        }
+        Ok(())
+    }
+
+    fn parse_operand_list(&mut self) -> Result<Vec<Operand>, QasmError> {
+        let mut operands = vec![self.parse_operand(true)?];
+        while self.cur.eat_sym(",") {
+            operands.push(self.parse_operand(true)?);
+        }
+        Ok(operands)
+    }
+
+    /// Parses `name` or `name[index]` for a quantum (or classical) register.
+    fn parse_operand(&mut self, quantum: bool) -> Result<Operand, QasmError> {
+        let (line, col) = self.cur.here();
+        let name = self.cur.expect_ident()?;
+        let regs = if quantum { &self.qregs } else { &self.cregs };
+        let reg = *regs.get(&name).ok_or_else(|| {
+            let kind = if quantum { "quantum" } else { "classical" };
+            QasmError::new(line, col, format!("unknown {} register '{}'", kind, name))
+        })?;
+        if self.cur.eat_sym("[") {
+            let (line, col) = self.cur.here();
+            let index = self.cur.expect_int()? as usize;
+            if index >= reg.size {
+                return Err(QasmError::new(line, col, format!("index {} out of range for '{}[{}]'", index, name, reg.size)));
+            }
+            self.cur.expect_sym("]")?;
+            Ok(vec![reg.start + index])
+        } else {
+            Ok((reg.start..reg.start + reg.size).collect())
+        }
+    }
+}
+
+/// Applies OpenQASM broadcasting: single qubits are repeated alongside whole registers,
+/// which must all have the same size. Returns `None` on a size mismatch.
+pub(crate) fn broadcast(operands: &[Operand]) -> Option<Vec<Vec<usize>>> {
+    let size = operands.iter().map(|o| o.len()).filter(|&n| n != 1).max().unwrap_or(1);
+    if operands.iter().any(|o| o.len() != 1 && o.len() != size) {
+        return None;
+    }
+    Some((0..size).map(|i| {
+        operands.iter().map(|o| if o.len() == 1 { o[0] } else { o[i] }).collect()
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Gate;
+
+    #[test]
+    fn test_bell_program() {
+        let src = r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[2];
+            creg c[2];
+            h q[0];
+            cx q[0], q[1];
+            barrier q;
+            measure q -> c;
+        "#;
+        let circ = parse_qasm2(src).unwrap();
+        assert_eq!(circ.num_qubits, 2);
+        assert_eq!(circ.num_clbits, 2);
+        let ops: Vec<Operation> = circ.instructions.iter().map(|i| i.op.clone()).collect();
+        assert_eq!(ops, vec![
+            Operation::Gate { gate: Gate::H, qubits: vec![0] },
+            Operation::Gate { gate: Gate::CX, qubits: vec![0, 1] },
+            Operation::Barrier(vec![0, 1]),
+            Operation::Measure { qubit: 0, clbit: 0 },
+            Operation::Measure { qubit: 1, clbit: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_custom_gate_and_if() {
+        let src = r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            gate bell(theta) a, b { h a; cx a, b; rz(theta/2) b; }
+            qreg q[2];
+            qreg r[1];
+            creg c[1];
+            bell(pi) q[1], r[0];
+            reset q[0];
+            if (c == 1) x r[0];
+        "#;
+        let circ = parse_qasm2(src).unwrap();
+        let ops: Vec<Operation> = circ.instructions.iter().map(|i| i.op.clone()).collect();
+        assert_eq!(ops, vec![
+            Operation::Gate { gate: Gate::H, qubits: vec![1] },
+            Operation::Gate { gate: Gate::CX, qubits: vec![1, 2] },
+            Operation::Gate { gate: Gate::P(std::f64::consts::FRAC_PI_2), qubits: vec![2] },
+            Operation::Reset(0),
+            Operation::Gate { gate: Gate::X, qubits: vec![2] },
+        ]);
+        assert_eq!(circ.instructions[4].condition, Some(Condition::new(&[0], 1)));
+    }
+
+    #[test]
+    fn test_broadcast_and_expansion() {
+        let src = "OPENQASM 2.0; include \"qelib1.inc\"; qreg a[2]; qreg b[2]; swap a, b;";
+        let circ = parse_qasm2(src).unwrap();
+        assert_eq!(circ.gate_count(), 6);
+        assert_eq!(circ.instructions[3].op, Operation::Gate { gate: Gate::CX, qubits: vec![1, 3] });
+    }
+
+    #[test]
+    fn test_errors_carry_position() {
+        let err = parse_qasm2("OPENQASM 2.0;\nqreg q[1];\nfoo q[0];").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 1));
+        assert!(err.message.contains("undefined gate 'foo'"));
+
+        let err = parse_qasm2("qreg q[2];\nU(0, 0) q[0];").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+
+        let err = parse_qasm2("qreg q[2];\nmeasure q[5] -> c[0];").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 11));
+    }
+}
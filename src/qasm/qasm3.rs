@@ -0,0 +1,600 @@
+use std::collections::HashMap;
+use crate::{Circuit, Condition, Instruction, Operation};
+use super::expr::{parse_expr, Expr};
+use super::gates::{parse_modifiers, GateTable};
+use super::lexer::{describe, tokenize, Cursor, Tok};
+use super::qasm2::{broadcast, Operand, Register};
+use super::QasmError;
+
+struct Parser {
+    cur: Cursor,
+    gates: GateTable,
+    qregs: HashMap<String, Register>,
+    cregs: HashMap<String, Register>,
+    num_qubits: usize,
+    num_clbits: usize,
+    instructions: Vec<Instruction>,
+    /// Loop variables and `const` declarations.
+    env: HashMap<String, f64>,
+    /// Conditions of the enclosing `if`/`else` blocks.
+    conditions: Vec<Condition>,
+}
+
+/// Parses the commonly used subset of OpenQASM 3 into a `Circuit`.
+///
+/// Supported: `qubit`/`bit` (and `qreg`/`creg`) declarations, `const` numbers,
+/// `gate` definitions, the `ctrl @`, `negctrl @`, `inv @` and `pow(k) @` modifiers,
+/// `measure`, `reset`, `barrier`, `for` loops over constant ranges (unrolled) and
+/// `if`/`else` on measurement results (turned into instruction conditions).
+pub fn parse_qasm3(source: &str) -> Result<Circuit, QasmError> {
+    let mut parser = Parser {
+        cur: Cursor::new(tokenize(source)?),
+        gates: GateTable::new(true),
+        qregs: HashMap::new(),
+        cregs: HashMap::new(),
+        num_qubits: 0,
+        num_clbits: 0,
+        instructions: Vec::new(),
+        env: HashMap::new(),
+        conditions: Vec::new(),
+    };
+    parser.parse_header()?;
+    while !parser.cur.at_eof() {
+        parser.parse_statement()?;
+    }
+    Ok(Circuit {
+        num_qubits: parser.num_qubits,
+        num_clbits: parser.num_clbits,
+        instructions: parser.instructions,
+    })
+}
+
+impl Parser {
+    fn parse_header(&mut self) -> Result<(), QasmError> {
+        if !self.cur.is_ident("OPENQASM") {
+            return Ok(());
+        }
+        self.cur.next();
+        let (line, col) = self.cur.here();
+        match self.cur.next() {
+            Tok::Real(v) if (3.0..4.0).contains(&v) => {}
+            Tok::Int(3) => {}
+            other => return Err(QasmError::new(line, col, format!("unsupported OpenQASM version {}", describe(&other)))),
+        }
+        self.cur.expect_sym(";")
+    }
+
+    fn parse_statement(&mut self) -> Result<(), QasmError> {
+        if self.cur.eat_sym("{") {
+            while !self.cur.eat_sym("}") {
+                if self.cur.at_eof() {
+                    return Err(self.cur.error("expected '}', found end of file"));
+                }
+                self.parse_statement()?;
+            }
+            return Ok(());
+        }
+        let (line, col) = self.cur.here();
+        if self.cur.is_ident("ctrl") || self.cur.is_ident("negctrl") || self.cur.is_ident("inv") || self.cur.is_ident("pow") {
+            return self.parse_gate_call(line, col);
+        }
+        let keyword = match self.cur.peek() {
+            Tok::Ident(name) => name.clone(),
+            other => return Err(self.cur.error(format!("expected statement, found {}", describe(other)))),
+        };
+        match keyword.as_str() {
+            "include" => {
+                self.cur.next();
+                let (line, col) = self.cur.here();
+                match self.cur.next() {
+                    Tok::Str(file) if file == "stdgates.inc" => self.gates.include_stdgates()?,
+                    Tok::Str(file) => return Err(QasmError::new(line, col, format!("cannot include \"{}\"", file))),
+                    other => return Err(QasmError::new(line, col, format!("expected file name, found {}", describe(&other)))),
+                }
+                self.cur.expect_sym(";")
+            }
+            "qubit" | "bit" => {
+                self.cur.next();
+                let size = if self.cur.eat_sym("[") {
+                    let size = self.parse_index()?;
+                    self.cur.expect_sym("]")?;
+                    size
+                } else {
+                    1
+                };
+                let name_pos = self.cur.here();
+                let name = self.cur.expect_ident()?;
+                self.cur.expect_sym(";")?;
+                self.declare(name, size, keyword == "qubit", name_pos)
+            }
+            "qreg" | "creg" => {
+                self.cur.next();
+                let name_pos = self.cur.here();
+                let name = self.cur.expect_ident()?;
+                self.cur.expect_sym("[")?;
+                let size = self.parse_index()?;
+                self.cur.expect_sym("]")?;
+                self.cur.expect_sym(";")?;
+                self.declare(name, size, keyword == "qreg", name_pos)
+            }
+            "const" => {
+                self.cur.next();
+                self.skip_type()?;
+                let name = self.cur.expect_ident()?;
+                self.cur.expect_sym("=")?;
+                let value = parse_expr(&mut self.cur)?.eval(&self.env)?;
+                self.cur.expect_sym(";")?;
+                self.env.insert(name, value);
+                Ok(())
+            }
+            "gate" => {
+                self.cur.next();
+                self.gates.parse_gate_def(&mut self.cur, false)
+            }
+            "measure" => {
+                self.cur.next();
+                let qubits = self.parse_operand(true)?;
+                self.cur.expect_sym("->")?;
+                let clbits = self.parse_operand(false)?;
+                self.cur.expect_sym(";")?;
+                self.emit_measure(qubits, clbits, line, col)
+            }
+            "reset" => {
+                self.cur.next();
+                let qubits = self.parse_operand(true)?;
+                self.cur.expect_sym(";")?;
+                for q in qubits {
+                    self.emit_op(Operation::Reset(q));
+                }
+                Ok(())
+            }
+            "barrier" => {
+                self.cur.next();
+                let qubits = if self.cur.is_sym(";") {
+                    (0..self.num_qubits).collect()
+                } else {
+                    self.parse_operand_list()?.into_iter().flatten().collect()
+                };
+                self.cur.expect_sym(";")?;
+                self.emit_op(Operation::Barrier(qubits));
+                Ok(())
+            }
+            "for" => self.parse_for(),
+            "if" => self.parse_if(),
+            name if self.cregs.contains_key(name) => {
+                // `c = measure q;` or `c[i] = measure q[j];`
+                let clbits = self.parse_operand(false)?;
+                self.cur.expect_sym("=")?;
+                self.cur.expect_keyword("measure")?;
+                let qubits = self.parse_operand(true)?;
+                self.cur.expect_sym(";")?;
+                self.emit_measure(qubits, clbits, line, col)
+            }
+            _ => self.parse_gate_call(line, col),
+        }
+    }
+
+    fn declare(&mut self, name: String, size: usize, quantum: bool, (line, col): (usize, usize)) -> Result<(), QasmError> {
+        if self.qregs.contains_key(&name) || self.cregs.contains_key(&name) {
+            return Err(QasmError::new(line, col, format!("'{}' is already declared", name)));
+        }
+        if quantum {
+            self.qregs.insert(name, Register { start: self.num_qubits, size });
+            self.num_qubits += size;
+        } else {
+            self.cregs.insert(name, Register { start: self.num_clbits, size });
+            self.num_clbits += size;
+        }
+        Ok(())
+    }
+
+    /// Skips a classical type such as `int`, `uint[32]` or `float[64]`.
+    fn skip_type(&mut self) -> Result<(), QasmError> {
+        self.cur.expect_ident()?;
+        if self.cur.eat_sym("[") {
+            parse_expr(&mut self.cur)?;
+            self.cur.expect_sym("]")?;
+        }
+        Ok(())
+    }
+
+    /// `for [type] i in [start:end]` / `[start:step:end]` / `{a, b, ...}`, unrolled.
+    fn parse_for(&mut self) -> Result<(), QasmError> {
+        self.cur.expect_keyword("for")?;
+        let first = self.cur.expect_ident()?;
+        let var = if self.cur.eat_sym("[") {
+            parse_expr(&mut self.cur)?;
+            self.cur.expect_sym("]")?;
+            self.cur.expect_ident()?
+        } else if !self.cur.is_ident("in") {
+            self.cur.expect_ident()?
+        } else {
+            first
+        };
+        self.cur.expect_keyword("in")?;
+
+        let (line, col) = self.cur.here();
+        let values: Vec<f64> = if self.cur.eat_sym("{") {
+            let mut values = vec![parse_expr(&mut self.cur)?.eval(&self.env)?];
+            while self.cur.eat_sym(",") {
+                values.push(parse_expr(&mut self.cur)?.eval(&self.env)?);
+            }
+            self.cur.expect_sym("}")?;
+            values
+        } else {
+            self.cur.expect_sym("[")?;
+            let start = parse_expr(&mut self.cur)?.eval(&self.env)?;
+            self.cur.expect_sym(":")?;
+            let mut end = parse_expr(&mut self.cur)?.eval(&self.env)?;
+            let mut step = 1.0;
+            if self.cur.eat_sym(":") {
+                step = end;
+                end = parse_expr(&mut self.cur)?.eval(&self.env)?;
+            }
+            self.cur.expect_sym("]")?;
+            if step == 0.0 {
+                return Err(QasmError::new(line, col, "range step cannot be zero"));
+            }
+            // Ranges are inclusive of the end in OpenQASM 3.
+            let mut values = Vec::new();
+            let mut v = start;
+            while (step > 0.0 && v <= end) || (step < 0.0 && v >= end) {
+                values.push(v);
+                v += step;
+            }
+            values
+        };
+
+        if values.is_empty() {
+            return self.skip_statement();
+        }
+        let body_start = self.cur.position();
+        let shadowed = self.env.get(&var).cloned();
+        for v in values {
+            self.cur.seek(body_start);
+            self.env.insert(var.clone(), v);
+            self.parse_statement()?;
+        }
+        match shadowed {
+            Some(v) => self.env.insert(var, v),
+            None => self.env.remove(&var),
+        };
+        Ok(())
+    }
+
+    /// Moves past one statement without evaluating it, for the body of a loop that never
+    /// runs: its expressions may only make sense for values the loop variable never takes.
+    fn skip_statement(&mut self) -> Result<(), QasmError> {
+        if self.cur.is_ident("if") {
+            self.cur.next();
+            self.skip_group()?;
+            self.skip_statement()?;
+            if self.cur.is_ident("else") {
+                self.cur.next();
+                self.skip_statement()?;
+            }
+            return Ok(());
+        }
+        if self.cur.is_ident("for") {
+            while !self.cur.is_ident("in") {
+                if self.cur.at_eof() {
+                    return Err(self.cur.error("expected 'in', found end of file"));
+                }
+                self.cur.next();
+            }
+            self.cur.next();
+            self.skip_group()?;
+            return self.skip_statement();
+        }
+        // Anything else ends at a `;`, or at the brace closing a block or gate body.
+        let mut depth = 0;
+        loop {
+            if self.cur.at_eof() {
+                return Err(self.cur.error("expected ';', found end of file"));
+            }
+            match self.cur.next() {
+                Tok::Sym("(" | "[" | "{") => depth += 1,
+                Tok::Sym(")" | "]") => depth -= 1,
+                Tok::Sym("}") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Tok::Sym(";") if depth == 0 => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Skips a bracketed group such as `(c == 1)` or `[0:n]`, nested groups included.
+    fn skip_group(&mut self) -> Result<(), QasmError> {
+        if !(self.cur.is_sym("(") || self.cur.is_sym("[") || self.cur.is_sym("{")) {
+            return Err(self.cur.error(format!("expected '(', '[' or '{{', found {}", describe(self.cur.peek()))));
+        }
+        let mut depth = 0;
+        loop {
+            if self.cur.at_eof() {
+                return Err(self.cur.error("unclosed bracket at end of file"));
+            }
+            match self.cur.next() {
+                Tok::Sym("(" | "[" | "{") => depth += 1,
+                Tok::Sym(")" | "]" | "}") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<(), QasmError> {
+        self.cur.expect_keyword("if")?;
+        self.cur.expect_sym("(")?;
+        let condition = self.parse_condition()?;
+        self.cur.expect_sym(")")?;
+        self.conditions.push(condition.clone());
+        self.parse_statement()?;
+        self.conditions.pop();
+        if self.cur.is_ident("else") {
+            self.cur.next();
+            self.conditions.push(condition.negate());
+            self.parse_statement()?;
+            self.conditions.pop();
+        }
+        Ok(())
+    }
+
+    /// `a && b`, `!a`, `(a)`, `c == 3`, `c[0] != 1`, or a bare `c[0]`.
+    fn parse_condition(&mut self) -> Result<Condition, QasmError> {
+        let mut cond = self.parse_condition_atom()?;
+        while self.cur.eat_sym("&&") {
+            cond = cond.and(self.parse_condition_atom()?);
+        }
+        Ok(cond)
+    }
+
+    fn parse_condition_atom(&mut self) -> Result<Condition, QasmError> {
+        if self.cur.eat_sym("!") {
+            return Ok(self.parse_condition_atom()?.negate());
+        }
+        if self.cur.eat_sym("(") {
+            let cond = self.parse_condition()?;
+            self.cur.expect_sym(")")?;
+            return Ok(cond);
+        }
+        let clbits = self.parse_operand(false)?;
+        let equal = if self.cur.eat_sym("==") {
+            true
+        } else if self.cur.eat_sym("!=") {
+            false
+        } else {
+            // A bare bit tests for 1, a bare register for non-zero.
+            return Ok(if clbits.len() == 1 {
+                Condition::new(&clbits, 1)
+            } else {
+                Condition::new(&clbits, 0).negate()
+            });
+        };
+        let value = match self.cur.next() {
+            Tok::Int(v) => v,
+            Tok::Ident(b) if b == "true" => 1,
+            Tok::Ident(b) if b == "false" => 0,
+            other => return Err(self.cur.error(format!("expected integer, found {}", describe(&other)))),
+        };
+        let cond = Condition::new(&clbits, value);
+        Ok(if equal { cond } else { cond.negate() })
+    }
+
+    fn parse_gate_call(&mut self, line: usize, col: usize) -> Result<(), QasmError> {
+        let modifiers = parse_modifiers(&mut self.cur)?;
+        let modifiers = modifiers.iter().map(|m| m.eval(&self.env)).collect::<Result<Vec<_>, _>>()?;
+        let (name_line, name_col) = self.cur.here();
+        let name = self.cur.expect_ident()?;
+        if !self.gates.contains(&name) {
+            return Err(QasmError::new(name_line, name_col, format!("undefined gate '{}'", name)));
+        }
+        let mut params = Vec::new();
+        if self.cur.eat_sym("(") {
+            if !self.cur.is_sym(")") {
+                params.push(parse_expr(&mut self.cur)?.eval(&self.env)?);
+                while self.cur.eat_sym(",") {
+                    params.push(parse_expr(&mut self.cur)?.eval(&self.env)?);
+                }
+            }
+            self.cur.expect_sym(")")?;
+        }
+        let operands = if self.cur.is_sym(";") { Vec::new() } else { self.parse_operand_list()? };
+        self.cur.expect_sym(";")?;
+
+        let mut ops = Vec::new();
+        let calls = if operands.is_empty() {
+            vec![Vec::new()]
+        } else {
+            broadcast(&operands).ok_or_else(|| QasmError::new(line, col, "register operands have different sizes"))?
+        };
+        for qubits in calls {
+            self.gates.expand_with_modifiers(&modifiers, &name, &params, &qubits, line, col, &mut ops)?;
+        }
+        for op in ops {
+            self.emit_op(op);
+        }
+        Ok(())
+    }
+
+    fn emit_measure(&mut self, qubits: Operand, clbits: Operand, line: usize, col: usize) -> Result<(), QasmError> {
+        if qubits.len() != clbits.len() {
+            return Err(QasmError::new(line, col, "measure operands have different sizes"));
+        }
+        for (qubit, clbit) in qubits.into_iter().zip(clbits) {
+            self.emit_op(Operation::Measure { qubit, clbit });
+        }
+        Ok(())
+    }
+
+    fn emit_op(&mut self, op: Operation) {
+        let condition = self.conditions.iter().cloned().reduce(|a, b| a.and(b));
+        self.instructions.push(Instruction { op, condition });
+    }
+
+    fn parse_operand_list(&mut self) -> Result<Vec<Operand>, QasmError> {
+        let mut operands = vec![self.parse_operand(true)?];
+        while self.cur.eat_sym(",") {
+            operands.push(self.parse_operand(true)?);
+        }
+        Ok(operands)
+    }
+
+    /// Parses `name`, `name[index]` or `name[start:end]` (inclusive) with constant
+    /// expressions as indices.
+    fn parse_operand(&mut self, quantum: bool) -> Result<Operand, QasmError> {
+        let (line, col) = self.cur.here();
+        let name = self.cur.expect_ident()?;
+        let regs = if quantum { &self.qregs } else { &self.cregs };
+        let reg = *regs.get(&name).ok_or_else(|| {
+            let kind = if quantum { "qubit" } else { "bit" };
+            QasmError::new(line, col, format!("unknown {} register '{}'", kind, name))
+        })?;
+        if !self.cur.eat_sym("[") {
+            return Ok((reg.start..reg.start + reg.size).collect());
+        }
+        let (line, col) = self.cur.here();
+        let first = self.parse_index()?;
+        let last = if self.cur.eat_sym(":") { self.parse_index()? } else { first };
+        self.cur.expect_sym("]")?;
+        if first > last || last >= reg.size {
+            return Err(QasmError::new(line, col, format!("index {} out of range for '{}[{}]'", last, name, reg.size)));
+        }
+        Ok((reg.start + first..=reg.start + last).collect())
+    }
+
+    fn parse_index(&mut self) -> Result<usize, QasmError> {
+        let (line, col) = self.cur.here();
+        let expr: Expr = parse_expr(&mut self.cur)?;
+        let v = expr.eval(&self.env)?;
+        if v < 0.0 || v.fract() != 0.0 {
+            return Err(QasmError::new(line, col, format!("expected a non-negative integer, got {}", v)));
+        }
+        Ok(v as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gate, QuantumCircuit};
+
+    fn ops(circ: &Circuit) -> Vec<Operation> {
+        circ.instructions.iter().map(|i| i.op.clone()).collect()
+    }
+
+    #[test]
+    fn test_declarations_loop_and_measure() {
+        let src = r#"
+            OPENQASM 3.0;
+            include "stdgates.inc";
+            qubit[3] q;
+            bit[3] c;
+            h q[0];
+            for int i in [0:1] {
+                cx q[i], q[i + 1];
+            }
+            c = measure q;
+        "#;
+        let circ = parse_qasm3(src).unwrap();
+        assert_eq!((circ.num_qubits, circ.num_clbits), (3, 3));
+        assert_eq!(ops(&circ)[..3], [
+            Operation::Gate { gate: Gate::H, qubits: vec![0] },
+            Operation::Gate { gate: Gate::CX, qubits: vec![0, 1] },
+            Operation::Gate { gate: Gate::CX, qubits: vec![1, 2] },
+        ]);
+        assert_eq!(ops(&circ)[5], Operation::Measure { qubit: 2, clbit: 2 });
+    }
+
+    #[test]
+    fn test_empty_loop_is_not_evaluated() {
+        // `i - 1` would be -1 in the body, but the body never runs.
+        let src = r#"
+            OPENQASM 3;
+            include "stdgates.inc";
+            qubit[2] q;
+            bit[1] c;
+            for int i in [1:0] { x q[i - 1]; }
+            for uint[8] i in [2:-1:3] {
+                if (c[0] == 1) { x q[i - 3]; } else h q[i - 3];
+                for j in [0:i] { cx q[j], q[i - j]; }
+            }
+            h q[1];
+        "#;
+        let circ = parse_qasm3(src).unwrap();
+        assert_eq!(ops(&circ), [Operation::Gate { gate: Gate::H, qubits: vec![1] }]);
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let src = r#"
+            OPENQASM 3;
+            include "stdgates.inc";
+            qubit[3] q;
+            ctrl @ x q[0], q[1];
+            negctrl @ ctrl @ h q[0], q[1], q[2];
+            inv @ s q[2];
+            pow(2) @ t q[1];
+            pow(0.5) @ z q[0];
+            ctrl(2) @ x q[2], q[1], q[0];
+        "#;
+        let circ = parse_qasm3(src).unwrap();
+        assert_eq!(ops(&circ), vec![
+            Operation::Gate { gate: Gate::CX, qubits: vec![0, 1] },
+            Operation::Controlled { gate: Gate::H, controls: vec![1], neg_controls: vec![0], target: 2 },
+            Operation::Gate { gate: Gate::Sdg, qubits: vec![2] },
+            Operation::Gate { gate: Gate::T, qubits: vec![1] },
+            Operation::Gate { gate: Gate::T, qubits: vec![1] },
+            Operation::Gate { gate: Gate::P(std::f64::consts::FRAC_PI_2), qubits: vec![0] },
+            Operation::Controlled { gate: Gate::X, controls: vec![2, 1], neg_controls: vec![], target: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_if_else_on_measurement() {
+        let src = r#"
+            OPENQASM 3;
+            include "stdgates.inc";
+            qubit[2] q;
+            bit[2] c;
+            x q[0];
+            c[0] = measure q[0];
+            if (c[0] == 1) { x q[1]; } else { h q[1]; }
+            reset q[0];
+            c[1] = measure q[1];
+        "#;
+        let circ = parse_qasm3(src).unwrap();
+        assert_eq!(circ.instructions[2].condition, Some(Condition::new(&[0], 1)));
+        assert_eq!(circ.instructions[3].condition, Some(Condition::new(&[0], 1).negate()));
+
+        let mut sim = QuantumCircuit::new(2);
+        assert_eq!(sim.run(&circ), vec![true, true]);
+    }
+
+    #[test]
+    fn test_ccx_runs_through_controlled_gate() {
+        let src = "OPENQASM 3; include \"stdgates.inc\"; qubit[3] q; x q[0]; x q[1]; ccx q[0], q[1], q[2];";
+        let circ = parse_qasm3(src).unwrap();
+        let mut sim = QuantumCircuit::new(3);
+        sim.run(&circ);
+        let state = sim.get_state_vector();
+        assert!((state[0b111].re - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_errors_carry_position() {
+        let err = parse_qasm3("OPENQASM 3;\nqubit[2] q;\nfor i in [0:2] { h q[i]; }").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("undefined gate 'h'"));
+
+        let err = parse_qasm3("include \"stdgates.inc\";\nqubit[2] q;\npow(0.5) @ h q[0];").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 1));
+    }
+}
@@ -0,0 +1,232 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use crate::{DdNode, NodePtr, QuantumCircuit};
+use crate::constants::ZERO;
+use super::measure::subtree_norm_sqr;
+
+/// When `run` should approximate the state on its own; see
+/// `QuantumCircuit::set_approximation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApproximationPolicy {
+    /// Approximate whenever the diagram has more non-terminal nodes than this.
+    pub max_nodes: usize,
+    /// Skip any approximation that would bring the cumulative fidelity below this.
+    pub min_fidelity: f64,
+    /// Passed to `approximate`: edges carrying less of the norm than this are pruned.
+    pub threshold: f64,
+}
+
+type NodeKey = *const RefCell<DdNode>;
+
+impl QuantumCircuit {
+    /// Prunes every edge whose contribution to the squared norm is below `threshold`,
+    /// renormalizes, and returns the fidelity |⟨ψ|ψ'⟩|² between the old and new state.
+    ///
+    /// The contribution of an edge is the total probability of the basis states whose
+    /// paths go through it. Pruning removes exactly those paths, so the fidelity is the
+    /// probability that is kept, and sub-diagrams only reachable through pruned edges
+    /// are dropped. The result is also folded into `approximation_fidelity()`.
+    pub fn approximate(&mut self, threshold: f64) -> f64 {
+        let (pruned, fidelity) = self.plan_pruning(threshold);
+        self.prune(&pruned, fidelity);
+        fidelity
+    }
+
+    /// Estimated fidelity of the current state with the exact one: the product of the
+    /// fidelities of every approximation so far, 1 if there was none.
+    pub fn approximation_fidelity(&self) -> f64 {
+        self.fidelity
+    }
+
+    /// Makes `run` call `approximate` after any step that leaves the diagram larger than
+    /// `policy.max_nodes`, as long as the cumulative fidelity stays at or above
+    /// `policy.min_fidelity`. `None` switches it off again.
+    pub fn set_approximation(&mut self, policy: Option<ApproximationPolicy>) {
+        self.approximation = policy;
+    }
+
+    /// Applies the approximation policy, if any, to the current state.
+    pub(crate) fn approximate_if_needed(&mut self) {
+        let Some(policy) = self.approximation else {
+            return;
+        };
+        if self.count_nodes().1 <= policy.max_nodes {
+            return;
+        }
+        let (pruned, fidelity) = self.plan_pruning(policy.threshold);
+        if self.fidelity * fidelity >= policy.min_fidelity {
+            self.prune(&pruned, fidelity);
+        }
+    }
+
+    /// The arms to prune for `threshold` and the fidelity left after pruning them.
+    fn plan_pruning(&self, threshold: f64) -> (Vec<(NodePtr, bool)>, f64) {
+        let mut norms = HashMap::new();
+        let total = subtree_norm_sqr(&self.root, &mut norms);
+
+        // Visit nodes from the root down, so every incoming path is counted before a
+        // node passes its probability mass on to its children.
+        let mut nodes = Vec::new();
+        collect(&self.root, &mut HashSet::new(), &mut nodes);
+        nodes.sort_by_key(|node| match &*node.borrow() {
+            DdNode::NonTerminal { qubit, .. } => std::cmp::Reverse(*qubit),
+            DdNode::Terminal(_) => std::cmp::Reverse(0),
+        });
+        let mut mass: HashMap<NodeKey, f64> = HashMap::from([(Rc::as_ptr(&self.root), 1.0)]);
+        let mut pruned = Vec::new();
+        let mut pruned_keys = HashSet::new();
+        for node in &nodes {
+            let m = mass.get(&Rc::as_ptr(node)).copied().unwrap_or(0.0);
+            if let DdNode::NonTerminal { zero, one, .. } = &*node.borrow() {
+                for (arm, (weight, child)) in [(false, zero), (true, one)] {
+                    let norm = norms.get(&Rc::as_ptr(child)).copied().unwrap_or(0.0);
+                    let contribution = m * weight.norm_sqr() * norm;
+                    if contribution == 0.0 {
+                        continue;
+                    }
+                    if contribution < threshold * total {
+                        pruned.push((node.clone(), arm));
+                        pruned_keys.insert((Rc::as_ptr(node), arm));
+                    } else {
+                        *mass.entry(Rc::as_ptr(child)).or_insert(0.0) += m * weight.norm_sqr();
+                    }
+                }
+            }
+        }
+
+        let kept = kept_norm_sqr(&self.root, &pruned_keys, &mut HashMap::new());
+        if kept <= 0.0 {
+            // Everything would be pruned; keep the state as it is.
+            return (Vec::new(), 1.0);
+        }
+        (pruned, kept / total)
+    }
+
+    fn prune(&mut self, pruned: &[(NodePtr, bool)], fidelity: f64) {
+        if pruned.is_empty() {
+            return;
+        }
+        for (node, arm) in pruned {
+            if let DdNode::NonTerminal { zero, one, .. } = &mut *node.borrow_mut() {
+                let terminal = Rc::new(RefCell::new(DdNode::Terminal(ZERO)));
+                if *arm { *one = (ZERO, terminal) } else { *zero = (ZERO, terminal) }
+            }
+        }
+
+        self.normalize();
+        self.fidelity *= fidelity;
+    }
+}
+
+fn collect(node: &NodePtr, visited: &mut HashSet<NodeKey>, nodes: &mut Vec<NodePtr>) {
+    if !visited.insert(Rc::as_ptr(node)) {
+        return;
+    }
+    if let DdNode::NonTerminal { qubit, zero, one } = &*node.borrow() {
+        if *qubit == usize::MAX {
+            return;
+        }
+        nodes.push(node.clone());
+        collect(&zero.1, visited, nodes);
+        collect(&one.1, visited, nodes);
+    }
+}
+
+/// Like `subtree_norm_sqr`, but treating the `pruned` arms as zero.
+fn kept_norm_sqr(node: &NodePtr, pruned: &HashSet<(NodeKey, bool)>, memo: &mut HashMap<NodeKey, f64>) -> f64 {
+    let key = Rc::as_ptr(node);
+    if let Some(n) = memo.get(&key) {
+        return *n;
+    }
+    let norm = match &*node.borrow() {
+        DdNode::Terminal(_) => 0.0,
+        DdNode::NonTerminal { qubit, .. } if *qubit == usize::MAX => 1.0,
+        DdNode::NonTerminal { zero, one, .. } => {
+            let mut norm = 0.0;
+            for (arm, (weight, child)) in [(false, zero), (true, one)] {
+                if !pruned.contains(&(key, arm)) {
+                    norm += weight.norm_sqr() * kept_norm_sqr(child, pruned, memo);
+                }
+            }
+            norm
+        }
+    };
+    memo.insert(key, norm);
+    norm
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use crate::{ApproximationPolicy, Circuit, Gate, QuantumCircuit};
+
+    /// Every qubit slightly rotated away from |0⟩, entangled with its neighbour.
+    fn nearly_zero(n: usize, theta: f64) -> Circuit {
+        let mut circ = Circuit::new(n, 0);
+        for q in 0..n {
+            circ.gate(Gate::U(theta, 0.0, 0.0), &[q]);
+        }
+        for q in 0..n - 1 {
+            circ.cx(q, q + 1);
+        }
+        circ
+    }
+
+    #[test]
+    fn test_approximate_prunes_small_branch() {
+        // cos(0.1)|000⟩ + sin(0.1)|111⟩: the |1⟩ arm of the root carries sin²(0.1).
+        let mut sim = QuantumCircuit::new(3);
+        sim.apply_unitary(2, Gate::U(0.2, 0.0, 0.0).matrix().unwrap());
+        sim.apply_mcu(&[2], &[], 1, Gate::X.matrix().unwrap());
+        sim.apply_mcu(&[2], &[], 0, Gate::X.matrix().unwrap());
+        let before = sim.count_nodes().1;
+
+        let fidelity = sim.approximate(0.05);
+        let expected = (0.1f64).cos().powi(2);
+        assert!(approx_eq!(f64, fidelity, expected, epsilon = 1e-9));
+        assert!(approx_eq!(f64, sim.approximation_fidelity(), expected, epsilon = 1e-9));
+        assert!(sim.count_nodes().1 < before);
+
+        let state = sim.get_state_vector();
+        assert!(approx_eq!(f64, state[0].norm(), 1.0, epsilon = 1e-9));
+        assert!(state[7].norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_approximate_keeps_exact_state_below_threshold() {
+        let mut sim = QuantumCircuit::new(2);
+        sim.apply_unitary(1, Gate::H.matrix().unwrap());
+        sim.apply_mcu(&[1], &[], 0, Gate::X.matrix().unwrap());
+        let expected = sim.get_state_vector();
+        assert_eq!(sim.approximate(0.1), 1.0);
+        assert_eq!(sim.get_state_vector(), expected);
+    }
+
+    #[test]
+    fn test_policy_respects_fidelity_bound() {
+        let circ = nearly_zero(6, 0.3);
+        let mut exact = QuantumCircuit::new(6);
+        exact.run(&circ);
+
+        let mut sim = QuantumCircuit::new(6);
+        sim.set_approximation(Some(ApproximationPolicy { max_nodes: 8, min_fidelity: 0.9, threshold: 0.01 }));
+        sim.run(&circ);
+        assert!(sim.approximation_fidelity() < 1.0 && sim.approximation_fidelity() >= 0.9);
+        assert!(sim.count_nodes().1 <= exact.count_nodes().1);
+        let support = |s: &QuantumCircuit| s.get_state_vector().iter().filter(|a| a.norm() > 1e-12).count();
+        assert!(support(&sim) < support(&exact));
+
+        // The overlap with the exact state is at least the tracked estimate.
+        let overlap: num::complex::Complex64 = exact.get_state_vector().iter()
+            .zip(sim.get_state_vector())
+            .map(|(a, b)| a.conj() * b)
+            .sum();
+        assert!(overlap.norm_sqr() >= sim.approximation_fidelity() - 1e-9);
+
+        let mut strict = QuantumCircuit::new(6);
+        strict.set_approximation(Some(ApproximationPolicy { max_nodes: 8, min_fidelity: 0.9999, threshold: 0.01 }));
+        strict.run(&circ);
+        assert_eq!(strict.approximation_fidelity(), 1.0);
+    }
+}
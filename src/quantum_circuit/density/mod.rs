@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use num::complex::Complex64;
+use crate::{NodePtr, QuantumCircuit};
+use crate::constants::{ONE, ZERO};
+use crate::gates::apply_mcu::children_at;
+use super::inner_product::PairKey;
+
+impl QuantumCircuit {
+    /// The reduced density matrix of `qubits`, tracing out all other qubits. Bit `k` of
+    /// the row and column index is the value of `qubits[k]`. The matrix has 4^m entries
+    /// for m listed qubits, so this is meant for small subsystems.
+    ///
+    /// The diagram is contracted with its own conjugate: traced levels sum the matching
+    /// arms and kept levels spread the four arm pairs over the matrix, one matrix per
+    /// pair of nodes.
+    pub fn reduced_density_matrix(&self, qubits: &[usize]) -> Vec<Vec<Complex64>> {
+        let mut positions = vec![None; self.num_qubits];
+        for (k, &qubit) in qubits.iter().enumerate() {
+            assert!(qubit < self.num_qubits, "Qubit {} out of range for {} qubits", qubit, self.num_qubits);
+            let level = self.level_of(qubit);
+            assert!(positions[level].is_none(), "Qubit {} listed twice", qubit);
+            positions[level] = Some(k);
+        }
+        let dim = 1 << qubits.len();
+        let rho = contract(&self.root, &self.root, self.num_qubits, &positions, dim, &mut HashMap::new());
+        let trace: f64 = (0..dim).map(|i| rho[i * dim + i].re).sum();
+        assert!(trace > 0.0, "Cannot take the reduced state of the zero state");
+        rho.chunks(dim).map(|row| row.iter().map(|x| x / trace).collect()).collect()
+    }
+}
+
+/// The unnormalized matrix Σ_env |a⟩⟨b| over the sub-diagrams below level `height`,
+/// flattened row-major with side `dim`. `positions[l]` is the index bit of the kept qubit
+/// at level `l`, if any. A level that an edge skips has the same sub-diagram on both arms.
+fn contract(
+    a: &NodePtr,
+    b: &NodePtr,
+    height: usize,
+    positions: &[Option<usize>],
+    dim: usize,
+    memo: &mut HashMap<(PairKey, usize), Rc<Vec<Complex64>>>,
+) -> Rc<Vec<Complex64>> {
+    let key = ((Rc::as_ptr(a), Rc::as_ptr(b)), height);
+    if let Some(rho) = memo.get(&key) {
+        return rho.clone();
+    }
+    let mut rho = vec![ZERO; dim * dim];
+    if height == 0 {
+        // Both are the sink: zero edges are never followed.
+        rho[0] = ONE;
+    } else {
+        let level = height - 1;
+        let (a0, a1) = children_at(&(ONE, a.clone()), level);
+        let (b0, b1) = children_at(&(ONE, b.clone()), level);
+        for (x, ea) in [(0, &a0), (1, &a1)] {
+            for (y, eb) in [(0, &b0), (1, &b1)] {
+                let weight = ea.0 * eb.0.conj();
+                let (row, col) = match positions[level] {
+                    Some(k) => (x << k, y << k),
+                    // A traced level only pairs matching arms.
+                    None if x != y => continue,
+                    None => (0, 0),
+                };
+                if weight == ZERO {
+                    continue;
+                }
+                let sub = contract(&ea.1, &eb.1, level, positions, dim, memo);
+                for (i, value) in sub.iter().enumerate() {
+                    if *value != ZERO {
+                        rho[((i / dim) | row) * dim + ((i % dim) | col)] += weight * value;
+                    }
+                }
+            }
+        }
+    }
+    let rho = Rc::new(rho);
+    memo.insert(key, rho.clone());
+    rho
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use num::complex::Complex64;
+    use crate::{Circuit, DdNode, QuantumCircuit};
+    use crate::constants::ZERO;
+    use crate::gates::apply_mcu::{zero_edge, Edge};
+    use crate::quantum_circuit::new::new_sink;
+
+    fn c(re: f64, im: f64) -> Complex64 {
+        Complex64::new(re, im)
+    }
+
+    /// Reduced state of `qubits` from a dense vector, normalized by its trace.
+    fn dense_reduced(state: &[Complex64], qubits: &[usize]) -> Vec<Vec<Complex64>> {
+        let dim = 1 << qubits.len();
+        let mut rho = vec![vec![ZERO; dim]; dim];
+        let index = |i: usize| -> usize { qubits.iter().enumerate().map(|(k, q)| (i >> q & 1) << k).sum() };
+        let mask: usize = qubits.iter().map(|q| 1 << q).sum();
+        for (i, x) in state.iter().enumerate() {
+            for (j, y) in state.iter().enumerate() {
+                if i & !mask == j & !mask {
+                    rho[index(i)][index(j)] += x * y.conj();
+                }
+            }
+        }
+        let trace: f64 = (0..dim).map(|i| rho[i][i].re).sum();
+        rho.iter().map(|row| row.iter().map(|x| x / trace).collect()).collect()
+    }
+
+    fn assert_matrix_close(rho: &[Vec<Complex64>], expected: &[Vec<Complex64>], qubits: &[usize]) {
+        assert_eq!(rho.len(), expected.len());
+        for (row, expected_row) in rho.iter().zip(expected) {
+            for (x, y) in row.iter().zip(expected_row) {
+                assert!((x - y).norm() < 1e-12, "{:?}: {} != {}", qubits, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reduced_state_of_bell_pair() {
+        let mut bell = QuantumCircuit::new(3);
+        bell.run(Circuit::new(3, 0).h(0).cx(0, 2).x(1));
+        let rho = bell.reduced_density_matrix(&[0]);
+        assert!((rho[0][0].re - 0.5).abs() < 1e-12 && (rho[1][1].re - 0.5).abs() < 1e-12);
+        assert!(rho[0][1].norm() < 1e-12);
+
+        let both = bell.reduced_density_matrix(&[2, 0]);
+        for (i, j) in [(0, 0), (0, 3), (3, 0), (3, 3)] {
+            assert!((both[i][j].re - 0.5).abs() < 1e-12);
+        }
+        let flipped = bell.reduced_density_matrix(&[1]);
+        assert!((flipped[1][1].re - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_shared_nodes() {
+        // In a Dicke state every node below the root has several parents, so each node
+        // pair's matrix is reused; a phase on one branch makes the coherences complex.
+        let mut sim = QuantumCircuit::dicke(5, 2);
+        sim.apply_s(3);
+        let state = sim.get_state_vector();
+        for qubits in [vec![4], vec![0, 3], vec![3, 1, 4], vec![0, 1, 2, 3, 4]] {
+            assert_matrix_close(&sim.reduced_density_matrix(&qubits), &dense_reduced(&state, &qubits), &qubits);
+        }
+        // One qubit of D(5, 2) is |1⟩ with probability 2/5 and has no coherence.
+        let single = sim.reduced_density_matrix(&[2]);
+        assert!((single[1][1].re - 0.4).abs() < 1e-12 && single[0][1].norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_edges_that_skip_levels() {
+        // The root's |0⟩ arm goes straight to the sink, skipping levels 1 and 0, so all
+        // four of their basis states get amplitude 0.5 there.
+        let sink = new_sink();
+        let node = |qubit, zero: Edge, one: Edge| Rc::new(RefCell::new(DdNode::NonTerminal { qubit, zero, one }));
+        let low = node(0, (c(0.6, 0.0), sink.clone()), (c(0.0, 0.8), sink.clone()));
+        let mid = node(1, (c(1.0, 0.0), low), zero_edge());
+        let root = node(2, (c(0.5, 0.0), sink), (c(0.5, 0.0), mid));
+        let sim = QuantumCircuit::from_root(root, 3);
+        let state = [c(0.5, 0.0), c(0.5, 0.0), c(0.5, 0.0), c(0.5, 0.0), c(0.3, 0.0), c(0.0, 0.4), ZERO, ZERO];
+        for qubits in [vec![], vec![0], vec![1], vec![2], vec![2, 0], vec![1, 2], vec![0, 1, 2]] {
+            assert_matrix_close(&sim.reduced_density_matrix(&qubits), &dense_reduced(&state, &qubits), &qubits);
+        }
+    }
+}
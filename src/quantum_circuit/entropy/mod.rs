@@ -0,0 +1,300 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::rc::Rc;
+use num::complex::Complex64;
+use crate::{DdNode, NodePtr, Pauli, QuantumCircuit};
+use crate::constants::{ONE, ZERO};
+use super::inner_product::{pair_product, PairKey};
+
+/// Entanglement between a set of qubits and the rest of the register, from
+/// `QuantumCircuit::entanglement_entropy`. Entropies are in bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntanglementEntropy {
+    /// -Σ λ log2 λ over the Schmidt spectrum.
+    pub von_neumann: f64,
+    /// -log2 Σ λ², the Rényi-2 entropy.
+    pub renyi2: f64,
+    /// An upper bound on the Schmidt rank: 2^min(|cut|, n - |cut|), or the number of
+    /// distinct nodes just below the cut if that is smaller and the cut splits the levels
+    /// into a top and a bottom part.
+    pub schmidt_rank_bound: usize,
+}
+
+impl QuantumCircuit {
+    /// The entanglement entropy between the qubits in `cut` and the rest.
+    ///
+    /// If the cut is aligned with the level order (see `reorder`), the state is
+    /// Σ_i |α_i⟩|v_i⟩ over the r distinct nodes v_i just below the cut, and the spectrum
+    /// comes from two r×r Gram matrices built on the diagram. Other cuts fall back to the
+    /// reduced density matrix of the smaller side, which is only feasible when that side
+    /// is small.
+    pub fn entanglement_entropy(&self, cut: &[usize]) -> EntanglementEntropy {
+        let mut in_cut = vec![false; self.num_qubits];
+        for &qubit in cut {
+            assert!(qubit < self.num_qubits, "Qubit {} out of range for {} qubits", qubit, self.num_qubits);
+            assert!(!in_cut[qubit], "Qubit {} listed twice", qubit);
+            in_cut[qubit] = true;
+        }
+        let size = cut.len().min(self.num_qubits - cut.len());
+        if size == 0 {
+            return EntanglementEntropy { von_neumann: 0.0, renyi2: 0.0, schmidt_rank_bound: 1 };
+        }
+
+        // The cut is aligned if its levels, or those of the rest, are the lowest ones.
+        let bottom = cut.iter().map(|&q| self.level_of(q)).max().unwrap() + 1;
+        let bottom = if bottom == cut.len() {
+            Some(bottom)
+        } else {
+            let rest = (0..self.num_qubits).filter(|&q| !in_cut[q]).map(|q| self.level_of(q)).max().unwrap() + 1;
+            (rest == self.num_qubits - cut.len()).then_some(rest)
+        };
+        match bottom {
+            Some(bottom) => self.aligned_entropy(bottom, 1 << size),
+            None => {
+                let smaller: Vec<usize> = if cut.len() <= self.num_qubits - cut.len() {
+                    cut.to_vec()
+                } else {
+                    (0..self.num_qubits).filter(|&q| !in_cut[q]).collect()
+                };
+                let rho = self.reduced_density_matrix(&smaller);
+                entropies(&hermitian_eigenvalues(&rho), 1 << size)
+            }
+        }
+    }
+
+    /// Entropy across the cut between levels `bottom - 1` and `bottom`, where the smaller
+    /// side has dimension `dim`.
+    fn aligned_entropy(&self, bottom: usize, dim: usize) -> EntanglementEntropy {
+        let mut index = HashMap::new();
+        let mut nodes = Vec::new();
+        collect_level(&self.root, bottom - 1, &mut index, &mut nodes);
+        let r = nodes.len();
+
+        // ⟨α_i|α_j⟩ over the part above the cut and ⟨v_i|v_j⟩ below it.
+        let top = top_gram(&self.root, &self.root, bottom - 1, &index, &mut HashMap::new());
+        let identity = vec![Pauli::I; self.num_qubits];
+        let mut memo = HashMap::new();
+        let mut below = vec![vec![ZERO; r]; r];
+        for i in 0..r {
+            for j in i..r {
+                below[i][j] = pair_product(&(ONE, nodes[i].clone()), &(ONE, nodes[j].clone()), bottom, &identity, &mut memo);
+                below[j][i] = below[i][j].conj();
+            }
+        }
+
+        // The reduced state of the lower part is V C V† with C_ij = ⟨α_j|α_i⟩ and
+        // V†V = G, so its spectrum is that of G^½ C G^½.
+        let c = embed(&(0..r).map(|i| (0..r).map(|j| top[j * r + i]).collect()).collect::<Vec<_>>());
+        let (values, vectors) = symmetric_eigen(embed(&below));
+        let n = 2 * r;
+        let sqrt_g: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| {
+            (0..n).map(|k| vectors[i][k] * values[k].max(0.0).sqrt() * vectors[j][k]).sum()
+        }).collect()).collect();
+        let product = multiply(&multiply(&sqrt_g, &c), &sqrt_g);
+        let (mut values, _) = symmetric_eigen(product);
+        values.sort_by(|a, b| b.total_cmp(a));
+        // The real embedding doubles every eigenvalue.
+        let spectrum: Vec<f64> = values.iter().step_by(2).copied().collect();
+        entropies(&spectrum, r.min(dim))
+    }
+}
+
+/// Distinct nodes at `level` reachable through nonzero edges, in the order found.
+fn collect_level(node: &NodePtr, level: usize, index: &mut HashMap<*const RefCell<DdNode>, usize>, nodes: &mut Vec<NodePtr>) {
+    if let DdNode::NonTerminal { qubit, zero, one } = &*node.borrow() {
+        if *qubit == level {
+            if let Entry::Vacant(entry) = index.entry(Rc::as_ptr(node)) {
+                entry.insert(nodes.len());
+                nodes.push(node.clone());
+            }
+            return;
+        }
+        for edge in [zero, one] {
+            if edge.0 != ZERO {
+                collect_level(&edge.1, level, index, nodes);
+            }
+        }
+    }
+}
+
+/// Σ over equally labelled paths from `a` and `b` down to cut nodes v_i and v_j of
+/// conj(weight a→v_i) · weight b→v_j, flattened row-major by (i, j).
+fn top_gram(
+    a: &NodePtr,
+    b: &NodePtr,
+    level: usize,
+    index: &HashMap<*const RefCell<DdNode>, usize>,
+    memo: &mut HashMap<PairKey, Rc<Vec<Complex64>>>,
+) -> Rc<Vec<Complex64>> {
+    let key = (Rc::as_ptr(a), Rc::as_ptr(b));
+    if let Some(gram) = memo.get(&key) {
+        return gram.clone();
+    }
+    let r = index.len();
+    let mut gram = vec![ZERO; r * r];
+    if let (DdNode::NonTerminal { qubit, zero: a0, one: a1 }, DdNode::NonTerminal { zero: b0, one: b1, .. }) = (&*a.borrow(), &*b.borrow()) {
+        if *qubit == level {
+            gram[index[&key.0] * r + index[&key.1]] = ONE;
+        } else {
+            for (ea, eb) in [(a0, b0), (a1, b1)] {
+                let weight = ea.0.conj() * eb.0;
+                if weight != ZERO {
+                    for (g, sub) in gram.iter_mut().zip(top_gram(&ea.1, &eb.1, level, index, memo).iter()) {
+                        *g += weight * sub;
+                    }
+                }
+            }
+        }
+    }
+    let gram = Rc::new(gram);
+    memo.insert(key, gram.clone());
+    gram
+}
+
+/// Entropies of an unnormalized spectrum.
+fn entropies(spectrum: &[f64], schmidt_rank_bound: usize) -> EntanglementEntropy {
+    let total: f64 = spectrum.iter().map(|l| l.max(0.0)).sum();
+    let probabilities: Vec<f64> = spectrum.iter().map(|l| l.max(0.0) / total).filter(|p| *p > 1e-14).collect();
+    EntanglementEntropy {
+        von_neumann: -probabilities.iter().map(|p| p * p.log2()).sum::<f64>(),
+        renyi2: -probabilities.iter().map(|p| p * p).sum::<f64>().log2(),
+        schmidt_rank_bound,
+    }
+}
+
+/// Eigenvalues of a Hermitian matrix, each once.
+fn hermitian_eigenvalues(matrix: &[Vec<Complex64>]) -> Vec<f64> {
+    let (mut values, _) = symmetric_eigen(embed(matrix));
+    values.sort_by(|a, b| b.total_cmp(a));
+    values.into_iter().step_by(2).collect()
+}
+
+/// The real symmetric matrix [[X, -Y], [Y, X]] of a Hermitian X + iY. It has the same
+/// eigenvalues, each twice.
+fn embed(matrix: &[Vec<Complex64>]) -> Vec<Vec<f64>> {
+    let n = matrix.len();
+    (0..2 * n).map(|i| (0..2 * n).map(|j| {
+        let x = matrix[i % n][j % n];
+        match (i < n, j < n) {
+            (true, true) | (false, false) => x.re,
+            (true, false) => -x.im,
+            (false, true) => x.im,
+        }
+    }).collect()).collect()
+}
+
+fn multiply(a: &[Vec<f64>], b: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = a.len();
+    (0..n).map(|i| (0..n).map(|j| (0..n).map(|k| a[i][k] * b[k][j]).sum()).collect()).collect()
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a real symmetric matrix, by cyclic
+/// Jacobi rotations.
+fn symmetric_eigen(mut a: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = a.len();
+    let mut v: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+    let scale: f64 = a.iter().flatten().map(|x| x * x).sum::<f64>().max(f64::MIN_POSITIVE);
+    for _ in 0..100 {
+        let off: f64 = (0..n).flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j))).map(|(i, j)| a[i][j] * a[i][j]).sum();
+        if off <= 1e-28 * scale {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut().chain(v.iter_mut()) {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+                let row_p: Vec<f64> = a[p].iter().zip(&a[q]).map(|(pk, qk)| c * pk - s * qk).collect();
+                let row_q: Vec<f64> = a[p].iter().zip(&a[q]).map(|(pk, qk)| s * pk + c * qk).collect();
+                a[p] = row_p;
+                a[q] = row_q;
+            }
+        }
+    }
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Circuit, QuantumCircuit};
+
+    #[test]
+    fn test_entropy_of_simple_states() {
+        let ghz = QuantumCircuit::ghz(5);
+        for cut in [vec![0], vec![4], vec![1, 3], vec![0, 1, 2]] {
+            let entropy = ghz.entanglement_entropy(&cut);
+            assert!((entropy.von_neumann - 1.0).abs() < 1e-9, "{:?}", cut);
+            assert!((entropy.renyi2 - 1.0).abs() < 1e-9, "{:?}", cut);
+        }
+        assert_eq!(ghz.entanglement_entropy(&[0, 1]).schmidt_rank_bound, 2);
+
+        let product = QuantumCircuit::uniform(4);
+        let entropy = product.entanglement_entropy(&[1, 2]);
+        assert!(entropy.von_neumann.abs() < 1e-9 && entropy.renyi2.abs() < 1e-9);
+        assert_eq!(product.entanglement_entropy(&[]).von_neumann, 0.0);
+
+        // Two Bell pairs across the cut carry two bits.
+        let mut bells = QuantumCircuit::new(4);
+        bells.run(Circuit::new(4, 0).h(0).cx(0, 2).h(1).cx(1, 3));
+        assert!((bells.entanglement_entropy(&[0, 1]).von_neumann - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cuts_at_every_level() {
+        // Pairs (0, 3), (1, 4) and (2, 5) hold cos(θ/2)|00⟩ + sin(θ/2)|11⟩, so each cut
+        // between two levels crosses a known set of pairs and the entropies add up.
+        let angles = [0.7, std::f64::consts::FRAC_PI_2, 2.1];
+        let mut circ = Circuit::new(6, 0);
+        for (q, theta) in angles.iter().enumerate() {
+            circ.u(q, *theta, 0.0, 0.0).cx(q, q + 3);
+        }
+        let mut sim = QuantumCircuit::new(6);
+        sim.run(&circ);
+
+        let pair = |theta: f64| {
+            let (p, q) = ((theta / 2.0).cos().powi(2), (theta / 2.0).sin().powi(2));
+            (-p * p.log2() - q * q.log2(), -(p * p + q * q).log2())
+        };
+        for bottom in 1..6 {
+            // Cut off levels 0..bottom from below, and the same cut seen from above.
+            let lower: Vec<usize> = (0..bottom).collect();
+            let upper: Vec<usize> = (bottom..6).collect();
+            let crossing: Vec<usize> = (0..3).filter(|&p| (p < bottom) != (p + 3 < bottom)).collect();
+            let von_neumann: f64 = crossing.iter().map(|&p| pair(angles[p]).0).sum();
+            let renyi2: f64 = crossing.iter().map(|&p| pair(angles[p]).1).sum();
+            for cut in [&lower, &upper] {
+                let entropy = sim.entanglement_entropy(cut);
+                assert!((entropy.von_neumann - von_neumann).abs() < 1e-9, "{:?}: {}", cut, entropy.von_neumann);
+                assert!((entropy.renyi2 - renyi2).abs() < 1e-9, "{:?}: {}", cut, entropy.renyi2);
+                assert!(entropy.schmidt_rank_bound >= 1 << crossing.len(), "{:?}", cut);
+            }
+        }
+    }
+
+    #[test]
+    fn test_entropy_does_not_depend_on_level_order() {
+        let mut circ = Circuit::new(5, 0);
+        circ.h(0).cx(0, 3).u(3, 0.7, 0.2, 0.1).cx(3, 1).h(4).cz(4, 2).t(2).cx(2, 0);
+        let mut a = QuantumCircuit::new(5);
+        a.run(&circ);
+        let mut b = QuantumCircuit::new(5);
+        b.swap_levels(1);
+        b.swap_levels(3);
+        b.run(&circ);
+        for cut in [vec![0, 3], vec![2], vec![1, 4]] {
+            let (x, y) = (a.entanglement_entropy(&cut), b.entanglement_entropy(&cut));
+            assert!((x.von_neumann - y.von_neumann).abs() < 1e-9, "{:?}", cut);
+            assert!((x.renyi2 - y.renyi2).abs() < 1e-9, "{:?}", cut);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use crate::QuantumCircuit;
+use crate::constants::ONE;
+use super::inner_product::pair_product;
+
+/// A single-qubit Pauli operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pauli {
+    I,
+    X,
+    Y,
+    Z,
+}
+
+/// A tensor product of Paulis on distinct qubits, identity elsewhere. Parses from text
+/// such as `"X0 Z3 Y5"`; the empty string is the identity.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PauliString {
+    /// The non-identity factors as `(qubit, pauli)`, sorted by qubit.
+    pub factors: Vec<(usize, Pauli)>,
+}
+
+/// Error from parsing a `PauliString`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauliError {
+    pub message: String,
+}
+
+impl fmt::Display for PauliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for PauliError {}
+
+impl PauliString {
+    /// The product of `factors`. Identity factors are dropped; panics if a qubit appears
+    /// twice.
+    pub fn new(factors: &[(usize, Pauli)]) -> Self {
+        let mut factors: Vec<(usize, Pauli)> = factors.iter().copied().filter(|(_, p)| *p != Pauli::I).collect();
+        factors.sort_by_key(|(q, _)| *q);
+        assert!(factors.windows(2).all(|w| w[0].0 != w[1].0), "Qubit used twice in a Pauli string");
+        PauliString { factors }
+    }
+}
+
+impl FromStr for PauliString {
+    type Err = PauliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |message: String| PauliError { message };
+        let mut factors = Vec::new();
+        for token in s.split_whitespace() {
+            let mut chars = token.chars();
+            let pauli = match chars.next().map(|c| c.to_ascii_uppercase()) {
+                Some('I') => Pauli::I,
+                Some('X') => Pauli::X,
+                Some('Y') => Pauli::Y,
+                Some('Z') => Pauli::Z,
+                _ => return Err(error(format!("'{}' does not start with I, X, Y or Z", token))),
+            };
+            let qubit = chars.as_str().parse()
+                .map_err(|_| error(format!("'{}' needs a qubit index after the Pauli", token)))?;
+            if factors.iter().any(|&(q, _)| q == qubit) {
+                return Err(error(format!("qubit {} appears twice", qubit)));
+            }
+            factors.push((qubit, pauli));
+        }
+        Ok(PauliString::new(&factors))
+    }
+}
+
+impl fmt::Display for PauliString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let factors: Vec<String> = self.factors.iter().map(|(q, p)| format!("{:?}{}", p, q)).collect();
+        write!(f, "{}", factors.join(" "))
+    }
+}
+
+impl QuantumCircuit {
+    /// The expectation value ⟨ψ|P|ψ⟩ of a Pauli string on the (normalized) state.
+    ///
+    /// The diagram is walked against itself with P applied on the way down, so each
+    /// pair of nodes is evaluated once and nothing is expanded to a dense vector.
+    pub fn expectation(&self, pauli: &PauliString) -> f64 {
+        let mut paulis = vec![Pauli::I; self.num_qubits];
+        for &(qubit, p) in &pauli.factors {
+            assert!(qubit < self.num_qubits, "Pauli on qubit {} of a {}-qubit state", qubit, self.num_qubits);
+            paulis[self.level_of(qubit)] = p;
+        }
+        let root = (ONE, self.root.clone());
+        // P is Hermitian, so the imaginary part is only rounding error.
+        pair_product(&root, &root, self.num_qubits, &paulis, &mut HashMap::new()).re
+    }
+
+    /// The expectation value of a Hamiltonian given as weighted Pauli strings,
+    /// Σ c_k ⟨ψ|P_k|ψ⟩.
+    pub fn expectation_sum(&self, terms: &[(f64, PauliString)]) -> f64 {
+        terms.iter().map(|(c, p)| c * self.expectation(p)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Circuit, Pauli, PauliString, QuantumCircuit};
+
+    #[test]
+    fn test_parse_pauli_string() {
+        let p: PauliString = "X0 z3 Y5 I2".parse().unwrap();
+        assert_eq!(p.factors, vec![(0, Pauli::X), (3, Pauli::Z), (5, Pauli::Y)]);
+        assert_eq!(p.to_string(), "X0 Z3 Y5");
+        assert_eq!("".parse::<PauliString>().unwrap(), PauliString::default());
+        assert!("X0 Z0".parse::<PauliString>().is_err());
+        assert!("W1".parse::<PauliString>().is_err());
+        assert!("X".parse::<PauliString>().is_err());
+    }
+
+    #[test]
+    fn test_expectation_of_simple_states() {
+        let plus = QuantumCircuit::uniform(2);
+        assert!((plus.expectation(&"X0 X1".parse().unwrap()) - 1.0).abs() < 1e-12);
+        assert!(plus.expectation(&"Z0".parse().unwrap()).abs() < 1e-12);
+
+        let ghz = QuantumCircuit::ghz(3);
+        assert!((ghz.expectation(&"Z0 Z2".parse().unwrap()) - 1.0).abs() < 1e-12);
+        assert!((ghz.expectation(&"X0 X1 X2".parse().unwrap()) - 1.0).abs() < 1e-12);
+        assert!(ghz.expectation(&"Y0 X1 X2".parse().unwrap()).abs() < 1e-12);
+        assert!((ghz.expectation(&"Y0 Y1 X2".parse().unwrap()) + 1.0).abs() < 1e-12);
+
+        // |+i⟩ = S|+⟩ has ⟨Y⟩ = 1.
+        let mut plus_i = QuantumCircuit::uniform(1);
+        plus_i.apply_s(0);
+        assert!((plus_i.expectation(&"Y0".parse().unwrap()) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_product_state_in_any_level_order() {
+        // Qubit q points along (θ_q, φ_q) on the Bloch sphere, so ⟨X⟩, ⟨Y⟩ and ⟨Z⟩ are known
+        // per qubit and multiply across a string, wherever the qubits sit in the diagram.
+        let angles = [(0.4, 1.3), (2.2, -0.6), (1.1, 2.9), (0.8, 0.0)];
+        let single = |q: usize, p: Pauli| {
+            let (theta, phi): (f64, f64) = angles[q];
+            match p {
+                Pauli::I => 1.0,
+                Pauli::X => theta.sin() * phi.cos(),
+                Pauli::Y => theta.sin() * phi.sin(),
+                Pauli::Z => theta.cos(),
+            }
+        };
+        let strings = ["Z0", "X1 Y2", "Y0 Z1 X3", "X0 X1 X2 X3", "Y3 Y1", "Z2 Z3", ""];
+        for swaps in [vec![], vec![1], vec![0, 2, 1], vec![2, 1, 0, 2]] {
+            let mut sim = QuantumCircuit::product_state(&angles);
+            for level in swaps.iter().copied() {
+                sim.swap_levels(level);
+            }
+            for s in strings {
+                let p: PauliString = s.parse().unwrap();
+                let expected: f64 = p.factors.iter().map(|&(q, pauli)| single(q, pauli)).product();
+                let got = sim.expectation(&p);
+                assert!((got - expected).abs() < 1e-9, "swaps {:?}, {}: {} != {}", swaps, s, got, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_expectation_sum() {
+        // Transverse-field Ising terms on a Bell pair: ⟨ZZ⟩ = 1, ⟨X⟩ = 0.
+        let mut bell = QuantumCircuit::new(2);
+        bell.run(Circuit::new(2, 0).h(0).cx(0, 1));
+        let hamiltonian = vec![
+            (-1.0, "Z0 Z1".parse().unwrap()),
+            (0.5, "X0".parse().unwrap()),
+            (0.5, "X1".parse().unwrap()),
+        ];
+        assert!((bell.expectation_sum(&hamiltonian) + 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fmt;
+use num::complex::Complex64;
+use crate::{NodePtr, QuantumCircuit};
+use crate::constants::ZERO;
+use crate::gates::apply_mcu::{zero_edge, Edge, UniqueTable};
+use super::new::new_sink;
+
+/// How far the squared norm of an input state may be from 1.
+const NORM_TOLERANCE: f64 = 1e-8;
+
+/// Error from building a diagram out of amplitudes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateError {
+    pub message: String,
+}
+
+impl StateError {
+    fn new(message: impl Into<String>) -> Self {
+        StateError { message: message.into() }
+    }
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl QuantumCircuit {
+    /// Builds the diagram of a dense state vector, indexed like `get_state_vector`
+    /// (bit `q` of the index is qubit `q`).
+    ///
+    /// The length must be a power of two and the state normalized; use
+    /// `from_unnormalized_state_vector` to have it scaled instead.
+    pub fn from_state_vector(amplitudes: &[Complex64]) -> Result<QuantumCircuit, StateError> {
+        let num_qubits = dense_num_qubits(amplitudes.len())?;
+        check_norm(amplitudes.iter())?;
+        Ok(build(num_qubits, nonzero(amplitudes)))
+    }
+
+    /// Like `from_state_vector`, but scales any non-zero vector to unit norm.
+    pub fn from_unnormalized_state_vector(amplitudes: &[Complex64]) -> Result<QuantumCircuit, StateError> {
+        let num_qubits = dense_num_qubits(amplitudes.len())?;
+        let entries = normalized(nonzero(amplitudes))?;
+        Ok(build(num_qubits, entries))
+    }
+
+    /// Builds the diagram of an `num_qubits`-qubit state from its non-zero amplitudes,
+    /// keyed by basis state index as in `from_state_vector`. Missing indices are zero.
+    pub fn from_sparse(amplitudes: HashMap<usize, Complex64>, num_qubits: usize) -> Result<QuantumCircuit, StateError> {
+        if let Some(&index) = amplitudes.keys().find(|&&i| num_qubits < usize::BITS as usize && i >> num_qubits != 0) {
+            return Err(StateError::new(format!("basis state {} does not exist with {} qubits", index, num_qubits)));
+        }
+        check_norm(amplitudes.values())?;
+        let mut entries: Vec<(usize, Complex64)> = amplitudes.into_iter().filter(|(_, a)| *a != ZERO).collect();
+        entries.sort_by_key(|(i, _)| *i);
+        Ok(build(num_qubits, entries))
+    }
+}
+
+fn dense_num_qubits(len: usize) -> Result<usize, StateError> {
+    if !len.is_power_of_two() {
+        return Err(StateError::new(format!("a state vector needs a power-of-two length, got {}", len)));
+    }
+    Ok(len.trailing_zeros() as usize)
+}
+
+fn check_norm<'a>(amplitudes: impl Iterator<Item = &'a Complex64>) -> Result<(), StateError> {
+    let norm_sqr: f64 = amplitudes.map(|a| a.norm_sqr()).sum();
+    if (norm_sqr - 1.0).abs() > NORM_TOLERANCE {
+        return Err(StateError::new(format!("the state has squared norm {} instead of 1", norm_sqr)));
+    }
+    Ok(())
+}
+
+fn nonzero(amplitudes: &[Complex64]) -> Vec<(usize, Complex64)> {
+    amplitudes.iter().copied().enumerate().filter(|(_, a)| *a != ZERO).collect()
+}
+
+fn normalized(entries: Vec<(usize, Complex64)>) -> Result<Vec<(usize, Complex64)>, StateError> {
+    let norm = entries.iter().map(|(_, a)| a.norm_sqr()).sum::<f64>().sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return Err(StateError::new("cannot normalize a state with norm 0"));
+    }
+    Ok(entries.into_iter().map(|(i, a)| (i, a / norm)).collect())
+}
+
+/// Builds the diagram bottom-up through a unique table, so equal sub-diagrams are
+/// shared. `entries` are the non-zero amplitudes sorted by index.
+fn build(num_qubits: usize, entries: Vec<(usize, Complex64)>) -> QuantumCircuit {
+    let sink = new_sink();
+    if num_qubits == 0 {
+        return QuantumCircuit::from_root(sink, 0);
+    }
+    let mut unique = UniqueTable::default();
+    let root = build_level(num_qubits - 1, &entries, &sink, &mut unique);
+    QuantumCircuit::from_root_edge(root, num_qubits)
+}
+
+/// Edge for the amplitudes in `entries`, which agree on every bit above `level`.
+fn build_level(level: usize, entries: &[(usize, Complex64)], sink: &NodePtr, unique: &mut UniqueTable) -> Edge {
+    // Sorted by index, so the entries with bit `level` clear come first. Indices have no
+    // bits above `usize::BITS`, so those levels are |0⟩.
+    let split = entries.partition_point(|(i, _)| i.checked_shr(level as u32).unwrap_or(0) & 1 == 0);
+    let (zeros, ones) = entries.split_at(split);
+    let mut arm = |part: &[(usize, Complex64)]| match part {
+        [] => zero_edge(),
+        [(_, amplitude)] if level == 0 => (*amplitude, sink.clone()),
+        _ => build_level(level - 1, part, sink, unique),
+    };
+    let (zero, one) = (arm(zeros), arm(ones));
+    unique.make_node(level, zero, one)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use num::complex::Complex64;
+    use crate::{Gate, QuantumCircuit};
+
+    fn c(re: f64, im: f64) -> Complex64 {
+        Complex64::new(re, im)
+    }
+
+    fn assert_close(a: &[Complex64], b: &[Complex64]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).norm() < 1e-12, "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_of_simulated_state() {
+        let mut sim = QuantumCircuit::new(4);
+        sim.apply_unitary(3, Gate::H.matrix().unwrap());
+        sim.apply_unitary(0, Gate::U(0.4, 1.1, -0.3).matrix().unwrap());
+        sim.apply_mcu(&[3], &[], 1, Gate::X.matrix().unwrap());
+        sim.apply_t(2);
+        let state = sim.get_state_vector();
+
+        let built = QuantumCircuit::from_state_vector(&state).unwrap();
+        assert_eq!(built.num_qubits, 4);
+        assert_close(&built.get_state_vector(), &state);
+        assert!(built.count_nodes().1 <= sim.count_nodes().1);
+    }
+
+    #[test]
+    fn test_shares_equal_sub_diagrams() {
+        // |+⟩^⊗5 needs one node per level.
+        let n = 5;
+        let amplitude = c(1.0 / (1 << n) as f64, 0.0).sqrt();
+        let built = QuantumCircuit::from_state_vector(&vec![amplitude; 1 << n]).unwrap();
+        assert_eq!(built.count_nodes().1, n + 1);
+    }
+
+    #[test]
+    fn test_from_sparse() {
+        let h = std::f64::consts::FRAC_1_SQRT_2;
+        let ghz = HashMap::from([(0, c(h, 0.0)), (0b111, c(0.0, -h))]);
+        let mut sim = QuantumCircuit::from_sparse(ghz, 3).unwrap();
+        let state = sim.get_state_vector();
+        assert_close(&state, &[c(h, 0.0), c(0.0, 0.0), c(0.0, 0.0), c(0.0, 0.0),
+                               c(0.0, 0.0), c(0.0, 0.0), c(0.0, 0.0), c(0.0, -h)]);
+        // Three chains of nodes merge into two below the root.
+        assert_eq!(sim.count_nodes().1, 6);
+
+        sim.apply_mcu(&[2], &[], 0, Gate::X.matrix().unwrap());
+        assert!((sim.get_state_vector()[0b110] - c(0.0, -h)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_from_sparse_beyond_index_bits() {
+        // Indices only reach the lowest 64 qubits; the rest start in |0⟩.
+        let h = std::f64::consts::FRAC_1_SQRT_2;
+        let sim = QuantumCircuit::from_sparse(HashMap::from([(1, c(h, 0.0)), (1 << 63, c(h, 0.0))]), 70).unwrap();
+        assert!((sim.norm() - 1.0).abs() < 1e-12);
+        assert!((sim.calculate_probabilities(63).1 - 0.5).abs() < 1e-12);
+        assert!(sim.calculate_probabilities(69).1.abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_validation() {
+        let err = |r: Result<QuantumCircuit, super::StateError>| r.err().unwrap().message;
+        assert!(err(QuantumCircuit::from_state_vector(&[c(1.0, 0.0); 3])).contains("power-of-two"));
+        assert!(err(QuantumCircuit::from_state_vector(&[c(1.0, 0.0); 4])).contains("squared norm 4"));
+        assert!(err(QuantumCircuit::from_sparse(HashMap::from([(4, c(1.0, 0.0))]), 2)).contains("basis state 4"));
+
+        let scaled = QuantumCircuit::from_unnormalized_state_vector(&[c(3.0, 0.0), c(0.0, 4.0)]).unwrap();
+        assert_close(&scaled.get_state_vector(), &[c(0.6, 0.0), c(0.0, 0.8)]);
+        assert!(QuantumCircuit::from_unnormalized_state_vector(&[c(0.0, 0.0); 2]).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use num::complex::Complex64;
+use crate::{DdNode, Pauli, QuantumCircuit};
+use crate::constants::{IOTA, ONE, ZERO};
+use crate::gates::apply_mcu::{children_at, is_zero, Edge};
+use super::measure::subtree_norm_sqr;
+
+pub(crate) type PairKey = (*const RefCell<DdNode>, *const RefCell<DdNode>);
+
+impl QuantumCircuit {
+    /// The inner product ⟨self|other⟩, computed on the two diagrams directly.
+    ///
+    /// Both diagrams are walked together from the roots, and each pair of nodes is
+    /// evaluated once, so the cost is bounded by the product of the node counts rather
+    /// than by 2^n. If the qubits sit at different levels in the two states (see
+    /// `reorder`), a copy of `other` is first brought into the order of `self`.
+    pub fn inner_product(&self, other: &QuantumCircuit) -> Complex64 {
+        assert_eq!(self.num_qubits, other.num_qubits, "States have different numbers of qubits");
+        if self.qubits != other.qubits {
+            return self.inner_product(&other.copy_in_order_of(self));
+        }
+        let identity = vec![Pauli::I; self.num_qubits];
+        pair_product(&(ONE, self.root.clone()), &(ONE, other.root.clone()), self.num_qubits, &identity, &mut HashMap::new())
+    }
+
+    /// The fidelity |⟨self|other⟩|² between the two states, normalized by their norms
+    /// so that rounding errors in either state do not push it above 1.
+    pub fn fidelity(&self, other: &QuantumCircuit) -> f64 {
+        let overlap = self.inner_product(other).norm_sqr();
+        let norms = subtree_norm_sqr(&self.root, &mut HashMap::new()) * subtree_norm_sqr(&other.root, &mut HashMap::new());
+        (overlap / norms).min(1.0)
+    }
+}
+
+/// ⟨a|P|b⟩ for two edges into the part of the diagram below level `height`, where P has
+/// `paulis[l]` on level `l`. Edges may skip levels; a skipped level stands for the same
+/// sub-diagram on both arms.
+pub(crate) fn pair_product(a: &Edge, b: &Edge, height: usize, paulis: &[Pauli], memo: &mut HashMap<(PairKey, usize), Complex64>) -> Complex64 {
+    if is_zero(a) || is_zero(b) {
+        return ZERO;
+    }
+    let weights = a.0.conj() * b.0;
+    let key = ((std::rc::Rc::as_ptr(&a.1), std::rc::Rc::as_ptr(&b.1)), height);
+    if let Some(product) = memo.get(&key) {
+        return weights * product;
+    }
+    let product = if height == 0 {
+        // Both edges end at the sink.
+        ONE
+    } else {
+        let level = height - 1;
+        let (a0, a1) = children_at(&(ONE, a.1.clone()), level);
+        let (b0, b1) = children_at(&(ONE, b.1.clone()), level);
+        let mut below = |x: &Edge, y: &Edge| pair_product(x, y, level, paulis, memo);
+        match paulis[level] {
+            Pauli::I => below(&a0, &b0) + below(&a1, &b1),
+            Pauli::Z => below(&a0, &b0) - below(&a1, &b1),
+            Pauli::X => below(&a0, &b1) + below(&a1, &b0),
+            // Y|0⟩ = i|1⟩ and Y|1⟩ = -i|0⟩.
+            Pauli::Y => IOTA * (below(&a1, &b0) - below(&a0, &b1)),
+        }
+    };
+    memo.insert(key, product);
+    weights * product
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use num::complex::Complex64;
+    use crate::{Circuit, DdNode, QuantumCircuit};
+    use crate::constants::ZERO;
+    use crate::gates::apply_mcu::{zero_edge, Edge};
+    use crate::quantum_circuit::new::new_sink;
+
+    fn c(re: f64, im: f64) -> Complex64 {
+        Complex64::new(re, im)
+    }
+
+    fn dense_inner_product(a: &[Complex64], b: &[Complex64]) -> Complex64 {
+        a.iter().zip(b).map(|(x, y)| x.conj() * y).sum()
+    }
+
+    #[test]
+    fn test_shared_nodes() {
+        // Uniform, Dicke and W states reach most nodes through several parents, so the
+        // pair memo is hit on almost every step.
+        let mut entangled = QuantumCircuit::dicke(5, 2);
+        entangled.run(Circuit::new(5, 0).h(4).cx(4, 0).t(0).cz(1, 3));
+        let states = [
+            QuantumCircuit::uniform(5),
+            QuantumCircuit::dicke(5, 2),
+            QuantumCircuit::w_state(5),
+            QuantumCircuit::ghz(5),
+            entangled,
+        ];
+        for a in &states {
+            assert!((a.inner_product(a) - 1.0).norm() < 1e-9);
+            for b in &states {
+                let expected = dense_inner_product(&a.get_state_vector(), &b.get_state_vector());
+                assert!((a.inner_product(b) - expected).norm() < 1e-9, "{} != {}", a.inner_product(b), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edges_that_skip_levels() {
+        // The root's |0⟩ arm goes straight to level 0, so qubit 1 is skipped there and
+        // both of its values carry the same amplitudes.
+        let sink = new_sink();
+        let node = |qubit, zero: Edge, one: Edge| Rc::new(RefCell::new(DdNode::NonTerminal { qubit, zero, one }));
+        let low = node(0, (c(0.6, 0.0), sink.clone()), (c(0.0, 0.8), sink.clone()));
+        let other = node(0, (c(1.0, 0.0), sink.clone()), zero_edge());
+        let mid = node(1, (c(0.6, 0.0), low.clone()), (c(0.8, 0.0), other));
+        let root = node(2, (c(0.5, 0.0), low), (c(0.5, 0.0), mid));
+        let skipping = QuantumCircuit::from_root(root, 3);
+        let dense = [c(0.3, 0.0), c(0.0, 0.4), c(0.3, 0.0), c(0.0, 0.4), c(0.18, 0.0), c(0.0, 0.24), c(0.4, 0.0), ZERO];
+
+        assert!((skipping.inner_product(&skipping) - 0.75).norm() < 1e-12);
+        let full = QuantumCircuit::from_unnormalized_state_vector(&dense).unwrap();
+        assert!((skipping.fidelity(&full) - 1.0).abs() < 1e-12);
+        for seed in [1, 2] {
+            let other = QuantumCircuit::random_state(3, seed);
+            let expected = dense_inner_product(&other.get_state_vector(), &dense);
+            assert!((other.inner_product(&skipping) - expected).norm() < 1e-12);
+            assert!((skipping.inner_product(&other) - expected.conj()).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_fidelity() {
+        let plus = QuantumCircuit::uniform(3);
+        let zero = QuantumCircuit::new(3);
+        assert!((plus.fidelity(&zero) - 0.125).abs() < 1e-12);
+        assert!((QuantumCircuit::ghz(3).fidelity(&zero) - 0.5).abs() < 1e-12);
+        assert!(QuantumCircuit::basis_state("011").fidelity(&zero).abs() < 1e-12);
+
+        // Global phases do not change the fidelity.
+        let mut phased = QuantumCircuit::ghz(3);
+        phased.apply_global_phase(1.2);
+        assert!((phased.fidelity(&QuantumCircuit::ghz(3)) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_different_level_orders() {
+        let mut circ = Circuit::new(4, 0);
+        circ.h(0).cx(0, 3).t(3).h(1).cz(1, 2).u(2, 0.3, 0.1, 0.2);
+        let mut a = QuantumCircuit::new(4);
+        a.run(&circ);
+        let mut b = QuantumCircuit::new(4);
+        b.swap_levels(0);
+        b.swap_levels(2);
+        b.run(&circ);
+        assert!((a.fidelity(&b) - 1.0).abs() < 1e-9);
+        assert!((b.inner_product(&a) - 1.0).norm() < 1e-9);
+    }
+}
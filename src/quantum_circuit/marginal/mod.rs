@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::{DdNode, NodePtr, QuantumCircuit};
+
+impl QuantumCircuit {
+    /// The joint outcome distribution of measuring `qubits`, with the other qubits traced
+    /// out. Bit `k` of each key is the outcome of `qubits[k]`; outcomes with zero
+    /// probability are left out.
+    ///
+    /// Like `calculate_probabilities`, the probabilities are not rescaled: they add up to
+    /// norm², so a state that drifted from norm 1 shows it here.
+    ///
+    /// Each node is visited once and returns the distribution over the observed qubits
+    /// below it, so unobserved levels only add up already computed sub-diagram norms
+    /// and the cost follows the size of the diagram rather than 2^n.
+    pub fn marginal_probabilities(&self, qubits: &[usize]) -> HashMap<usize, f64> {
+        let mut positions = vec![None; self.num_qubits];
+        for (k, &qubit) in qubits.iter().enumerate() {
+            assert!(qubit < self.num_qubits, "Qubit {} out of range for {} qubits", qubit, self.num_qubits);
+            let level = self.level_of(qubit);
+            assert!(positions[level].is_none(), "Qubit {} listed twice", qubit);
+            positions[level] = Some(k);
+        }
+        let mut dist = marginal(&self.root, &positions, &mut HashMap::new());
+        dist.retain(|_, p| *p > 0.0);
+        dist
+    }
+}
+
+/// Unnormalized distribution over the observed qubits below `node`. `positions[l]` is
+/// the key bit of the qubit at level `l`, if it is observed.
+fn marginal(
+    node: &NodePtr,
+    positions: &[Option<usize>],
+    memo: &mut HashMap<*const RefCell<DdNode>, HashMap<usize, f64>>,
+) -> HashMap<usize, f64> {
+    let key = Rc::as_ptr(node);
+    if let Some(dist) = memo.get(&key) {
+        return dist.clone();
+    }
+    let dist = match &*node.borrow() {
+        DdNode::Terminal(_) => HashMap::new(),
+        DdNode::NonTerminal { qubit, .. } if *qubit == usize::MAX => HashMap::from([(0, 1.0)]),
+        DdNode::NonTerminal { qubit, zero, one } => {
+            let mut dist = HashMap::new();
+            for (edge, bit) in [(zero, 0), (one, 1)] {
+                let weight = edge.0.norm_sqr();
+                if weight == 0.0 {
+                    continue;
+                }
+                let offset = positions[*qubit].map_or(0, |k| bit << k);
+                for (bits, p) in marginal(&edge.1, positions, memo) {
+                    *dist.entry(bits | offset).or_insert(0.0) += weight * p;
+                }
+            }
+            dist
+        }
+    };
+    memo.insert(key, dist.clone());
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::QuantumCircuit;
+
+    #[test]
+    fn test_marginal_of_ghz() {
+        let dist = QuantumCircuit::ghz(4).marginal_probabilities(&[3, 1]);
+        assert_eq!(dist.len(), 2);
+        assert!((dist[&0b00] - 0.5).abs() < 1e-12);
+        assert!((dist[&0b11] - 0.5).abs() < 1e-12);
+
+        let single = QuantumCircuit::basis_state("0110").marginal_probabilities(&[1]);
+        assert_eq!(single.len(), 1);
+        assert!((single[&1] - 1.0).abs() < 1e-12);
+        let nothing = QuantumCircuit::ghz(3).marginal_probabilities(&[]);
+        assert_eq!(nothing.len(), 1);
+        assert!((nothing[&0] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_marginal_of_dicke_states() {
+        // In D(n, k) an outcome with j ones on m listed qubits has probability
+        // C(n - m, k - j) / C(n, k), so only the number of ones matters, whatever the order
+        // of the list or of the levels.
+        let choose = |n: usize, k: usize| -> f64 { (0..k).map(|i| (n - i) as f64 / (i + 1) as f64).product() };
+        for (n, k) in [(5, 2), (6, 1), (6, 3)] {
+            for swaps in [vec![], vec![2, 0], vec![1, 3, 2]] {
+                let mut sim = QuantumCircuit::dicke(n, k);
+                for level in swaps.iter().copied() {
+                    sim.swap_levels(level);
+                }
+                for qubits in [vec![0], vec![3, 0], vec![4, 1, 2], (0..n).rev().collect()] {
+                    let dist = sim.marginal_probabilities(&qubits);
+                    let m = qubits.len();
+                    for bits in 0..1usize << m {
+                        let j = bits.count_ones() as usize;
+                        let expected = if j <= k && k - j <= n - m { choose(n - m, k - j) / choose(n, k) } else { 0.0 };
+                        let got = dist.get(&bits).copied().unwrap_or(0.0);
+                        assert!((got - expected).abs() < 1e-9, "D({}, {}) swaps {:?}, {:?} = {:b}", n, k, swaps, qubits, bits);
+                    }
+                }
+                let (p0, p1) = sim.calculate_probabilities(3);
+                let single = sim.marginal_probabilities(&[3]);
+                assert!((single.get(&0).copied().unwrap_or(0.0) - p0).abs() < 1e-9);
+                assert!((single.get(&1).copied().unwrap_or(0.0) - p1).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_marginal_is_not_renormalized() {
+        // apply_u does not preserve the norm, so this state has drifted.
+        let mut sim = QuantumCircuit::new(3);
+        sim.apply_h(2);
+        sim.apply_u(0, 1.0, 0.5, 0.25);
+        let dist = sim.marginal_probabilities(&[0, 2]);
+        let norm_sqr = sim.norm().powi(2);
+        assert!((norm_sqr - 1.0).abs() > 1e-3);
+        assert!((dist.values().sum::<f64>() - norm_sqr).abs() < 1e-12);
+        let p = |bits| dist.get(&bits).copied().unwrap_or(0.0);
+        let (p0, p1) = sim.calculate_probabilities(0);
+        assert!((p(0b00) + p(0b10) - p0).abs() < 1e-12);
+        assert!((p(0b01) + p(0b11) - p1).abs() < 1e-12);
+    }
+}
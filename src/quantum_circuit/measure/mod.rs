@@ -1,44 +1,164 @@
-use num::complex::Complex64;
-use rand::{rng, Rng};
-use crate::{DdNode, NodePtr, QuantumCircuit};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use rand::Rng;
+use crate::{DdNode, NodePtr, QuantumCircuit, QubitIter};
+use crate::constants::ZERO;
 
 /// Measure a single qubit, returning the measurement result
 impl QuantumCircuit {
+    /// Samples the qubit and collapses the state onto the observed outcome.
     pub fn measure_qubit(&mut self, qubit: usize) -> u8 {
+        self.check_norm("measure");
         let (prob0, _) = self.calculate_probabilities(qubit);
-        let mut rng = rng();
-        if rng.random::<f64>() < prob0 { 0 } else { 1 }
+        let outcome = if self.rng.random::<f64>() < prob0 { 0 } else { 1 };
+        self.collapse(qubit, outcome);
+        outcome
     }
 
-    /// Calculate probabilities for |0⟩ and |1⟩ states of a qubit
+    /// Resets the qubit to |0⟩ by measuring it and flipping it back if needed. Returns
+    /// the measured outcome.
+    pub fn reset(&mut self, qubit: usize) -> u8 {
+        let outcome = self.measure_qubit(qubit);
+        if outcome == 1 {
+            self.apply_x(qubit);
+        }
+        outcome
+    }
+
+    /// Samples `shots` outcomes of measuring every qubit, without changing the state.
+    /// Entry `q` of each outcome is the value of qubit `q`.
+    ///
+    /// Each shot walks from the root to the sink, taking each arm with probability
+    /// proportional to the squared norm below it. The norms are computed once, so a
+    /// shot costs one step per qubit and the state is never expanded.
+    pub fn sample(&mut self, shots: usize) -> HashMap<Vec<bool>, usize> {
+        let mut norms = HashMap::new();
+        subtree_norm_sqr(&self.root, &mut norms);
+        let mut counts = HashMap::new();
+        for _ in 0..shots {
+            let mut outcome = vec![false; self.num_qubits];
+            let mut node = self.root.clone();
+            loop {
+                let next = match &*node.borrow() {
+                    DdNode::NonTerminal { qubit, zero, one } if *qubit != usize::MAX => {
+                        let p0 = zero.0.norm_sqr() * subtree_norm_sqr(&zero.1, &mut norms);
+                        let p1 = one.0.norm_sqr() * subtree_norm_sqr(&one.1, &mut norms);
+                        let take_one = self.rng.random::<f64>() * (p0 + p1) >= p0;
+                        outcome[self.qubit_at(*qubit)] = take_one;
+                        if take_one { one.1.clone() } else { zero.1.clone() }
+                    }
+                    _ => break,
+                };
+                node = next;
+            }
+            *counts.entry(outcome).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Calculate probabilities for |0⟩ and |1⟩ states of a qubit.
+    ///
+    /// The two are not rescaled by the norm, so they add up to norm² and a kernel that
+    /// broke the norm shows up here instead of being hidden (see `set_norm_monitor`).
     pub fn calculate_probabilities(&self, target: usize) -> (f64, f64) {
-        let mut prob0 = 0.0;
-        let mut prob1 = 0.0;
-        self.traverse(&self.root, Complex64::new(1.0, 0.0), target, &mut prob0, &mut prob1);
-        let total = prob0 + prob1;
-        (prob0 / total, prob1 / total)
-    }
-
-    fn traverse(&self, node: &NodePtr, mut amplitude: Complex64, target: usize, prob0: &mut f64, prob1: &mut f64) {
-        let node_ref = node.borrow();
-        match &*node_ref {
-            DdNode::Terminal(_) => {
-                let prob = amplitude.norm_sqr();
-                if target == 0 {
-                    *prob0 += prob;
+        let mut norms = HashMap::new();
+        let mut ones = HashMap::new();
+        let total = subtree_norm_sqr(&self.root, &mut norms);
+        let prob1 = self.traverse(&self.root, self.level_of(target), &mut norms, &mut ones);
+        (total - prob1, prob1)
+    }
+
+    /// Returns the squared norm of the part of the sub-diagram below `node`
+    /// in which `target` is |1⟩. Shared nodes are evaluated once.
+    fn traverse(
+        &self,
+        node: &NodePtr,
+        target: usize,
+        norms: &mut HashMap<*const RefCell<DdNode>, f64>,
+        ones: &mut HashMap<*const RefCell<DdNode>, f64>,
+    ) -> f64 {
+        let key = Rc::as_ptr(node);
+        if let Some(p) = ones.get(&key) {
+            return *p;
+        }
+        let prob = match &*node.borrow() {
+            DdNode::Terminal(_) => 0.0,
+            DdNode::NonTerminal { qubit, zero, one } => {
+                if *qubit == target {
+                    one.0.norm_sqr() * subtree_norm_sqr(&one.1, norms)
+                } else if *qubit > target && *qubit != usize::MAX {
+                    zero.0.norm_sqr() * self.traverse(&zero.1, target, norms, ones)
+                        + one.0.norm_sqr() * self.traverse(&one.1, target, norms, ones)
+                } else {
+                    0.0
                 }
             }
-            DdNode::NonTerminal { qubit, zero, one } => {
-                amplitude *= zero.0;
-                self.traverse(&zero.1, amplitude, target, prob0, prob1);
+        };
+        ones.insert(key, prob);
+        prob
+    }
 
-                amplitude = one.0;
-                self.traverse(&one.1, amplitude, target, prob0, prob1);
+    /// Projects the state onto `outcome` for `qubit` and renormalizes it.
+    pub fn collapse(&mut self, qubit: usize, outcome: u8) {
+        let (prob0, prob1) = self.calculate_probabilities(qubit);
+        let prob = if outcome == 0 { prob0 } else { prob1 };
+        assert!(prob > 0.0, "Cannot collapse qubit {} onto an outcome with zero probability", qubit);
 
-                if *qubit == target {
-                    *prob1 += amplitude.norm_sqr();
+        let iter = QubitIter::new(self.root.clone(), self.level_of(qubit));
+        for node in iter {
+            let terminal = Rc::new(RefCell::new(DdNode::Terminal(ZERO)));
+            let mut node_ref = node.borrow_mut();
+            if let DdNode::NonTerminal { zero, one, .. } = &mut *node_ref {
+                if outcome == 0 {
+                    *one = (ZERO, terminal);
+                } else {
+                    *zero = (ZERO, terminal);
                 }
             }
         }
+
+        self.normalize();
+    }
+}
+
+/// Squared norm of the state represented by the sub-diagram below `node`.
+/// The sink contributes 1 and Terminal nodes contribute 0.
+pub(crate) fn subtree_norm_sqr(node: &NodePtr, memo: &mut HashMap<*const RefCell<DdNode>, f64>) -> f64 {
+    let key = Rc::as_ptr(node);
+    if let Some(n) = memo.get(&key) {
+        return *n;
+    }
+    let norm = match &*node.borrow() {
+        DdNode::Terminal(_) => 0.0,
+        DdNode::NonTerminal { qubit, .. } if *qubit == usize::MAX => 1.0,
+        DdNode::NonTerminal { zero, one, .. } => {
+            zero.0.norm_sqr() * subtree_norm_sqr(&zero.1, memo)
+                + one.0.norm_sqr() * subtree_norm_sqr(&one.1, memo)
+        }
+    };
+    memo.insert(key, norm);
+    norm
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::QuantumCircuit;
+
+    #[test]
+    fn test_sample_wide_register() {
+        // A 100-qubit GHZ state: far too big to expand, cheap to sample.
+        let mut sim = QuantumCircuit::new(100);
+        sim.set_seed(5);
+        sim.apply_h(0);
+        for q in 1..100 {
+            sim.apply_cnot(q - 1, q);
+        }
+        let counts = sim.sample(400);
+        assert_eq!(counts.len(), 2);
+        let ones = counts[&vec![true; 100]];
+        assert_eq!(ones + counts[&vec![false; 100]], 400);
+        assert!((150..250).contains(&ones), "{} of 400 shots were all ones", ones);
+        assert!((sim.norm() - 1.0).abs() < 1e-9);
     }
 }
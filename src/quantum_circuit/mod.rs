@@ -2,4 +2,24 @@ mod statevector;
 mod new;
 mod qubit_iter;
 mod measure;
-mod count_nodes;
\ No newline at end of file
+mod count_nodes;
+mod run;
+mod reorder;
+mod approximate;
+mod serialize;
+mod from_state;
+mod states;
+mod inner_product;
+mod norm;
+mod expectation;
+mod marginal;
+mod top_k;
+mod density;
+mod entropy;
+
+pub use approximate::ApproximationPolicy;
+pub use serialize::DdLoadError;
+pub use from_state::StateError;
+pub use norm::{DriftAction, NormMonitor};
+pub use expectation::{Pauli, PauliError, PauliString};
+pub use entropy::EntanglementEntropy;
@@ -1,18 +1,16 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use crate::{DdNode, QuantumCircuit};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::{DdNode, NodePtr, QuantumCircuit};
 use crate::constants::{ONE, ZERO};
+use crate::gates::apply_mcu::Edge;
 
 impl QuantumCircuit {
     /// Initialize a new quantum state with all qubits in all |0⟩ state
     pub fn new(num_qubits: usize) -> Self {
         let terminal = Rc::new(RefCell::new(DdNode::Terminal(ZERO)));
-        let sink = Rc::new(RefCell::new(DdNode::NonTerminal {
-            qubit: usize::MAX,
-            zero: (ZERO, terminal.clone()),
-            one: (ZERO, terminal.clone()),
-        }));
-        let mut current = sink;
+        let mut current = new_sink();
 
         // Build initial decision diagram structure
         // q_(n-1) is the root node
@@ -25,9 +23,58 @@ impl QuantumCircuit {
             current = new_node;
         }
 
+        Self::from_root(current, num_qubits)
+    }
+
+    /// Wraps an already built diagram. The random generator is seeded from the OS.
+    pub(crate) fn from_root(root: NodePtr, num_qubits: usize) -> Self {
         QuantumCircuit {
-            root: current,
+            root,
             num_qubits,
+            rng: StdRng::from_os_rng(),
+            levels: (0..num_qubits).collect(),
+            qubits: (0..num_qubits).collect(),
+            approximation: None,
+            fidelity: 1.0,
+            norm_monitor: None,
+        }
+    }
+
+    /// Like `from_root`, for a root reached through an edge of weight `root.0`. The
+    /// root has no incoming edge, so the weight goes into both of its arms.
+    pub(crate) fn from_root_edge(root: Edge, num_qubits: usize) -> Self {
+        let (weight, root) = root;
+        if weight != ONE {
+            if let DdNode::NonTerminal { zero, one, .. } = &mut *root.borrow_mut() {
+                zero.0 *= weight;
+                one.0 *= weight;
+            }
         }
+        Self::from_root(root, num_qubits)
+    }
+
+    /// DD level holding logical qubit `qubit`. Gate methods take logical indices and
+    /// translate them with this before walking the diagram.
+    pub fn level_of(&self, qubit: usize) -> usize {
+        self.levels[qubit]
+    }
+
+    /// Logical qubit stored at DD level `level`.
+    pub fn qubit_at(&self, level: usize) -> usize {
+        self.qubits[level]
+    }
+
+    /// Reseeds the generator used for measurements and noise, making runs reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
     }
-}
\ No newline at end of file
+}
+/// A fresh sink node: the non-terminal below level 0, whose arms lead to Terminal(0).
+pub(crate) fn new_sink() -> NodePtr {
+    let terminal = Rc::new(RefCell::new(DdNode::Terminal(ZERO)));
+    Rc::new(RefCell::new(DdNode::NonTerminal {
+        qubit: usize::MAX,
+        zero: (ZERO, terminal.clone()),
+        one: (ZERO, terminal),
+    }))
+}
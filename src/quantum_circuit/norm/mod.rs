@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use num::complex::Complex64;
+use crate::{DdNode, QuantumCircuit};
+use super::measure::subtree_norm_sqr;
+
+/// What the norm monitor does when a gate leaves the state off unit norm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftAction {
+    /// Print a warning naming the gate to stderr.
+    Warn,
+    /// Warn, then rescale the state back to unit norm.
+    Renormalize,
+    /// Panic, naming the gate. Meant for tests.
+    Panic,
+}
+
+/// Settings for `QuantumCircuit::set_norm_monitor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormMonitor {
+    /// Largest accepted |norm - 1|.
+    pub tolerance: f64,
+    pub action: DriftAction,
+}
+
+impl QuantumCircuit {
+    /// The norm of the state, from one memoized pass over the diagram. Every gate is
+    /// unitary, so this stays 1 unless a kernel is wrong or rounding errors pile up.
+    pub fn norm(&self) -> f64 {
+        subtree_norm_sqr(&self.root, &mut HashMap::new()).sqrt()
+    }
+
+    /// Rescales the state to unit norm.
+    pub fn normalize(&mut self) {
+        let norm = self.norm();
+        assert!(norm > 0.0, "Cannot normalize the zero state");
+        // The root has no incoming edge, so the scale goes on both of its arms.
+        let scale = Complex64::new(1.0 / norm, 0.0);
+        if let DdNode::NonTerminal { zero, one, .. } = &mut *self.root.borrow_mut() {
+            zero.0 *= scale;
+            one.0 *= scale;
+        }
+    }
+
+    /// Makes every `apply_*` gate method check the norm afterwards and act on drift
+    /// beyond the tolerance. `None` switches the check off again. The check costs one
+    /// pass over the diagram per gate.
+    pub fn set_norm_monitor(&mut self, monitor: Option<NormMonitor>) {
+        self.norm_monitor = monitor;
+    }
+
+    /// Runs the norm monitor, if any, after `gate` was applied.
+    pub(crate) fn check_norm(&mut self, gate: &str) {
+        let Some(monitor) = self.norm_monitor else {
+            return;
+        };
+        let norm = self.norm();
+        if (norm - 1.0).abs() <= monitor.tolerance {
+            return;
+        }
+        let message = format!("norm is {} after {}", norm, gate);
+        match monitor.action {
+            DriftAction::Warn => eprintln!("warning: {}", message),
+            DriftAction::Renormalize => {
+                eprintln!("warning: {}, renormalizing", message);
+                self.normalize();
+            }
+            DriftAction::Panic => panic!("{}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::complex::Complex64;
+    use crate::{DriftAction, Gate, NormMonitor, QuantumCircuit};
+
+    fn monitored(action: DriftAction) -> QuantumCircuit {
+        let mut sim = QuantumCircuit::new(3);
+        sim.set_norm_monitor(Some(NormMonitor { tolerance: 1e-9, action }));
+        sim
+    }
+
+    #[test]
+    fn test_norm_and_normalize() {
+        let mut sim = QuantumCircuit::random_state(4, 1);
+        assert!((sim.norm() - 1.0).abs() < 1e-12);
+        // 2·I is not unitary, so the norm doubles.
+        let (two, zero) = (Complex64::new(2.0, 0.0), Complex64::new(0.0, 0.0));
+        sim.apply_unitary(2, [[two, zero], [zero, two]]);
+        assert!((sim.norm() - 2.0).abs() < 1e-12);
+        sim.normalize();
+        assert!((sim.norm() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_monitor_accepts_unitary_gates() {
+        let mut sim = monitored(DriftAction::Panic);
+        sim.apply_unitary(2, Gate::H.matrix().unwrap());
+        sim.apply_mcu(&[2], &[], 0, Gate::X.matrix().unwrap());
+        sim.apply_t(0);
+        sim.apply_y(1);
+        sim.apply_global_phase(0.4);
+    }
+
+    #[test]
+    #[should_panic(expected = "after u")]
+    fn test_monitor_catches_apply_u() {
+        // `apply_u` does not preserve the norm, which is why `run` avoids it.
+        let mut sim = monitored(DriftAction::Panic);
+        sim.apply_u(0, 1.0, 0.5, 0.25);
+    }
+
+    #[test]
+    fn test_probabilities_show_drift() {
+        let mut sim = QuantumCircuit::new(2);
+        sim.apply_u(0, 1.0, 0.5, 0.25);
+        let (p0, p1) = sim.calculate_probabilities(0);
+        assert!((p0 + p1 - sim.norm().powi(2)).abs() < 1e-12);
+        assert!((p0 + p1 - 1.0).abs() > 1e-3);
+    }
+
+    #[test]
+    #[should_panic(expected = "after measure")]
+    fn test_monitor_runs_before_measuring() {
+        let mut sim = QuantumCircuit::new(2);
+        sim.apply_u(0, 1.0, 0.5, 0.25);
+        sim.set_norm_monitor(Some(NormMonitor { tolerance: 1e-9, action: DriftAction::Panic }));
+        sim.measure_qubit(0);
+    }
+
+    #[test]
+    fn test_monitor_renormalizes() {
+        let mut sim = monitored(DriftAction::Renormalize);
+        sim.apply_u(0, 1.0, 0.5, 0.25);
+        assert!((sim.norm() - 1.0).abs() < 1e-12);
+    }
+}
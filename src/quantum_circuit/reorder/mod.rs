@@ -0,0 +1,212 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::{DdNode, NodePtr, QuantumCircuit};
+use crate::constants::{ONE, TOL};
+use crate::gates::apply_mcu::{children_at, is_zero, zero_edge, Edge, UniqueTable};
+
+impl QuantumCircuit {
+    /// Exchanges the variables at DD levels `level` and `level + 1`.
+    ///
+    /// Only the nodes at these two levels are rebuilt: each upper node with
+    /// grandchildren `x_ab` (upper arm `a`, lower arm `b`) becomes an upper node whose
+    /// `b` arm leads to a lower node with arms `(x_0b, x_1b)`. Nodes below are shared
+    /// as they are, and nodes above are copied so that identical sub-diagrams merge.
+    /// Gate, measurement and output methods keep using logical qubit indices.
+    pub fn swap_levels(&mut self, level: usize) {
+        assert!(level + 1 < self.num_qubits, "Cannot swap level {} with level {}", level, level + 1);
+        let mut swap = LevelSwap { level, rebuilt: HashMap::new(), unique: UniqueTable::default() };
+        let (weight, root) = swap.rebuild(&self.root);
+        // The root has no incoming edge, so its weight goes into both arms.
+        if (weight - ONE).norm() > TOL {
+            if let DdNode::NonTerminal { zero, one, .. } = &mut *root.borrow_mut() {
+                zero.0 *= weight;
+                one.0 *= weight;
+            }
+        }
+        self.root = root;
+
+        let (lower, upper) = (self.qubits[level], self.qubits[level + 1]);
+        self.qubits.swap(level, level + 1);
+        self.levels[lower] = level + 1;
+        self.levels[upper] = level;
+    }
+
+    /// Reorders the qubits by sifting to shrink the diagram, and returns the final
+    /// number of non-terminal nodes.
+    ///
+    /// Each qubit in turn is moved through every level with adjacent swaps and left at
+    /// the level where the diagram was smallest.
+    pub fn reorder(&mut self) -> usize {
+        let mut size = self.count_nodes().1;
+        for qubit in 0..self.num_qubits {
+            let (mut best_level, mut best_size) = (self.level_of(qubit), size);
+            while self.level_of(qubit) > 0 {
+                self.swap_levels(self.level_of(qubit) - 1);
+                size = self.count_nodes().1;
+                if size < best_size {
+                    (best_level, best_size) = (self.level_of(qubit), size);
+                }
+            }
+            while self.level_of(qubit) + 1 < self.num_qubits {
+                self.swap_levels(self.level_of(qubit));
+                size = self.count_nodes().1;
+                if size < best_size {
+                    (best_level, best_size) = (self.level_of(qubit), size);
+                }
+            }
+            while self.level_of(qubit) > best_level {
+                self.swap_levels(self.level_of(qubit) - 1);
+            }
+            size = self.count_nodes().1;
+        }
+        size
+    }
+
+    /// A copy of the state with the qubits at the same levels as in `other`, so the two
+    /// diagrams can be walked side by side.
+    pub(crate) fn copy_in_order_of(&self, other: &QuantumCircuit) -> QuantumCircuit {
+        let mut copy = QuantumCircuit::from_bytes(&self.to_bytes()).expect("a saved state loads back");
+        for (level, &qubit) in other.qubits.iter().enumerate() {
+            for l in (level..copy.level_of(qubit)).rev() {
+                copy.swap_levels(l);
+            }
+        }
+        copy
+    }
+}
+
+struct LevelSwap {
+    level: usize,
+    /// Rebuilt edge for every node at or above the upper level.
+    rebuilt: HashMap<*const RefCell<DdNode>, Edge>,
+    unique: UniqueTable,
+}
+
+impl LevelSwap {
+    fn rebuild(&mut self, node: &NodePtr) -> Edge {
+        let (qubit, zero, one) = match &*node.borrow() {
+            DdNode::NonTerminal { qubit, zero, one } if *qubit != usize::MAX && *qubit > self.level => {
+                (*qubit, zero.clone(), one.clone())
+            }
+            _ => return (ONE, node.clone()),
+        };
+        if let Some(done) = self.rebuilt.get(&Rc::as_ptr(node)) {
+            return done.clone();
+        }
+
+        let upper = self.level + 1;
+        let edge = if qubit == upper {
+            let (x0, x1) = children_at(&(ONE, node.clone()), upper);
+            let (x00, x01) = children_at(&x0, self.level);
+            let (x10, x11) = children_at(&x1, self.level);
+            let zero = self.unique.make_node(self.level, x00, x10);
+            let one = self.unique.make_node(self.level, x01, x11);
+            self.unique.make_node(upper, zero, one)
+        } else {
+            let zero = self.child(zero);
+            let one = self.child(one);
+            self.unique.make_node(qubit, zero, one)
+        };
+        self.rebuilt.insert(Rc::as_ptr(node), edge.clone());
+        edge
+    }
+
+    fn child(&mut self, edge: Edge) -> Edge {
+        if is_zero(&edge) {
+            return zero_edge();
+        }
+        let (weight, node) = self.rebuild(&edge.1);
+        (edge.0 * weight, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::QuantumCircuit;
+
+    fn assert_same_state(a: &QuantumCircuit, b: &QuantumCircuit) {
+        for (x, y) in a.get_state_vector().iter().zip(b.get_state_vector()) {
+            assert!((x - y).norm() < 1e-9, "{} != {}", x, y);
+        }
+    }
+
+    fn entangled(n: usize) -> QuantumCircuit {
+        let mut sim = QuantumCircuit::new(n);
+        for q in 0..n {
+            sim.apply_unitary(q, crate::Gate::H.matrix().unwrap());
+        }
+        sim.apply_mcu(&[0], &[], 3, crate::Gate::X.matrix().unwrap());
+        sim.apply_t(1);
+        sim.apply_mcu(&[2], &[], 0, crate::Gate::Z.matrix().unwrap());
+        sim
+    }
+
+    #[test]
+    fn test_swap_levels_keeps_state() {
+        let original = entangled(4);
+        let mut sim = entangled(4);
+        for level in [0, 1, 2, 1, 0] {
+            sim.swap_levels(level);
+            assert_same_state(&original, &sim);
+        }
+        assert_eq!(sim.qubit_at(3), 0);
+        assert_eq!(sim.level_of(3), 0);
+    }
+
+    #[test]
+    fn test_gates_after_reordering() {
+        let mut reference = entangled(4);
+        let mut sim = entangled(4);
+        sim.swap_levels(2);
+        sim.swap_levels(0);
+        for s in [&mut reference, &mut sim] {
+            s.apply_unitary(3, crate::Gate::H.matrix().unwrap());
+            s.apply_mcu(&[3], &[1], 0, crate::Gate::Y.matrix().unwrap());
+            s.apply_s(2);
+        }
+        assert_same_state(&reference, &sim);
+        let (p, q) = (sim.calculate_probabilities(0), reference.calculate_probabilities(0));
+        assert!((p.0 - q.0).abs() < 1e-9 && (p.1 - q.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cx_and_cz_with_control_below_target() {
+        let mut sim = QuantumCircuit::new(3);
+        sim.apply_x(2);
+        sim.swap_levels(1);
+        sim.swap_levels(0);
+        assert!(sim.level_of(2) < sim.level_of(0));
+        sim.apply_cnot(2, 0);
+        assert!((sim.get_state_vector()[0b101].norm() - 1.0).abs() < 1e-12);
+
+        let mut reference = entangled(4);
+        let mut sim = entangled(4);
+        sim.reorder();
+        sim.swap_levels(sim.level_of(1));
+        for s in [&mut reference, &mut sim] {
+            s.apply_cnot(1, 2);
+            s.apply_cz(0, 3);
+            s.apply_cnot(3, 0);
+        }
+        assert_same_state(&reference, &sim);
+    }
+
+    #[test]
+    fn test_reorder_does_not_grow() {
+        // Bell pairs (0, 3) and (1, 2) are far apart in the default order.
+        let mut sim = QuantumCircuit::new(4);
+        for (c, t) in [(0, 3), (1, 2)] {
+            sim.apply_unitary(c, crate::Gate::H.matrix().unwrap());
+            sim.apply_mcu(&[c], &[], t, crate::Gate::X.matrix().unwrap());
+        }
+        let expected = sim.get_state_vector();
+        let before = sim.count_nodes().1;
+        let after = sim.reorder();
+        assert!(after <= before, "{} > {}", after, before);
+        assert_eq!(after, sim.count_nodes().1);
+        for (x, y) in expected.iter().zip(sim.get_state_vector()) {
+            assert!((x - y).norm() < 1e-9);
+        }
+    }
+}
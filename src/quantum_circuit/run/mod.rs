@@ -0,0 +1,223 @@
+use crate::{Circuit, Gate, Instruction, Operation, QuantumCircuit};
+
+impl QuantumCircuit {
+    /// Runs every instruction of `circuit` on the current state and returns
+    /// the classical register (one bool per clbit, all starting at false).
+    ///
+    /// Consecutive unconditioned diagonal gates (a layer of CZs, a QAOA cost layer)
+    /// are applied together with `apply_diagonal_batch`. With `set_approximation`, the
+    /// state may be approximated after any step.
+    pub fn run(&mut self, circuit: &Circuit) -> Vec<bool> {
+        assert!(circuit.num_qubits <= self.num_qubits,
+                "Circuit uses {} qubits but the simulator has {}", circuit.num_qubits, self.num_qubits);
+        let mut clbits = vec![false; circuit.num_clbits];
+        let mut rest = &circuit.instructions[..];
+        while let Some(inst) = rest.first() {
+            let diagonal = rest.iter().take_while(|i| i.condition.is_none() && i.op.is_diagonal()).count();
+            if diagonal > 1 {
+                let ops: Vec<Operation> = rest[..diagonal].iter().map(|i| i.op.clone()).collect();
+                self.apply_diagonal_batch(&ops);
+                rest = &rest[diagonal..];
+            } else {
+                self.apply_instruction(inst, &mut clbits);
+                rest = &rest[1..];
+            }
+            self.approximate_if_needed();
+        }
+        clbits
+    }
+
+    /// Applies a single instruction, reading and writing the classical register `clbits`.
+    pub fn apply_instruction(&mut self, inst: &Instruction, clbits: &mut [bool]) {
+        if let Some(cond) = &inst.condition {
+            if !cond.is_satisfied(clbits) {
+                return;
+            }
+        }
+        match &inst.op {
+            Operation::Gate { gate, qubits } => self.apply_gate(*gate, qubits),
+            Operation::Controlled { gate, controls, neg_controls, target } => {
+                let matrix = gate.matrix().expect("Controlled gates take a single-qubit gate");
+                self.apply_mcu(controls, neg_controls, *target, matrix);
+            }
+            Operation::GlobalPhase(gamma) => self.apply_global_phase(*gamma),
+            Operation::Measure { qubit, clbit } => {
+                clbits[*clbit] = self.measure_qubit(*qubit) == 1;
+            }
+            Operation::Reset(qubit) => {
+                self.reset(*qubit);
+            }
+            Operation::Barrier(_) => {}
+        }
+    }
+
+    /// Dispatches an IR gate onto the matching `apply_*` method. U goes through
+    /// `apply_unitary`, because `apply_u` does not preserve the norm.
+    pub fn apply_gate(&mut self, gate: Gate, qubits: &[usize]) {
+        match gate {
+            Gate::H => self.apply_h(qubits[0]),
+            Gate::X => self.apply_x(qubits[0]),
+            Gate::Y => self.apply_y(qubits[0]),
+            Gate::Z => self.apply_z(qubits[0]),
+            Gate::S => self.apply_s(qubits[0]),
+            Gate::Sdg => self.apply_sdg(qubits[0]),
+            Gate::T => self.apply_t(qubits[0]),
+            Gate::Tdg => self.apply_tdg(qubits[0]),
+            Gate::P(theta) => self.apply_p(qubits[0], theta),
+            Gate::U(..) => self.apply_unitary(qubits[0], gate.matrix().unwrap()),
+            Gate::CX => self.apply_cnot(qubits[0], qubits[1]),
+            Gate::CZ => self.apply_cz(qubits[0], qubits[1]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use crate::{Circuit, Gate, Operation, QuantumCircuit, RandomCircuit};
+
+    #[test]
+    fn test_run_matches_direct_calls() {
+        let mut circ = Circuit::new(3, 0);
+        circ.h(2).cx(2, 0).t(0).x(1);
+
+        let mut direct = QuantumCircuit::new(3);
+        direct.apply_h(2);
+        direct.apply_cnot(2, 0);
+        direct.apply_t(0);
+        direct.apply_x(1);
+
+        let mut sim = QuantumCircuit::new(3);
+        sim.run(&circ);
+        let (a, b) = (sim.get_state_vector(), direct.get_state_vector());
+        for i in 0..8 {
+            assert!(approx_eq!(f64, a[i].re, b[i].re, epsilon = 1e-9)
+                && approx_eq!(f64, a[i].im, b[i].im, epsilon = 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_fused_run_matches_unfused() {
+        let mut circ = Circuit::new(4, 0);
+        circ.h(3).cx(3, 1).h(1).t(1).u(1, 0.3, 1.1, -0.4).cx(1, 0).h(0).s(0).h(0)
+            .p(2, 0.7).h(2).cz(2, 3).u(3, 1.9, 0.2, 0.5).t(3).h(3).y(2).h(2);
+
+        let mut plain = QuantumCircuit::new(4);
+        plain.run(&circ);
+        let mut fused = QuantumCircuit::new(4);
+        fused.run(&circ.fuse_single_qubit_gates());
+        assert!(circ.fuse_single_qubit_gates().len() < circ.len());
+
+        // The same state built without apply_unitary at all.
+        let mut reference = QuantumCircuit::new(4);
+        for inst in &circ.instructions {
+            match &inst.op {
+                Operation::Gate { gate, qubits } if gate.num_qubits() == 1 => {
+                    reference.apply_mcu(&[], &[], qubits[0], gate.matrix().unwrap());
+                }
+                _ => reference.apply_instruction(inst, &mut []),
+            }
+        }
+
+        let (a, b, c) = (plain.get_state_vector(), fused.get_state_vector(), reference.get_state_vector());
+        for i in 0..16 {
+            for (x, y) in [(a[i], b[i]), (a[i], c[i])] {
+                assert!(approx_eq!(f64, x.re, y.re, epsilon = 1e-9) && approx_eq!(f64, x.im, y.im, epsilon = 1e-9),
+                        "amplitude {}: {} != {}", i, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagonal_batch_matches_single_gates() {
+        // A graph state on 5 qubits followed by a QAOA-style layer of phases.
+        let mut circ = Circuit::new(5, 0);
+        for q in 0..5 {
+            circ.h(q);
+        }
+        circ.u(2, 0.4, 0.9, -1.3);
+        let mut batch = Circuit::new(5, 0);
+        batch.cz(4, 3).cz(4, 1).cz(3, 2).cz(2, 0).cz(1, 0).t(3).p(1, 0.37).sdg(4)
+            .controlled(Gate::P(1.1), &[4, 0], &[2], 3).global_phase(0.25);
+        let ops: Vec<Operation> = batch.instructions.iter().map(|i| i.op.clone()).collect();
+
+        let mut batched = QuantumCircuit::new(5);
+        batched.run(&circ);
+        batched.apply_diagonal_batch(&ops);
+
+        let mut single = QuantumCircuit::new(5);
+        single.run(&circ);
+        for inst in &batch.instructions {
+            single.apply_instruction(inst, &mut []);
+        }
+
+        let (a, b) = (batched.get_state_vector(), single.get_state_vector());
+        for i in 0..32 {
+            assert!(approx_eq!(f64, a[i].re, b[i].re, epsilon = 1e-9) && approx_eq!(f64, a[i].im, b[i].im, epsilon = 1e-9),
+                    "amplitude {}: {} != {}", i, a[i], b[i]);
+        }
+        assert!(!Operation::Gate { gate: Gate::H, qubits: vec![0] }.is_diagonal());
+    }
+
+    #[test]
+    fn test_measure_and_conditional() {
+        // q0 is flipped, measured, and the result conditionally flips q1.
+        let mut circ = Circuit::new(2, 2);
+        circ.x(0).measure(0, 0).x(1).c_if(&[0], 1).measure(1, 1);
+
+        let mut sim = QuantumCircuit::new(2);
+        let clbits = sim.run(&circ);
+        assert_eq!(clbits, vec![true, true]);
+    }
+
+    #[test]
+    fn test_conditions_on_clbits_past_64() {
+        // `c == 0` on a 70-bit register: true before bit 69 is set, false after.
+        let all: Vec<usize> = (0..70).collect();
+        let mut circ = Circuit::new(3, 70);
+        circ.x(0).c_if(&all, 0).measure(0, 69).x(1).c_if(&all, 0).x(2).c_if(&[69], 1);
+
+        let mut sim = QuantumCircuit::new(3);
+        let clbits = sim.run(&circ);
+        assert!(clbits[69]);
+        assert_eq!(sim.get_state_vector()[0b101].norm(), 1.0);
+    }
+
+    #[test]
+    fn test_measure_collapses_bell_pair() {
+        let mut circ = Circuit::new(2, 2);
+        circ.h(1).cx(1, 0).measure(1, 1).measure(0, 0);
+        for _ in 0..10 {
+            let mut sim = QuantumCircuit::new(2);
+            let clbits = sim.run(&circ);
+            assert_eq!(clbits[0], clbits[1]);
+        }
+    }
+
+    #[test]
+    fn test_random_circuits_survive_rewrites() {
+        // Fusing, optimizing and reordering must not change what a circuit computes.
+        let config = RandomCircuit::new(5, 6);
+        for seed in 0..8 {
+            let circ = Circuit::random(&config, seed);
+            let mut reference = QuantumCircuit::new(5);
+            reference.run(&circ);
+            let expected = reference.get_state_vector();
+            let norm: f64 = expected.iter().map(|a| a.norm_sqr()).sum();
+            assert!(approx_eq!(f64, norm, 1.0, epsilon = 1e-9), "seed {}: norm {}", seed, norm);
+
+            let mut reordered = QuantumCircuit::new(5);
+            reordered.reorder();
+            reordered.swap_levels(1);
+            for rewritten in [circ.fuse_single_qubit_gates(), circ.optimize()] {
+                let mut sim = QuantumCircuit::new(5);
+                sim.run(&rewritten);
+                let overlap: num::complex::Complex64 = expected.iter().zip(sim.get_state_vector()).map(|(a, b)| a.conj() * b).sum();
+                assert!(approx_eq!(f64, overlap.norm(), 1.0, epsilon = 1e-9), "seed {}: overlap {}", seed, overlap);
+            }
+            reordered.run(&circ);
+            let (a, b) = (reordered.get_state_vector(), expected);
+            assert!(a.iter().zip(&b).all(|(x, y)| (x - y).norm() < 1e-9), "seed {}: reordered run differs", seed);
+        }
+    }
+}
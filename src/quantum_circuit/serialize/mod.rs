@@ -0,0 +1,580 @@
+// Checkpoint format for decision diagrams.
+//
+// Both encodings list the non-terminal nodes children first, so every node refers
+// only to nodes before it, and shared nodes are written once. An arm is a weight and
+// a child: a zero arm (weight 0, pointing to a Terminal), the sink, or an earlier node.
+//
+// Binary (little endian):
+//   "QDDS", version: u16, num_qubits: u32, qubit at each level: num_qubits x u32,
+//   node count: u32, nodes: (level: u32, 2 x (re: f64, im: f64, child: u32)), root: u32
+// where child 0 is a zero arm, 1 is the sink and k + 2 is node k.
+//
+// JSON:
+//   {"format": "qdd_rs", "version": 1, "num_qubits": n, "order": [...],
+//    "nodes": [{"level": l, "zero": [re, im, child], "one": [re, im, child]}, ...],
+//    "root": child}
+// where child is null for a zero arm, "sink" or a node index.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use num::complex::Complex64;
+use crate::{DdNode, NodePtr, QuantumCircuit};
+use crate::constants::ZERO;
+
+const MAGIC: &[u8; 4] = b"QDDS";
+const VERSION: u16 = 1;
+
+/// Error from reading a saved decision diagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DdLoadError {
+    pub message: String,
+}
+
+impl DdLoadError {
+    fn new(message: impl Into<String>) -> Self {
+        DdLoadError { message: message.into() }
+    }
+}
+
+impl fmt::Display for DdLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for DdLoadError {}
+
+/// Where an arm leads, as stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Child {
+    Zero,
+    Sink,
+    Node(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Arm {
+    weight: Complex64,
+    child: Child,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    level: usize,
+    zero: Arm,
+    one: Arm,
+}
+
+/// The flat form both encodings read and write.
+struct Table {
+    num_qubits: usize,
+    order: Vec<usize>,
+    nodes: Vec<Node>,
+    root: Child,
+}
+
+impl QuantumCircuit {
+    /// Writes the state to `path`: as JSON if the path ends in `.json`, in the compact
+    /// binary format otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|e| e == "json") {
+            std::fs::write(path, self.to_json())
+        } else {
+            std::fs::write(path, self.to_bytes())
+        }
+    }
+
+    /// Reads a state written by `save`, telling the two formats apart by the binary
+    /// header.
+    pub fn load(path: impl AsRef<Path>) -> Result<QuantumCircuit, DdLoadError> {
+        let data = std::fs::read(path.as_ref())
+            .map_err(|e| DdLoadError::new(format!("{}: {}", path.as_ref().display(), e)))?;
+        if data.starts_with(MAGIC) {
+            QuantumCircuit::from_bytes(&data)
+        } else {
+            let text = String::from_utf8(data).map_err(|_| DdLoadError::new("not a qdd_rs file"))?;
+            QuantumCircuit::from_json(&text)
+        }
+    }
+
+    /// Encodes the diagram, the qubit order and `num_qubits` in the binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let table = self.to_table();
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(table.num_qubits as u32).to_le_bytes());
+        for &q in &table.order {
+            out.extend_from_slice(&(q as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&(table.nodes.len() as u32).to_le_bytes());
+        for node in &table.nodes {
+            out.extend_from_slice(&(node.level as u32).to_le_bytes());
+            for arm in [&node.zero, &node.one] {
+                out.extend_from_slice(&arm.weight.re.to_le_bytes());
+                out.extend_from_slice(&arm.weight.im.to_le_bytes());
+                out.extend_from_slice(&child_code(arm.child).to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&child_code(table.root).to_le_bytes());
+        out
+    }
+
+    /// Decodes and validates a diagram written by `to_bytes`.
+    pub fn from_bytes(data: &[u8]) -> Result<QuantumCircuit, DdLoadError> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(DdLoadError::new("not a qdd_rs binary file"));
+        }
+        let version = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(DdLoadError::new(format!("unsupported format version {}", version)));
+        }
+        let num_qubits = reader.u32()? as usize;
+        let order = (0..num_qubits).map(|_| reader.u32().map(|q| q as usize)).collect::<Result<_, _>>()?;
+        let count = reader.u32()? as usize;
+        let mut nodes = Vec::new();
+        for _ in 0..count {
+            let level = reader.u32()? as usize;
+            let zero = Arm { weight: reader.complex()?, child: child_from_code(reader.u32()?) };
+            let one = Arm { weight: reader.complex()?, child: child_from_code(reader.u32()?) };
+            nodes.push(Node { level, zero, one });
+        }
+        let root = child_from_code(reader.u32()?);
+        if reader.pos != data.len() {
+            return Err(DdLoadError::new("trailing bytes after the root"));
+        }
+        QuantumCircuit::from_table(Table { num_qubits, order, nodes, root })
+    }
+
+    /// Encodes the diagram as human-readable JSON; see the module comment for the layout.
+    pub fn to_json(&self) -> String {
+        let table = self.to_table();
+        let child = |c: Child| match c {
+            Child::Zero => "null".to_string(),
+            Child::Sink => "\"sink\"".to_string(),
+            Child::Node(k) => k.to_string(),
+        };
+        let arm = |a: &Arm| format!("[{:?}, {:?}, {}]", a.weight.re, a.weight.im, child(a.child));
+        let nodes: Vec<String> = table.nodes.iter()
+            .map(|n| format!("    {{\"level\": {}, \"zero\": {}, \"one\": {}}}", n.level, arm(&n.zero), arm(&n.one)))
+            .collect();
+        let order: Vec<String> = table.order.iter().map(|q| q.to_string()).collect();
+        format!(
+            "{{\n  \"format\": \"qdd_rs\",\n  \"version\": {},\n  \"num_qubits\": {},\n  \"order\": [{}],\n  \"nodes\": [\n{}\n  ],\n  \"root\": {}\n}}\n",
+            VERSION, table.num_qubits, order.join(", "), nodes.join(",\n"), child(table.root),
+        )
+    }
+
+    /// Decodes and validates a diagram written by `to_json`.
+    pub fn from_json(text: &str) -> Result<QuantumCircuit, DdLoadError> {
+        let value = JsonParser { chars: text.chars().collect(), pos: 0 }.parse_document()?;
+        if value.field("format")?.as_str()? != "qdd_rs" {
+            return Err(DdLoadError::new("not a qdd_rs JSON file"));
+        }
+        let version = value.field("version")?.as_usize()?;
+        if version != VERSION as usize {
+            return Err(DdLoadError::new(format!("unsupported format version {}", version)));
+        }
+        let num_qubits = value.field("num_qubits")?.as_usize()?;
+        let order = value.field("order")?.as_array()?.iter().map(Json::as_usize).collect::<Result<_, _>>()?;
+        let arm = |json: &Json| -> Result<Arm, DdLoadError> {
+            match json.as_array()? {
+                [re, im, child] => Ok(Arm { weight: Complex64::new(re.as_f64()?, im.as_f64()?), child: child.as_child()? }),
+                _ => Err(DdLoadError::new("an arm must be [re, im, child]")),
+            }
+        };
+        let mut nodes = Vec::new();
+        for node in value.field("nodes")?.as_array()? {
+            nodes.push(Node {
+                level: node.field("level")?.as_usize()?,
+                zero: arm(node.field("zero")?)?,
+                one: arm(node.field("one")?)?,
+            });
+        }
+        let root = value.field("root")?.as_child()?;
+        QuantumCircuit::from_table(Table { num_qubits, order, nodes, root })
+    }
+
+    fn to_table(&self) -> Table {
+        let mut table = Table { num_qubits: self.num_qubits, order: self.qubits.clone(), nodes: Vec::new(), root: Child::Zero };
+        let mut index = HashMap::new();
+        table.root = flatten(&self.root, &mut table.nodes, &mut index);
+        table
+    }
+
+    fn from_table(table: Table) -> Result<QuantumCircuit, DdLoadError> {
+        let n = table.num_qubits;
+        // Check the length first: `num_qubits` is untrusted and sizes the allocation below.
+        if table.order.len() != n {
+            return Err(DdLoadError::new("the qubit order is not a permutation of the qubits"));
+        }
+        let mut seen = vec![false; n];
+        if table.order.iter().any(|&q| q >= n || std::mem::replace(&mut seen[q], true)) {
+            return Err(DdLoadError::new("the qubit order is not a permutation of the qubits"));
+        }
+
+        let terminal = || Rc::new(RefCell::new(DdNode::Terminal(ZERO)));
+        let sink = Rc::new(RefCell::new(DdNode::NonTerminal {
+            qubit: usize::MAX,
+            zero: (ZERO, terminal()),
+            one: (ZERO, terminal()),
+        }));
+        let mut built: Vec<NodePtr> = Vec::with_capacity(table.nodes.len());
+        // Resolves an arm of a node at `level`; arms must lead exactly one level down.
+        let resolve = |arm: &Arm, level: usize, built: &[NodePtr], nodes: &[Node]| -> Result<(Complex64, NodePtr), DdLoadError> {
+            if !arm.weight.re.is_finite() || !arm.weight.im.is_finite() {
+                return Err(DdLoadError::new(format!("non-finite edge weight at level {}", level)));
+            }
+            let child = match arm.child {
+                Child::Zero => return Ok((ZERO, terminal())),
+                Child::Sink if level == 0 => sink.clone(),
+                Child::Node(k) if k < built.len() && level > 0 && nodes[k].level == level - 1 => built[k].clone(),
+                Child::Sink | Child::Node(_) => {
+                    return Err(DdLoadError::new(format!("a node at level {} must point to level {}", level, level as isize - 1)));
+                }
+            };
+            Ok((arm.weight, child))
+        };
+        for node in &table.nodes {
+            if node.level >= n {
+                return Err(DdLoadError::new(format!("node level {} is out of range for {} qubits", node.level, n)));
+            }
+            let zero = resolve(&node.zero, node.level, &built, &table.nodes)?;
+            let one = resolve(&node.one, node.level, &built, &table.nodes)?;
+            built.push(Rc::new(RefCell::new(DdNode::NonTerminal { qubit: node.level, zero, one })));
+        }
+        let root = match table.root {
+            Child::Sink if n == 0 => sink,
+            Child::Node(k) if k < built.len() && table.nodes[k].level + 1 == n => built[k].clone(),
+            _ => return Err(DdLoadError::new(format!("the root must be a node at level {}", n as isize - 1))),
+        };
+
+        let mut sim = QuantumCircuit::from_root(root, n);
+        for (level, &q) in table.order.iter().enumerate() {
+            sim.qubits[level] = q;
+            sim.levels[q] = level;
+        }
+        Ok(sim)
+    }
+}
+
+/// Appends `node` and everything below it to `nodes`, children first, and returns
+/// its reference. Zero arms are stored without a child.
+fn flatten(node: &NodePtr, nodes: &mut Vec<Node>, index: &mut HashMap<*const RefCell<DdNode>, usize>) -> Child {
+    if let Some(&k) = index.get(&Rc::as_ptr(node)) {
+        return Child::Node(k);
+    }
+    let (level, zero, one) = match &*node.borrow() {
+        DdNode::Terminal(_) => return Child::Zero,
+        DdNode::NonTerminal { qubit, .. } if *qubit == usize::MAX => return Child::Sink,
+        DdNode::NonTerminal { qubit, zero, one } => (*qubit, zero.clone(), one.clone()),
+    };
+    let mut arm = |(weight, child): (Complex64, NodePtr)| {
+        match flatten(&child, nodes, index) {
+            Child::Zero => Arm { weight: ZERO, child: Child::Zero },
+            _ if weight == ZERO => Arm { weight: ZERO, child: Child::Zero },
+            child => Arm { weight, child },
+        }
+    };
+    let (zero, one) = (arm(zero), arm(one));
+    nodes.push(Node { level, zero, one });
+    index.insert(Rc::as_ptr(node), nodes.len() - 1);
+    Child::Node(nodes.len() - 1)
+}
+
+fn child_code(child: Child) -> u32 {
+    match child {
+        Child::Zero => 0,
+        Child::Sink => 1,
+        Child::Node(k) => k as u32 + 2,
+    }
+}
+
+fn child_from_code(code: u32) -> Child {
+    match code {
+        0 => Child::Zero,
+        1 => Child::Sink,
+        k => Child::Node(k as usize - 2),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], DdLoadError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or_else(|| DdLoadError::new("unexpected end of file"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, DdLoadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn complex(&mut self) -> Result<Complex64, DdLoadError> {
+        let re = f64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        let im = f64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        Ok(Complex64::new(re, im))
+    }
+}
+
+/// Just enough JSON to read the files `to_json` writes.
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn field(&self, name: &str) -> Result<&Json, DdLoadError> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+                .ok_or_else(|| DdLoadError::new(format!("missing field '{}'", name))),
+            _ => Err(DdLoadError::new(format!("expected an object with field '{}'", name))),
+        }
+    }
+
+    fn as_f64(&self) -> Result<f64, DdLoadError> {
+        match self {
+            Json::Number(x) => Ok(*x),
+            _ => Err(DdLoadError::new("expected a number")),
+        }
+    }
+
+    fn as_usize(&self) -> Result<usize, DdLoadError> {
+        match self {
+            Json::Number(x) if *x >= 0.0 && x.fract() == 0.0 => Ok(*x as usize),
+            _ => Err(DdLoadError::new("expected a non-negative integer")),
+        }
+    }
+
+    fn as_str(&self) -> Result<&str, DdLoadError> {
+        match self {
+            Json::String(s) => Ok(s),
+            _ => Err(DdLoadError::new("expected a string")),
+        }
+    }
+
+    fn as_array(&self) -> Result<&[Json], DdLoadError> {
+        match self {
+            Json::Array(items) => Ok(items),
+            _ => Err(DdLoadError::new("expected an array")),
+        }
+    }
+
+    fn as_child(&self) -> Result<Child, DdLoadError> {
+        match self {
+            Json::Null => Ok(Child::Zero),
+            Json::String(s) if s == "sink" => Ok(Child::Sink),
+            _ => self.as_usize().map(Child::Node),
+        }
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl JsonParser {
+    fn parse_document(mut self) -> Result<Json, DdLoadError> {
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        if self.pos != self.chars.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, message: &str) -> DdLoadError {
+        DdLoadError::new(format!("invalid JSON at character {}: {}", self.pos, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), DdLoadError> {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, DdLoadError> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some('{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.chars.get(self.pos) == Some(&'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.parse_string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.parse_value()?));
+                    self.skip_whitespace();
+                    match self.chars.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some('}') => { self.pos += 1; return Ok(Json::Object(fields)); }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.chars.get(self.pos) == Some(&']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.parse_value()?);
+                    self.skip_whitespace();
+                    match self.chars.get(self.pos) {
+                        Some(',') => self.pos += 1,
+                        Some(']') => { self.pos += 1; return Ok(Json::Array(items)); }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some('"') => self.parse_string().map(Json::String),
+            Some('n') if self.chars[self.pos..].starts_with(&['n', 'u', 'l', 'l']) => {
+                self.pos += 4;
+                Ok(Json::Null)
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                text.parse().map(Json::Number).map_err(|_| self.error("invalid number"))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, DdLoadError> {
+        if self.chars.get(self.pos) != Some(&'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            match self.chars.get(self.pos) {
+                Some('"') => { self.pos += 1; return Ok(out); }
+                Some('\\') => {
+                    let escaped = self.chars.get(self.pos + 1).copied().ok_or_else(|| self.error("unterminated string"))?;
+                    out.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        c => c,
+                    });
+                    self.pos += 2;
+                }
+                Some(c) => { out.push(*c); self.pos += 1; }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Gate, QuantumCircuit};
+
+    fn prepared() -> QuantumCircuit {
+        let mut sim = QuantumCircuit::new(4);
+        sim.apply_unitary(3, Gate::H.matrix().unwrap());
+        sim.apply_mcu(&[3], &[], 1, Gate::X.matrix().unwrap());
+        sim.apply_unitary(0, Gate::U(0.3, 0.2, -0.7).matrix().unwrap());
+        sim.apply_t(1);
+        sim.swap_levels(1);
+        sim
+    }
+
+    fn assert_same(a: &QuantumCircuit, b: &QuantumCircuit) {
+        assert_eq!(a.num_qubits, b.num_qubits);
+        assert_eq!(a.count_nodes().1, b.count_nodes().1);
+        assert_eq!(a.get_state_vector(), b.get_state_vector());
+        assert_eq!(a.level_of(1), b.level_of(1));
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let sim = prepared();
+        let bytes = sim.to_bytes();
+        assert_eq!(&bytes[..4], b"QDDS");
+        let loaded = QuantumCircuit::from_bytes(&bytes).unwrap();
+        assert_same(&sim, &loaded);
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let sim = prepared();
+        let json = sim.to_json();
+        let mut loaded = QuantumCircuit::from_json(&json).unwrap();
+        assert_same(&sim, &loaded);
+        assert_eq!(loaded.to_json(), json);
+
+        // The loaded state keeps working as a simulator.
+        let mut original = prepared();
+        for s in [&mut original, &mut loaded] {
+            s.apply_mcu(&[1], &[], 2, Gate::X.matrix().unwrap());
+        }
+        assert_eq!(original.get_state_vector(), loaded.get_state_vector());
+    }
+
+    #[test]
+    fn test_save_and_load_files() {
+        let sim = prepared();
+        let dir = std::env::temp_dir();
+        for name in ["qdd_rs_checkpoint.bin", "qdd_rs_checkpoint.json"] {
+            let path = dir.join(format!("{}_{}", std::process::id(), name));
+            sim.save(&path).unwrap();
+            let loaded = QuantumCircuit::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_same(&sim, &loaded);
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_input() {
+        let bytes = prepared().to_bytes();
+        assert!(QuantumCircuit::from_bytes(&bytes[..bytes.len() - 1]).err().unwrap().message.contains("end of file"));
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 9;
+        assert!(QuantumCircuit::from_bytes(&wrong_version).err().unwrap().message.contains("version 9"));
+
+        let json = prepared().to_json();
+        let bad_order = json.replace("\"order\": [", "\"order\": [0, ");
+        assert!(QuantumCircuit::from_json(&bad_order).err().unwrap().message.contains("permutation"));
+        let huge = json.replace(&format!("\"num_qubits\": {}", prepared().num_qubits), "\"num_qubits\": 1000000000000000000");
+        assert!(QuantumCircuit::from_json(&huge).err().unwrap().message.contains("permutation"));
+        let bad_level = json.replacen("\"level\": 0", "\"level\": 2", 1);
+        assert!(QuantumCircuit::from_json(&bad_level).is_err());
+        assert!(QuantumCircuit::from_json("{\"format\": \"qdd_rs\"").is_err());
+    }
+}
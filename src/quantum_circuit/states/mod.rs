@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use num::complex::Complex64;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::{NodePtr, QuantumCircuit};
+use crate::constants::ONE;
+use crate::gates::apply_mcu::{make_node, zero_edge, Edge};
+use super::new::new_sink;
+
+impl QuantumCircuit {
+    /// The computational basis state `bits`, written with the highest qubit first as
+    /// in the state vector index (so `"100"` is qubit 2 in |1⟩).
+    pub fn basis_state(bits: &str) -> Self {
+        let num_qubits = bits.len();
+        let mut edge = (ONE, new_sink());
+        for (level, bit) in bits.chars().rev().enumerate() {
+            let one = match bit {
+                '0' => false,
+                '1' => true,
+                _ => panic!("Invalid bit '{}' in basis state '{}'", bit, bits),
+            };
+            edge = if one { make_node(level, zero_edge(), edge) } else { make_node(level, edge, zero_edge()) };
+        }
+        Self::from_root_edge(edge, num_qubits)
+    }
+
+    /// The product state with qubit `q` at Bloch angles `angles[q] = (theta, phi)`,
+    /// i.e. cos(theta/2)|0⟩ + e^(i*phi) sin(theta/2)|1⟩. One node per qubit.
+    pub fn product_state(angles: &[(f64, f64)]) -> Self {
+        let mut edge = (ONE, new_sink());
+        for (level, &(theta, phi)) in angles.iter().enumerate() {
+            let zero = (Complex64::new((theta / 2.0).cos(), 0.0), edge.1.clone());
+            let one = (Complex64::from_polar((theta / 2.0).sin(), phi), edge.1.clone());
+            edge = make_node(level, zero, one);
+        }
+        Self::from_root_edge(edge, angles.len())
+    }
+
+    /// The uniform superposition |+⟩^⊗n over all 2^n basis states.
+    pub fn uniform(num_qubits: usize) -> Self {
+        Self::product_state(&vec![(std::f64::consts::FRAC_PI_2, 0.0); num_qubits])
+    }
+
+    /// The GHZ state (|0...0⟩ + |1...1⟩)/√2: two chains of nodes below the root.
+    pub fn ghz(num_qubits: usize) -> Self {
+        assert!(num_qubits > 0, "A GHZ state needs at least one qubit");
+        let top = num_qubits - 1;
+        let h = Complex64::new(std::f64::consts::FRAC_1_SQRT_2, 0.0);
+        let (mut zeros, mut ones) = (new_sink(), new_sink());
+        for level in 0..top {
+            zeros = make_node(level, (ONE, zeros), zero_edge()).1;
+            ones = make_node(level, zero_edge(), (ONE, ones)).1;
+        }
+        Self::from_root_edge(make_node(top, (h, zeros), (h, ones)), num_qubits)
+    }
+
+    /// A Haar-random `num_qubits`-qubit state, the same for the same `seed`.
+    ///
+    /// The amplitudes are independent complex Gaussians, normalized, which is uniform
+    /// over the unit sphere. The diagram is built with `from_state_vector`, so it needs
+    /// the full 2^n vector in memory.
+    pub fn random_state(num_qubits: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let amplitudes: Vec<Complex64> = (0..1usize << num_qubits)
+            .map(|_| {
+                // Box-Muller: a radius and an angle give two independent normal samples.
+                let radius = (-2.0 * (1.0 - rng.random::<f64>()).ln()).sqrt();
+                Complex64::from_polar(radius, 2.0 * std::f64::consts::PI * rng.random::<f64>())
+            })
+            .collect();
+        Self::from_unnormalized_state_vector(&amplitudes).expect("a Gaussian vector is non-zero")
+    }
+
+    /// The W state: the equal superposition of the `num_qubits` basis states with a
+    /// single qubit in |1⟩.
+    pub fn w_state(num_qubits: usize) -> Self {
+        Self::dicke(num_qubits, 1)
+    }
+
+    /// The Dicke state |D(n, k)⟩: the equal superposition of all basis states with
+    /// exactly `k` qubits in |1⟩.
+    ///
+    /// The node at level `l` that still needs `r` ones among the qubits at or below it
+    /// is shared by all paths above, so the diagram has O(n·k) nodes.
+    pub fn dicke(num_qubits: usize, k: usize) -> Self {
+        assert!(k <= num_qubits && num_qubits > 0, "No Dicke state with {} of {} qubits excited", k, num_qubits);
+        let sink = new_sink();
+        let root = dicke_node(num_qubits - 1, k, &sink, &mut HashMap::new());
+        Self::from_root_edge((ONE, root), num_qubits)
+    }
+}
+
+/// Node at `level` for the normalized equal superposition of the strings over levels
+/// `0..=level` with `ones` ones. Each arm weight is the square root of the fraction of
+/// those strings that take it.
+fn dicke_node(level: usize, ones: usize, sink: &NodePtr, memo: &mut HashMap<(usize, usize), NodePtr>) -> NodePtr {
+    if let Some(node) = memo.get(&(level, ones)) {
+        return node.clone();
+    }
+    let total = binomial(level + 1, ones);
+    let mut arm = |bit: usize| -> Edge {
+        if bit > ones || binomial(level, ones - bit) == 0.0 {
+            return zero_edge();
+        }
+        let weight = Complex64::new((binomial(level, ones - bit) / total).sqrt(), 0.0);
+        let child = if level == 0 { sink.clone() } else { dicke_node(level - 1, ones - bit, sink, memo) };
+        (weight, child)
+    };
+    let (zero, one) = (arm(0), arm(1));
+    let node = make_node(level, zero, one).1;
+    memo.insert((level, ones), node.clone());
+    node
+}
+
+/// n choose k as a float, so that large n do not overflow.
+fn binomial(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use num::complex::Complex64;
+    use crate::QuantumCircuit;
+    use crate::constants::ZERO;
+
+    fn assert_close(a: &[Complex64], b: &[Complex64]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).norm() < 1e-12, "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_basis_and_product_states() {
+        let basis = QuantumCircuit::basis_state("110");
+        let mut expected = vec![ZERO; 8];
+        expected[0b110] = Complex64::new(1.0, 0.0);
+        assert_close(&basis.get_state_vector(), &expected);
+        assert_eq!(basis.count_nodes().1, 4);
+
+        let (theta, phi) = (0.8f64, 0.3f64);
+        let product = QuantumCircuit::product_state(&[(theta, phi), (std::f64::consts::PI, 0.0)]);
+        let (c, s) = ((theta / 2.0).cos(), Complex64::from_polar((theta / 2.0).sin(), phi));
+        assert_close(&product.get_state_vector(), &[ZERO, ZERO, Complex64::new(c, 0.0), s]);
+
+        let uniform = QuantumCircuit::uniform(3);
+        assert_close(&uniform.get_state_vector(), &[Complex64::new(1.0 / 8f64.sqrt(), 0.0); 8]);
+        assert_eq!(uniform.count_nodes().1, 4);
+    }
+
+    #[test]
+    fn test_ghz_and_w_states() {
+        let h = std::f64::consts::FRAC_1_SQRT_2;
+        let ghz = QuantumCircuit::ghz(4).get_state_vector();
+        assert!((ghz[0].re - h).abs() < 1e-12 && (ghz[15].re - h).abs() < 1e-12);
+        assert!(ghz[1..15].iter().all(|a| a.norm() == 0.0));
+
+        let w = QuantumCircuit::w_state(3).get_state_vector();
+        for (i, a) in w.iter().enumerate() {
+            let expected = if (i as u32).count_ones() == 1 { 1.0 / 3f64.sqrt() } else { 0.0 };
+            assert!((a.re - expected).abs() < 1e-12 && a.im == 0.0, "{}: {}", i, a);
+        }
+    }
+
+    #[test]
+    fn test_dicke_state() {
+        let (n, k) = (6, 3);
+        let sim = QuantumCircuit::dicke(n, k);
+        let state = sim.get_state_vector();
+        let amplitude = 1.0 / 20f64.sqrt();
+        for (i, a) in state.iter().enumerate() {
+            let expected = if (i as u32).count_ones() == k as u32 { amplitude } else { 0.0 };
+            assert!((a.re - expected).abs() < 1e-12, "{}: {}", i, a);
+        }
+        // One node per (level, ones still needed) that can still be completed.
+        assert!(sim.count_nodes().1 <= (n * (k + 1)) + 1);
+
+        let mut measured = QuantumCircuit::dicke(n, k);
+        assert_eq!((0..n).map(|q| measured.measure_qubit(q) as usize).sum::<usize>(), k);
+    }
+
+    #[test]
+    fn test_random_state() {
+        let a = QuantumCircuit::random_state(4, 7).get_state_vector();
+        let norm: f64 = a.iter().map(|x| x.norm_sqr()).sum();
+        assert!((norm - 1.0).abs() < 1e-12);
+        assert_close(&a, &QuantumCircuit::random_state(4, 7).get_state_vector());
+        let b = QuantumCircuit::random_state(4, 8).get_state_vector();
+        assert!(a.iter().zip(&b).any(|(x, y)| (x - y).norm() > 1e-3));
+        // Generic amplitudes leave nothing to share.
+        assert_eq!(QuantumCircuit::random_state(4, 7).count_nodes().1, 16);
+    }
+}
@@ -49,7 +49,7 @@ impl QuantumCircuit {
                     // Here, the accumulated amplitude is considered final.
                     self.distribute_amplitude(current_amplitude, current_index, mask, state_vector);
                 } else {
-                    let q = *qubit;
+                    let q = self.qubit_at(*qubit);
                     // Mark that qubit q has been decided.
                     let new_mask = mask | (1 << q);
                     // Process the zero branch:
@@ -159,10 +159,7 @@ mod tests {
             one: (ONE, terminal.clone()),
         }));
 
-        let sim = QuantumCircuit {
-            root: q1,
-            num_qubits: 2,
-        };
+        let sim = QuantumCircuit::from_root(q1, 2);
 
         let state = sim.get_state_vector();
         let expected = Complex64::new(2.0, 0.0); // 1.0 * 1.0 * 2 combinations
@@ -190,10 +187,7 @@ mod tests {
             one: (ONE, q1.clone()),
         }));
 
-        let sim = QuantumCircuit {
-            root: q2,
-            num_qubits: 3,
-        };
+        let sim = QuantumCircuit::from_root(q2, 3);
 
         let state = sim.get_state_vector();
         let expected = ZERO;
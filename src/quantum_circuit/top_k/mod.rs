@@ -0,0 +1,176 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use num::complex::Complex64;
+use crate::{DdNode, NodePtr, QuantumCircuit};
+use crate::constants::ONE;
+use super::measure::subtree_norm_sqr;
+
+/// A partial path from the root in the best-first search of `top_k`.
+struct Path {
+    /// Squared norm of everything below the path: |amplitude|² times the sub-diagram norm.
+    bound: f64,
+    amplitude: Complex64,
+    node: NodePtr,
+    /// Values of the qubits decided so far, by logical qubit; the rest are still `false`.
+    bits: Vec<bool>,
+}
+
+impl PartialEq for Path {
+    fn eq(&self, other: &Self) -> bool {
+        self.bound == other.bound
+    }
+}
+
+impl Eq for Path {}
+
+impl PartialOrd for Path {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Path {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.bound.total_cmp(&other.bound)
+    }
+}
+
+impl QuantumCircuit {
+    /// The `k` basis states with the largest probabilities, most likely first, with their
+    /// amplitudes. Entry `q` of each basis state is the value of qubit `q`, so registers of
+    /// any width are covered. Fewer than `k` are returned if fewer have nonzero amplitude.
+    ///
+    /// Paths from the root are expanded best-first, keyed by the squared norm of the
+    /// state below them. That key bounds every basis state the path can still reach,
+    /// so a path that arrives at the sink is the most probable outcome left, and only
+    /// the branches that can compete with the top `k` are ever expanded.
+    pub fn top_k(&self, k: usize) -> Vec<(Vec<bool>, Complex64)> {
+        let mut norms = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        let bound = subtree_norm_sqr(&self.root, &mut norms);
+        heap.push(Path { bound, amplitude: ONE, node: self.root.clone(), bits: vec![false; self.num_qubits] });
+
+        let mut found = Vec::new();
+        while found.len() < k {
+            let Some(path) = heap.pop() else {
+                break;
+            };
+            if path.bound == 0.0 {
+                break;
+            }
+            match &*path.node.borrow() {
+                DdNode::NonTerminal { qubit, .. } if *qubit == usize::MAX => found.push((path.bits.clone(), path.amplitude)),
+                DdNode::NonTerminal { qubit, zero, one } => {
+                    for (edge, value) in [(zero, false), (one, true)] {
+                        let amplitude = path.amplitude * edge.0;
+                        let bound = amplitude.norm_sqr() * subtree_norm_sqr(&edge.1, &mut norms);
+                        if bound > 0.0 {
+                            let mut bits = path.bits.clone();
+                            bits[self.qubit_at(*qubit)] = value;
+                            heap.push(Path { bound, amplitude, node: edge.1.clone(), bits });
+                        }
+                    }
+                }
+                DdNode::Terminal(_) => {}
+            };
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use num::complex::Complex64;
+    use crate::QuantumCircuit;
+
+    /// Index into `get_state_vector` of a basis state returned by `top_k`.
+    fn index(bits: &[bool]) -> usize {
+        bits.iter().enumerate().map(|(q, &b)| (b as usize) << q).sum()
+    }
+
+    #[test]
+    fn test_top_k_of_simple_states() {
+        let ghz = QuantumCircuit::ghz(40);
+        let top = ghz.top_k(3);
+        assert_eq!(top.len(), 2);
+        let mut indices: Vec<usize> = top.iter().map(|(bits, _)| index(bits)).collect();
+        indices.sort();
+        assert_eq!(indices, vec![0, (1 << 40) - 1]);
+        assert!(top.iter().all(|(_, a)| (a.norm_sqr() - 0.5).abs() < 1e-12));
+
+        let mut sim = QuantumCircuit::new(3);
+        sim.apply_x(1);
+        assert_eq!(sim.top_k(4).len(), 1);
+        assert_eq!(sim.top_k(4)[0].0, [false, true, false]);
+        assert!(sim.top_k(0).is_empty());
+    }
+
+    #[test]
+    fn test_light_branch_with_the_top_outcome() {
+        // Qubit 3 = 0 carries 0.4 on one basis state; qubit 3 = 1 carries 0.6 spread over
+        // four. The search has to come back to the lighter branch for the top outcome.
+        let (a, b) = (Complex64::new(0.4f64.sqrt(), 0.0), Complex64::new(0.0, 0.15f64.sqrt()));
+        let amplitudes = HashMap::from([(0b0101, a), (0b1000, b), (0b1011, b), (0b1100, b), (0b1111, b)]);
+        for swaps in [vec![], vec![2], vec![0, 1, 2]] {
+            let mut sim = QuantumCircuit::from_sparse(amplitudes.clone(), 4).unwrap();
+            for level in swaps.iter().copied() {
+                sim.swap_levels(level);
+            }
+            let top = sim.top_k(3);
+            assert_eq!(index(&top[0].0), 0b0101, "swaps {:?}", swaps);
+            assert!((top[0].1 - a).norm() < 1e-12);
+            for (bits, amplitude) in &top[1..] {
+                assert!((amplitudes[&index(bits)] - amplitude).norm() < 1e-12);
+            }
+            assert_eq!(sim.top_k(10).len(), 5);
+        }
+    }
+
+    #[test]
+    fn test_ranking_of_biased_product_states() {
+        // Each qubit is |1⟩ with its own probability, so every outcome's probability is a
+        // product and the ranking is known without building the state vector.
+        let thetas = [0.3, 2.6, 1.2, 1.9, 0.7];
+        let p1: Vec<f64> = thetas.iter().map(|t: &f64| (t / 2.0).sin().powi(2)).collect();
+        let probability = |bits: &[bool]| -> f64 {
+            bits.iter().zip(&p1).map(|(&b, p)| if b { *p } else { 1.0 - p }).product()
+        };
+        let mut ranked: Vec<f64> = (0..32usize)
+            .map(|i| probability(&(0..5).map(|q| i >> q & 1 == 1).collect::<Vec<_>>()))
+            .collect();
+        ranked.sort_by(|a, b| b.total_cmp(a));
+
+        for swaps in [vec![], vec![3, 1], vec![0, 1, 2, 3]] {
+            let mut sim = QuantumCircuit::product_state(&thetas.map(|t| (t, 0.0)));
+            for level in swaps.iter().copied() {
+                sim.swap_levels(level);
+            }
+            let top = sim.top_k(6);
+            assert_eq!(top.len(), 6);
+            for (rank, (bits, amplitude)) in top.iter().enumerate() {
+                assert!((amplitude.norm_sqr() - probability(bits)).abs() < 1e-12, "swaps {:?}", swaps);
+                assert!((amplitude.norm_sqr() - ranked[rank]).abs() < 1e-12, "swaps {:?}, rank {}", swaps, rank);
+            }
+        }
+    }
+
+    #[test]
+    fn test_top_k_beyond_index_bits() {
+        // 70 qubits: the all-ones outcome does not fit in a usize index.
+        let mut sim = QuantumCircuit::new(70);
+        sim.apply_h(0);
+        sim.apply_x(69);
+        sim.apply_cnot(0, 65);
+        let top = sim.top_k(2);
+        assert_eq!(top.len(), 2);
+        for (bits, amplitude) in &top {
+            assert_eq!(bits.len(), 70);
+            assert!(bits[69] && bits[0] == bits[65]);
+            assert!(bits.iter().filter(|&&b| b).count() == 1 + 2 * bits[0] as usize);
+            assert!((amplitude.norm_sqr() - 0.5).abs() < 1e-12);
+        }
+        assert_ne!(top[0].0, top[1].0);
+        assert_eq!(QuantumCircuit::ghz(70).top_k(1)[0].0.len(), 70);
+    }
+}
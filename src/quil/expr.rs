@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use num::complex::Complex64;
+
+/// Quil arithmetic expression. Values are complex so that `DEFGATE` matrix entries
+/// such as `-i*sin(%theta/2)` or `1.0i` can be written directly.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Num(Complex64),
+    /// `%name`, a parameter of the enclosing `DEFGATE`.
+    Param(String),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+    Call(String, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, params: &HashMap<String, f64>) -> Result<Complex64, String> {
+        Ok(match self {
+            Expr::Num(x) => *x,
+            Expr::Param(name) => match params.get(name) {
+                Some(v) => Complex64::new(*v, 0.0),
+                None => return Err(format!("unknown parameter '%{}'", name)),
+            },
+            Expr::Neg(e) => -e.eval(params)?,
+            Expr::Bin(op, a, b) => {
+                let (a, b) = (a.eval(params)?, b.eval(params)?);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powc(b),
+                }
+            }
+            Expr::Call(func, arg) => {
+                let x = arg.eval(params)?;
+                match func.as_str() {
+                    "sin" => x.sin(),
+                    "cos" => x.cos(),
+                    "sqrt" => x.sqrt(),
+                    "exp" => x.exp(),
+                    "cis" => (Complex64::i() * x).exp(),
+                    _ => unreachable!(),
+                }
+            }
+        })
+    }
+
+    /// Evaluates an expression that must come out real, such as a gate parameter.
+    pub fn eval_real(&self, params: &HashMap<String, f64>) -> Result<f64, String> {
+        let v = self.eval(params)?;
+        if v.im.abs() > 1e-12 {
+            return Err(format!("expected a real value, got {}", v));
+        }
+        Ok(v.re)
+    }
+}
+
+const FUNCTIONS: [&str; 5] = ["sin", "cos", "sqrt", "exp", "cis"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(Complex64),
+    Ident(String),
+    Param(String),
+    Sym(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Tok>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let value: f64 = literal.parse().map_err(|_| format!("invalid number '{}'", literal))?;
+            // A trailing `i` makes the literal imaginary, as in `1.5i`.
+            if i < chars.len() && chars[i] == 'i' && !chars.get(i + 1).is_some_and(|c| c.is_alphanumeric()) {
+                i += 1;
+                tokens.push(Tok::Num(Complex64::new(0.0, value)));
+            } else {
+                tokens.push(Tok::Num(Complex64::new(value, 0.0)));
+            }
+        } else if c.is_alphabetic() || c == '_' || c == '%' {
+            let start = if c == '%' { i + 1 } else { i };
+            i = start;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            if name.is_empty() {
+                return Err("expected a parameter name after '%'".to_string());
+            }
+            tokens.push(if c == '%' { Tok::Param(name) } else { Tok::Ident(name) });
+        } else if "+-*/^()".contains(c) {
+            tokens.push(Tok::Sym(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct ExprParser {
+    tokens: Vec<Tok>,
+    pos: usize,
+}
+
+/// Parses a complete expression such as `-1/sqrt(2)` or `cos(%theta/2)`.
+pub(crate) fn parse_expr(text: &str) -> Result<Expr, String> {
+    let mut p = ExprParser { tokens: tokenize(text)?, pos: 0 };
+    let e = p.sum()?;
+    if p.pos != p.tokens.len() {
+        return Err(format!("unexpected trailing input in '{}'", text.trim()));
+    }
+    Ok(e)
+}
+
+impl ExprParser {
+    fn peek_sym(&self, c: char) -> bool {
+        self.tokens.get(self.pos) == Some(&Tok::Sym(c))
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.product()?;
+        while self.peek_sym('+') || self.peek_sym('-') {
+            let op = if self.peek_sym('+') { '+' } else { '-' };
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.product()?));
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while self.peek_sym('*') || self.peek_sym('/') {
+            let op = if self.peek_sym('*') { '*' } else { '/' };
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek_sym('-') {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.peek_sym('+') {
+            self.pos += 1;
+            return self.unary();
+        }
+        let base = self.atom()?;
+        if self.peek_sym('^') {
+            self.pos += 1;
+            return Ok(Expr::Bin('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let tok = self.tokens.get(self.pos).cloned().ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        match tok {
+            Tok::Num(x) => Ok(Expr::Num(x)),
+            Tok::Param(name) => Ok(Expr::Param(name)),
+            Tok::Ident(name) if name == "pi" => Ok(Expr::Num(Complex64::new(std::f64::consts::PI, 0.0))),
+            Tok::Ident(name) if name == "i" => Ok(Expr::Num(Complex64::i())),
+            Tok::Ident(name) if FUNCTIONS.contains(&name.as_str()) => {
+                if !self.peek_sym('(') {
+                    return Err(format!("expected '(' after '{}'", name));
+                }
+                self.pos += 1;
+                let arg = self.sum()?;
+                if !self.peek_sym(')') {
+                    return Err("expected ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(Expr::Call(name, Box::new(arg)))
+            }
+            Tok::Ident(name) => Err(format!("unknown identifier '{}'", name)),
+            Tok::Sym('(') => {
+                let e = self.sum()?;
+                if !self.peek_sym(')') {
+                    return Err("expected ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(e)
+            }
+            Tok::Sym(c) => Err(format!("unexpected '{}'", c)),
+        }
+    }
+}
@@ -0,0 +1,635 @@
+// Quil front end: parses Quil programs into a list of IR instructions plus jumps,
+// and runs them on `QuantumCircuit` with a program counter.
+use std::collections::HashMap;
+use std::f64::consts::FRAC_PI_2;
+use std::fmt;
+use num::complex::Complex64;
+use crate::circuit::{add_controls, invert};
+use crate::constants::TOL;
+use crate::{Gate, Instruction, Operation, QuantumCircuit};
+
+mod expr;
+
+use expr::{parse_expr, Expr};
+
+/// Parse error with the 1-based line it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuilError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl QuilError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        QuilError { line, message: message.into() }
+    }
+}
+
+impl fmt::Display for QuilError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for QuilError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuilOp {
+    Instruction(Instruction),
+    /// `RESET` without a qubit: every qubit of the program back to |0⟩.
+    ResetAll,
+    /// Continue at the op with this index.
+    Jump(usize),
+    JumpWhen { target: usize, clbit: usize },
+    JumpUnless { target: usize, clbit: usize },
+    Halt,
+}
+
+/// A parsed Quil program. `BIT` memory is flattened into clbits in declaration order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QuilProgram {
+    pub num_qubits: usize,
+    pub num_clbits: usize,
+    /// Declared memory regions: name -> (first clbit, length).
+    pub memory: HashMap<String, (usize, usize)>,
+    pub ops: Vec<QuilOp>,
+}
+
+/// A `DEFGATE`: parameter names (without `%`) and matrix entries, rows first.
+struct GateDef {
+    params: Vec<String>,
+    rows: Vec<Vec<Expr>>,
+}
+
+/// Standard gates: name, number of parameters, number of qubits.
+const STANDARD_GATES: [(&str, usize, usize); 22] = [
+    ("I", 0, 1), ("X", 0, 1), ("Y", 0, 1), ("Z", 0, 1), ("H", 0, 1), ("S", 0, 1), ("T", 0, 1),
+    ("PHASE", 1, 1), ("RX", 1, 1), ("RY", 1, 1), ("RZ", 1, 1),
+    ("CZ", 0, 2), ("CNOT", 0, 2), ("CCNOT", 0, 3), ("CPHASE", 1, 2), ("CPHASE00", 1, 2), ("CPHASE01", 1, 2),
+    ("CPHASE10", 1, 2), ("SWAP", 0, 2), ("CSWAP", 0, 3), ("ISWAP", 0, 2), ("PSWAP", 1, 2),
+];
+
+struct Parser {
+    program: QuilProgram,
+    defgates: HashMap<String, GateDef>,
+    labels: HashMap<String, usize>,
+    /// Jumps waiting for their label: op index, label, line.
+    pending: Vec<(usize, String, usize)>,
+    /// Clbit for `MEASURE q` without a destination, allocated on first use.
+    scratch: Option<usize>,
+}
+
+/// Parses a Quil program.
+///
+/// Supported: `DECLARE` of `BIT` memory, the standard gates (`H`, `CNOT`, `RX(theta)`,
+/// `CPHASE(theta)`, `CSWAP`, ...), `DEFGATE` with a matrix, the `CONTROLLED` and
+/// `DAGGER` modifiers, `MEASURE`, `RESET`, `LABEL`, `JUMP`, `JUMP-WHEN`,
+/// `JUMP-UNLESS` and `HALT`. `DEFGATE` matrices must be single-qubit, controlled
+/// (identity except the last 2x2 block) or diagonal.
+pub fn parse_quil(source: &str) -> Result<QuilProgram, QuilError> {
+    let mut parser = Parser {
+        program: QuilProgram::default(),
+        defgates: HashMap::new(),
+        labels: HashMap::new(),
+        pending: Vec::new(),
+        scratch: None,
+    };
+    let lines: Vec<(usize, &str)> = source.lines().enumerate()
+        .map(|(i, l)| (i + 1, l.split('#').next().unwrap().trim_end()))
+        .collect();
+    let mut i = 0;
+    while i < lines.len() {
+        let (line_no, text) = lines[i];
+        i += 1;
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if text.starts_with("DEFGATE") {
+            // The matrix rows are the indented lines that follow.
+            let mut rows = Vec::new();
+            while i < lines.len() && lines[i].1.starts_with([' ', '\t']) {
+                if !lines[i].1.trim().is_empty() {
+                    rows.push(lines[i]);
+                }
+                i += 1;
+            }
+            parser.parse_defgate(line_no, text, &rows)?;
+        } else {
+            parser.parse_line(line_no, text)?;
+        }
+    }
+    for (index, label, line_no) in std::mem::take(&mut parser.pending) {
+        let target = *parser.labels.get(&label)
+            .ok_or_else(|| QuilError::new(line_no, format!("undefined label '@{}'", label)))?;
+        match &mut parser.program.ops[index] {
+            QuilOp::Jump(t) | QuilOp::JumpWhen { target: t, .. } | QuilOp::JumpUnless { target: t, .. } => *t = target,
+            _ => unreachable!(),
+        }
+    }
+    Ok(parser.program)
+}
+
+impl Parser {
+    fn parse_defgate(&mut self, line_no: usize, header: &str, rows: &[(usize, &str)]) -> Result<(), QuilError> {
+        let err = |msg: String| QuilError::new(line_no, msg);
+        let header = header.strip_prefix("DEFGATE").unwrap().trim();
+        let header = header.strip_suffix(':').ok_or_else(|| err("expected ':' at the end of DEFGATE".to_string()))?;
+        let header = match header.split_once(" AS ") {
+            Some((h, "MATRIX")) => h.trim(),
+            Some((_, kind)) => return Err(err(format!("DEFGATE AS {} is not supported", kind.trim()))),
+            None => header.trim(),
+        };
+        let (name, params) = split_call(header).map_err(err)?;
+        let params = params.iter().map(|p| {
+            p.strip_prefix('%').map(str::to_string).ok_or_else(|| err(format!("parameter '{}' must start with '%'", p)))
+        }).collect::<Result<Vec<_>, _>>()?;
+        if self.defgates.contains_key(name) || STANDARD_GATES.iter().any(|g| g.0 == name) {
+            return Err(err(format!("gate '{}' is already defined", name)));
+        }
+
+        let mut matrix = Vec::new();
+        for &(row_line, row) in rows {
+            let entries = split_top_level(row, ',').into_iter()
+                .map(|e| parse_expr(e).map_err(|m| QuilError::new(row_line, m)))
+                .collect::<Result<Vec<_>, _>>()?;
+            matrix.push(entries);
+        }
+        let dim = matrix.len();
+        if dim < 2 || !dim.is_power_of_two() || matrix.iter().any(|r| r.len() != dim) {
+            return Err(err(format!("the matrix of '{}' must be square with a power-of-two size", name)));
+        }
+        self.defgates.insert(name.to_string(), GateDef { params, rows: matrix });
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line_no: usize, text: &str) -> Result<(), QuilError> {
+        let err = |msg: String| QuilError::new(line_no, msg);
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words[0] {
+            "DECLARE" => {
+                let (name, kind) = match words[..] {
+                    [_, name, kind] => (name, kind),
+                    _ => return Err(err("expected 'DECLARE name BIT[n]'".to_string())),
+                };
+                let size = match kind.strip_prefix("BIT") {
+                    Some("") => 1,
+                    Some(rest) => rest.strip_prefix('[').and_then(|r| r.strip_suffix(']'))
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .ok_or_else(|| err(format!("invalid memory type '{}'", kind)))?,
+                    None => return Err(err(format!("only BIT memory is supported, got '{}'", kind))),
+                };
+                if self.program.memory.contains_key(name) {
+                    return Err(err(format!("memory '{}' is already declared", name)));
+                }
+                self.program.memory.insert(name.to_string(), (self.program.num_clbits, size));
+                self.program.num_clbits += size;
+            }
+            "MEASURE" => {
+                let (qubit, clbit) = match words[..] {
+                    [_, q] => (self.qubit(q).map_err(err)?, self.scratch_bit()),
+                    [_, q, addr] => (self.qubit(q).map_err(err)?, self.address(addr).map_err(err)?),
+                    _ => return Err(err("expected 'MEASURE qubit [address]'".to_string())),
+                };
+                self.push(Operation::Measure { qubit, clbit });
+            }
+            "RESET" => match words[..] {
+                [_] => self.program.ops.push(QuilOp::ResetAll),
+                [_, q] => {
+                    let q = self.qubit(q).map_err(err)?;
+                    self.push(Operation::Reset(q));
+                }
+                _ => return Err(err("expected 'RESET [qubit]'".to_string())),
+            },
+            "LABEL" => {
+                let label = match words[..] {
+                    [_, label] => label.strip_prefix('@'),
+                    _ => None,
+                }.ok_or_else(|| err("expected 'LABEL @name'".to_string()))?;
+                if self.labels.insert(label.to_string(), self.program.ops.len()).is_some() {
+                    return Err(err(format!("label '@{}' is defined twice", label)));
+                }
+            }
+            "JUMP" | "JUMP-WHEN" | "JUMP-UNLESS" => {
+                let (label, op) = match (words[0], &words[1..]) {
+                    ("JUMP", [label]) => (label, QuilOp::Jump(0)),
+                    ("JUMP-WHEN", [label, addr]) => (label, QuilOp::JumpWhen { target: 0, clbit: self.address(addr).map_err(err)? }),
+                    ("JUMP-UNLESS", [label, addr]) => (label, QuilOp::JumpUnless { target: 0, clbit: self.address(addr).map_err(err)? }),
+                    _ => return Err(err(format!("wrong number of arguments to {}", words[0]))),
+                };
+                let label = label.strip_prefix('@').ok_or_else(|| err(format!("expected a label, got '{}'", label)))?;
+                self.pending.push((self.program.ops.len(), label.to_string(), line_no));
+                self.program.ops.push(op);
+            }
+            "HALT" => self.program.ops.push(QuilOp::Halt),
+            "NOP" | "PRAGMA" => {}
+            _ => self.parse_gate(text).map_err(err)?,
+        }
+        Ok(())
+    }
+
+    /// `CONTROLLED`/`DAGGER` modifiers, then `NAME(params) qubits...`.
+    fn parse_gate(&mut self, text: &str) -> Result<(), String> {
+        let mut modifiers = Vec::new();
+        let mut rest = text;
+        loop {
+            let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            match word {
+                "CONTROLLED" | "DAGGER" => modifiers.push(word),
+                "FORKED" => return Err("the FORKED modifier is not supported".to_string()),
+                _ => break,
+            }
+            rest = tail.trim_start();
+        }
+        let name_end = rest.find(|c: char| c == '(' || c.is_whitespace()).unwrap_or(rest.len());
+        let call_end = if rest[name_end..].starts_with('(') {
+            name_end + rest[name_end..].find(')').ok_or("missing ')'")? + 1
+        } else {
+            name_end
+        };
+        let (name, param_text) = split_call(&rest[..call_end])?;
+        let params = param_text.iter()
+            .map(|p| parse_expr(p)?.eval_real(&HashMap::new()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let qubits = rest[call_end..].split_whitespace().map(|q| self.qubit(q)).collect::<Result<Vec<_>, _>>()?;
+        for (i, q) in qubits.iter().enumerate() {
+            if qubits[..i].contains(q) {
+                return Err(format!("qubit {} is used twice", q));
+            }
+        }
+        for op in self.gate_ops(&modifiers, name, &params, &qubits)? {
+            self.push(op);
+        }
+        Ok(())
+    }
+
+    fn gate_ops(&self, modifiers: &[&str], name: &str, params: &[f64], qubits: &[usize]) -> Result<Vec<Operation>, String> {
+        match modifiers.split_first() {
+            Some((&"CONTROLLED", rest)) => {
+                let (&control, targets) = qubits.split_first().ok_or("CONTROLLED needs a control qubit")?;
+                Ok(add_controls(self.gate_ops(rest, name, params, targets)?, &[control], true))
+            }
+            Some((_, rest)) => Ok(invert(self.gate_ops(rest, name, params, qubits)?)),
+            None => {
+                if let Some(&(_, num_params, num_qubits)) = STANDARD_GATES.iter().find(|g| g.0 == name) {
+                    check_arity(name, num_params, num_qubits, params, qubits)?;
+                    return Ok(standard_ops(name, params, qubits));
+                }
+                let def = self.defgates.get(name).ok_or_else(|| format!("undefined gate '{}'", name))?;
+                check_arity(name, def.params.len(), def.rows.len().trailing_zeros() as usize, params, qubits)?;
+                let env: HashMap<String, f64> = def.params.iter().cloned().zip(params.iter().cloned()).collect();
+                let matrix = def.rows.iter()
+                    .map(|row| row.iter().map(|e| e.eval(&env)).collect::<Result<Vec<_>, _>>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                matrix_ops(name, &matrix, qubits)
+            }
+        }
+    }
+
+    fn push(&mut self, op: Operation) {
+        self.program.num_qubits = Instruction::new(op.clone()).qubits().iter()
+            .fold(self.program.num_qubits, |n, &q| n.max(q + 1));
+        self.program.ops.push(QuilOp::Instruction(Instruction::new(op)));
+    }
+
+    fn qubit(&self, text: &str) -> Result<usize, String> {
+        text.parse().map_err(|_| format!("expected a qubit index, got '{}'", text))
+    }
+
+    /// `name` or `name[i]` in declared memory.
+    fn address(&self, text: &str) -> Result<usize, String> {
+        let (name, index) = match text.split_once('[') {
+            Some((name, rest)) => {
+                let index = rest.strip_suffix(']').and_then(|i| i.parse::<usize>().ok())
+                    .ok_or_else(|| format!("invalid memory reference '{}'", text))?;
+                (name, index)
+            }
+            None => (text, 0),
+        };
+        let &(start, size) = self.program.memory.get(name).ok_or_else(|| format!("undeclared memory '{}'", name))?;
+        if index >= size {
+            return Err(format!("index {} out of range for '{}' of length {}", index, name, size));
+        }
+        Ok(start + index)
+    }
+
+    fn scratch_bit(&mut self) -> usize {
+        *self.scratch.get_or_insert_with(|| {
+            self.program.num_clbits += 1;
+            self.program.num_clbits - 1
+        })
+    }
+}
+
+impl QuantumCircuit {
+    /// Runs a Quil program from the current state, following jumps until `HALT` or
+    /// the end of the program, and returns the final classical memory.
+    pub fn run_quil(&mut self, program: &QuilProgram) -> Vec<bool> {
+        assert!(program.num_qubits <= self.num_qubits,
+                "Program uses {} qubits but the simulator has {}", program.num_qubits, self.num_qubits);
+        let mut clbits = vec![false; program.num_clbits];
+        let mut pc = 0;
+        while pc < program.ops.len() {
+            match &program.ops[pc] {
+                QuilOp::Instruction(inst) => self.apply_instruction(inst, &mut clbits),
+                QuilOp::ResetAll => {
+                    for q in 0..program.num_qubits {
+                        self.reset(q);
+                    }
+                }
+                QuilOp::Jump(target) => {
+                    pc = *target;
+                    continue;
+                }
+                QuilOp::JumpWhen { target, clbit } | QuilOp::JumpUnless { target, clbit } => {
+                    let when = matches!(program.ops[pc], QuilOp::JumpWhen { .. });
+                    if clbits[*clbit] == when {
+                        pc = *target;
+                        continue;
+                    }
+                }
+                QuilOp::Halt => break,
+            }
+            pc += 1;
+        }
+        clbits
+    }
+}
+
+/// Splits `NAME(a, b)` into the name and its top-level comma-separated arguments.
+fn split_call(text: &str) -> Result<(&str, Vec<&str>), String> {
+    match text.split_once('(') {
+        None => Ok((text.trim(), Vec::new())),
+        Some((name, rest)) => {
+            let inner = rest.trim_end().strip_suffix(')').ok_or("missing ')'")?;
+            let args = split_top_level(inner, ',').into_iter().filter(|a| !a.trim().is_empty()).collect();
+            Ok((name.trim(), args))
+        }
+    }
+}
+
+/// Splits on `sep` outside parentheses.
+fn split_top_level(text: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn check_arity(name: &str, num_params: usize, num_qubits: usize, params: &[f64], qubits: &[usize]) -> Result<(), String> {
+    if params.len() != num_params {
+        return Err(format!("gate '{}' takes {} parameter(s), got {}", name, num_params, params.len()));
+    }
+    if qubits.len() != num_qubits {
+        return Err(format!("gate '{}' acts on {} qubit(s), got {}", name, num_qubits, qubits.len()));
+    }
+    Ok(())
+}
+
+/// IR operations for a standard gate. In Quil matrices the first qubit is the most
+/// significant, so `CPHASE01` puts its phase on q[0] = 0, q[1] = 1.
+fn standard_ops(name: &str, p: &[f64], q: &[usize]) -> Vec<Operation> {
+    let gate = |gate: Gate, qubits: &[usize]| Operation::Gate { gate, qubits: qubits.to_vec() };
+    let controlled = |gate: Gate, controls: &[usize], neg_controls: &[usize], target: usize| Operation::Controlled {
+        gate,
+        controls: controls.to_vec(),
+        neg_controls: neg_controls.to_vec(),
+        target,
+    };
+    let swap = |a: usize, b: usize| vec![gate(Gate::CX, &[a, b]), gate(Gate::CX, &[b, a]), gate(Gate::CX, &[a, b])];
+    match name {
+        "I" => Vec::new(),
+        "X" => vec![gate(Gate::X, q)],
+        "Y" => vec![gate(Gate::Y, q)],
+        "Z" => vec![gate(Gate::Z, q)],
+        "H" => vec![gate(Gate::H, q)],
+        "S" => vec![gate(Gate::S, q)],
+        "T" => vec![gate(Gate::T, q)],
+        "PHASE" => vec![gate(Gate::P(p[0]), q)],
+        "RX" => vec![gate(Gate::U(p[0], -FRAC_PI_2, FRAC_PI_2), q)],
+        "RY" => vec![gate(Gate::U(p[0], 0.0, 0.0), q)],
+        "RZ" => vec![gate(Gate::P(p[0]), q), Operation::GlobalPhase(-p[0] / 2.0)],
+        "CZ" => vec![gate(Gate::CZ, q)],
+        "CNOT" => vec![gate(Gate::CX, q)],
+        "CCNOT" => vec![controlled(Gate::X, &q[..2], &[], q[2])],
+        "CPHASE" => vec![controlled(Gate::P(p[0]), &q[..1], &[], q[1])],
+        "CPHASE00" => vec![
+            gate(Gate::X, &q[1..]),
+            controlled(Gate::P(p[0]), &[], &q[..1], q[1]),
+            gate(Gate::X, &q[1..]),
+        ],
+        "CPHASE01" => vec![controlled(Gate::P(p[0]), &[], &q[..1], q[1])],
+        "CPHASE10" => vec![controlled(Gate::P(p[0]), &[], &q[1..], q[0])],
+        "SWAP" => swap(q[0], q[1]),
+        "CSWAP" => vec![
+            gate(Gate::CX, &[q[2], q[1]]),
+            controlled(Gate::X, &q[..2], &[], q[2]),
+            gate(Gate::CX, &[q[2], q[1]]),
+        ],
+        // ISWAP = SWAP CZ (S x S) and PSWAP(t) = SWAP CPHASE(-2t) (P(t) x P(t)).
+        "ISWAP" => [vec![gate(Gate::S, &q[..1]), gate(Gate::S, &q[1..]), gate(Gate::CZ, q)], swap(q[0], q[1])].concat(),
+        "PSWAP" => [
+            vec![
+                gate(Gate::P(p[0]), &q[..1]),
+                gate(Gate::P(p[0]), &q[1..]),
+                controlled(Gate::P(-2.0 * p[0]), &q[..1], &[], q[1]),
+            ],
+            swap(q[0], q[1]),
+        ].concat(),
+        _ => unreachable!(),
+    }
+}
+
+/// IR operations for a `DEFGATE` matrix: any single-qubit unitary, a controlled
+/// single-qubit unitary, or a diagonal of phases.
+fn matrix_ops(name: &str, m: &[Vec<Complex64>], qubits: &[usize]) -> Result<Vec<Operation>, String> {
+    let dim = m.len();
+    let k = qubits.len();
+    for i in 0..dim {
+        for j in 0..dim {
+            let dot: Complex64 = (0..dim).map(|l| m[i][l] * m[j][l].conj()).sum();
+            let expected = if i == j { 1.0 } else { 0.0 };
+            if (dot - expected).norm() > 1e-9 {
+                return Err(format!("the matrix of '{}' is not unitary", name));
+            }
+        }
+    }
+    let is_zero = |z: Complex64| z.norm() < TOL;
+
+    if dim == 2 {
+        let (gate, alpha) = Gate::from_matrix([[m[0][0], m[0][1]], [m[1][0], m[1][1]]]);
+        let mut ops = vec![Operation::Gate { gate, qubits: qubits.to_vec() }];
+        if alpha.abs() > TOL {
+            ops.push(Operation::GlobalPhase(alpha));
+        }
+        return Ok(ops);
+    }
+    let identity_above = (0..dim - 2).all(|i| (0..dim).all(|j| {
+        let expected = if i == j { 1.0 } else { 0.0 };
+        (m[i][j] - expected).norm() < TOL && is_zero(m[j][i]) == (i != j)
+    }));
+    if identity_above {
+        let block = vec![m[dim - 2][dim - 2..].to_vec(), m[dim - 1][dim - 2..].to_vec()];
+        let inner = matrix_ops(name, &block, &qubits[k - 1..])?;
+        return Ok(add_controls(inner, &qubits[..k - 1], true));
+    }
+    let diagonal = (0..dim).all(|i| (0..dim).all(|j| i == j || is_zero(m[i][j])));
+    if diagonal {
+        let mut ops = Vec::new();
+        for (j, row) in m.iter().enumerate() {
+            let phase = row[j].arg();
+            if phase.abs() < TOL {
+                continue;
+            }
+            let bit = |i: usize| (j >> (k - 1 - i)) & 1 == 1;
+            let ones: Vec<usize> = (0..k).filter(|&i| bit(i)).map(|i| qubits[i]).collect();
+            let zeros: Vec<usize> = (0..k).filter(|&i| !bit(i)).map(|i| qubits[i]).collect();
+            match ones.split_last() {
+                Some((&target, rest)) => ops.push(Operation::Controlled {
+                    gate: Gate::P(phase),
+                    controls: rest.to_vec(),
+                    neg_controls: zeros,
+                    target,
+                }),
+                None => {
+                    let (&target, rest) = zeros.split_last().unwrap();
+                    let flip = Operation::Gate { gate: Gate::X, qubits: vec![target] };
+                    ops.push(flip.clone());
+                    ops.push(Operation::Controlled { gate: Gate::P(phase), controls: vec![], neg_controls: rest.to_vec(), target });
+                    ops.push(flip);
+                }
+            }
+        }
+        return Ok(ops);
+    }
+    Err(format!("the matrix of '{}' must be single-qubit, controlled or diagonal", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_of(src: &str) -> Vec<Complex64> {
+        let program = parse_quil(src).unwrap();
+        let mut sim = QuantumCircuit::new(program.num_qubits);
+        sim.set_seed(0);
+        sim.run_quil(&program);
+        sim.get_state_vector()
+    }
+
+    fn assert_state(actual: &[Complex64], expected: &[Complex64]) {
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a - b).norm() < 1e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_bell_program() {
+        let src = "
+            DECLARE ro BIT[2]
+            H 0
+            CNOT 0 1
+            MEASURE 0 ro[0]
+            MEASURE 1 ro[1]
+        ";
+        let program = parse_quil(src).unwrap();
+        assert_eq!(program.num_qubits, 2);
+        assert_eq!(program.memory["ro"], (0, 2));
+        assert_eq!(program.ops[1], QuilOp::Instruction(Instruction::new(Operation::Gate { gate: Gate::CX, qubits: vec![0, 1] })));
+        for seed in 0..5 {
+            let mut sim = QuantumCircuit::new(2);
+            sim.set_seed(seed);
+            let ro = sim.run_quil(&program);
+            assert_eq!(ro[0], ro[1]);
+        }
+    }
+
+    #[test]
+    fn test_defgate_matrices() {
+        // SQRT-X twice is X; a controlled DEFGATE and a diagonal one follow.
+        let src = "
+            DEFGATE SQRT-X:
+                0.5+0.5i, 0.5-0.5i
+                0.5-0.5i, 0.5+0.5i
+
+            DEFGATE CRY(%theta):
+                1, 0, 0, 0
+                0, 1, 0, 0
+                0, 0, cos(%theta/2), -sin(%theta/2)
+                0, 0, sin(%theta/2), cos(%theta/2)
+
+            DEFGATE CCPHASE AS MATRIX:
+                1, 0, 0, 0
+                0, 1, 0, 0
+                0, 0, 1, 0
+                0, 0, 0, -1
+
+            SQRT-X 1
+            SQRT-X 1
+            CRY(pi) 1 0
+            CCPHASE 0 1
+            DAGGER RZ(pi/2) 2
+        ";
+        let mut expected = vec![Complex64::new(0.0, 0.0); 8];
+        // |q2 q1 q0⟩ = |011⟩, with -1 from CCPHASE and exp(i*pi/4) from RZ(-pi/2) on |0⟩.
+        expected[0b011] = -Complex64::from_polar(1.0, std::f64::consts::FRAC_PI_4);
+        assert_state(&state_of(src), &expected);
+    }
+
+    #[test]
+    fn test_modifiers_and_standard_gates() {
+        let src = "X 0\nCONTROLLED CONTROLLED X 0 1 2\nCONTROLLED X 0 1\nCSWAP 2 1 3\nISWAP 0 4\nCPHASE10(pi) 3 1";
+        let mut expected = vec![Complex64::new(0.0, 0.0); 32];
+        // X 0, CCX(0,1,2) does nothing, CX(0,1) sets q1, CSWAP(2; 1, 3) does nothing,
+        // ISWAP moves q0 to q4 with a factor i, CPHASE10 has q3 = 0 so no phase.
+        expected[0b10010] = Complex64::i();
+        assert_state(&state_of(src), &expected);
+    }
+
+    #[test]
+    fn test_jump_when_loop() {
+        // Repeat until the measurement gives 1, then flip qubit 1 unless told to halt.
+        let src = "
+            DECLARE ro BIT
+            DECLARE done BIT
+            LABEL @retry
+            RESET 0
+            H 0
+            MEASURE 0 ro
+            JUMP-UNLESS @retry ro
+            JUMP-WHEN @end done
+            X 1
+            LABEL @end
+            HALT
+            X 2
+        ";
+        let program = parse_quil(src).unwrap();
+        for seed in 0..5 {
+            let mut sim = QuantumCircuit::new(program.num_qubits);
+            sim.set_seed(seed);
+            assert_eq!(sim.run_quil(&program), vec![true, false]);
+            assert!((sim.get_state_vector()[0b011].re - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse_quil("H 0\nJUMP @nowhere").unwrap_err(), QuilError::new(2, "undefined label '@nowhere'"));
+        assert!(parse_quil("FOO 0").unwrap_err().message.contains("undefined gate 'FOO'"));
+        assert!(parse_quil("RX 0").unwrap_err().message.contains("takes 1 parameter"));
+        let err = parse_quil("DEFGATE BAD:\n    1, 1\n    0, 1\nBAD 0").unwrap_err();
+        assert_eq!(err.line, 4);
+        assert!(err.message.contains("not unitary"));
+    }
+}
@@ -0,0 +1,372 @@
+// Interactive mode of the `qdd_rs` binary: apply gates one at a time, or step
+// forward and backward through a loaded circuit, watching the DD change.
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+use qdd_rs::{parse_qasm3, Circuit, Instruction, Operation, QuantumCircuit};
+use crate::{draw_dot, load, sparse_state, Program};
+
+pub const USAGE: &str = "\
+Usage: qdd_rs repl <num_qubits | circuit> [--seed <n>]";
+
+const HELP: &str = "\
+  <gate> [(params)] <qubits>   apply a gate, e.g. `h 2`, `cx 2 1`, `rz(pi/4) 0`
+  measure <qubit>              measure a qubit
+  reset <qubit>                reset a qubit to |0⟩
+  next [k] | n [k]             apply the next k instructions of the loaded circuit
+  run                          apply the rest of the loaded circuit
+  back [k] | b [k]             undo the last k steps
+  restart                      undo every step
+  state                        print the sparse state
+  dot                          print the DD in DOT format
+  watch <file.dot|file.png>    rewrite the file after every step
+  unwatch                      stop rewriting it
+  help                         print this help
+  quit | exit                  leave";
+
+/// A step that has been applied. Measurement outcomes are recorded so that
+/// stepping back can replay the history exactly.
+struct Step {
+    inst: Instruction,
+    outcome: Option<u8>,
+    from_circuit: bool,
+}
+
+pub struct Session {
+    sim: QuantumCircuit,
+    num_qubits: usize,
+    circuit: Circuit,
+    /// Index of the next instruction of `circuit` to apply.
+    cursor: usize,
+    clbits: Vec<bool>,
+    history: Vec<Step>,
+    watch: Option<String>,
+    /// `--seed`, reapplied whenever the state is rebuilt.
+    seed: Option<u64>,
+}
+
+pub fn main(args: &[String]) -> ExitCode {
+    let session = match parse_args(args) {
+        Ok(session) => session,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let mut session = match session {
+        Ok(session) => session,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("{} qubit(s), {} instruction(s) loaded. Type `help` for commands.",
+             session.num_qubits, session.circuit.len());
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("qdd> ");
+        std::io::stdout().flush().ok();
+        let Some(Ok(line)) = lines.next() else { break };
+        match line.trim() {
+            "quit" | "exit" => break,
+            "" => {}
+            command => match session.execute(command) {
+                Ok(output) => println!("{}", output),
+                Err(message) => println!("error: {}", message),
+            },
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// The outer error is a usage error, the inner one a failure to load the circuit.
+fn parse_args(args: &[String]) -> Result<Result<Session, String>, String> {
+    let mut target = None;
+    let mut seed = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a value")?;
+                seed = Some(value.parse::<u64>().map_err(|_| "--seed takes an unsigned integer")?);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            other if target.is_none() => target = Some(other.to_string()),
+            extra => return Err(format!("unexpected argument '{}'", extra)),
+        }
+    }
+    let target = target.ok_or("missing number of qubits or circuit file")?;
+    let circuit = match target.parse::<usize>() {
+        Ok(n) => Circuit::new(n, 0),
+        Err(_) => match load(&target) {
+            Ok(Program::Circuit(circuit)) => circuit,
+            Ok(Program::Quil(_)) => return Ok(Err("stepping through Quil programs is not supported".to_string())),
+            Err(message) => return Ok(Err(message)),
+        },
+    };
+    Ok(Ok(Session::new(circuit, seed)))
+}
+
+impl Session {
+    pub fn new(circuit: Circuit, seed: Option<u64>) -> Self {
+        let mut sim = QuantumCircuit::new(circuit.num_qubits);
+        if let Some(seed) = seed {
+            sim.set_seed(seed);
+        }
+        Session {
+            sim,
+            num_qubits: circuit.num_qubits,
+            clbits: vec![false; circuit.num_clbits],
+            circuit,
+            cursor: 0,
+            history: Vec::new(),
+            watch: None,
+            seed,
+        }
+    }
+
+    /// Runs one command and returns what to print.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let count = |default: usize| match words.get(1) {
+            Some(k) => k.parse::<usize>().map_err(|_| format!("expected a count, got '{}'", k)),
+            None => Ok(default),
+        };
+        match words[0] {
+            "help" => return Ok(HELP.to_string()),
+            "state" => return Ok(self.summary()),
+            "dot" => return Ok(self.sim.to_graphviz_dot()),
+            "watch" => {
+                let path = words.get(1).ok_or("watch needs a file name")?;
+                self.watch = Some(path.to_string());
+                self.render()?;
+                return Ok(format!("watching {}", path));
+            }
+            "unwatch" => {
+                self.watch = None;
+                return Ok("stopped watching".to_string());
+            }
+            "next" | "n" | "run" => {
+                let k = if words[0] == "run" { self.circuit.len() - self.cursor } else { count(1)? };
+                if self.cursor + k > self.circuit.len() {
+                    return Err(format!("only {} instruction(s) left", self.circuit.len() - self.cursor));
+                }
+                for _ in 0..k {
+                    let inst = self.circuit.instructions[self.cursor].clone();
+                    self.cursor += 1;
+                    self.apply(inst, true);
+                }
+            }
+            "back" | "b" | "restart" => {
+                let k = if words[0] == "restart" { self.history.len() } else { count(1)? };
+                if k > self.history.len() {
+                    return Err(format!("only {} step(s) to undo", self.history.len()));
+                }
+                let keep = self.history.len() - k;
+                self.cursor -= self.history[keep..].iter().filter(|s| s.from_circuit).count();
+                self.history.truncate(keep);
+                self.replay();
+            }
+            "measure" | "reset" => {
+                let qubit = self.qubit(words.get(1).ok_or(format!("{} needs a qubit", words[0]))?)?;
+                let op = if words[0] == "measure" { Operation::Measure { qubit, clbit: usize::MAX } } else { Operation::Reset(qubit) };
+                let outcome = self.apply(Instruction::new(op), false);
+                if words[0] == "measure" {
+                    return Ok(format!("measured {}\n{}", outcome.unwrap(), self.after_step()?));
+                }
+            }
+            _ => {
+                for inst in self.parse_gate(line)? {
+                    self.apply(inst, false);
+                }
+            }
+        }
+        self.after_step()
+    }
+
+    /// Applies an instruction, recording measurement outcomes. Measurements typed at
+    /// the prompt use `clbit: usize::MAX` and do not write the classical register.
+    fn apply(&mut self, inst: Instruction, from_circuit: bool) -> Option<u8> {
+        let outcome = match (&inst.op, &inst.condition) {
+            (_, Some(cond)) if !cond.is_satisfied(&self.clbits) => None,
+            (Operation::Measure { qubit, clbit }, _) => {
+                let outcome = self.sim.measure_qubit(*qubit);
+                if let Some(bit) = self.clbits.get_mut(*clbit) {
+                    *bit = outcome == 1;
+                }
+                Some(outcome)
+            }
+            (Operation::Reset(qubit), _) => Some(self.sim.reset(*qubit)),
+            _ => {
+                self.sim.apply_instruction(&inst, &mut self.clbits);
+                None
+            }
+        };
+        self.history.push(Step { inst, outcome, from_circuit });
+        outcome
+    }
+
+    /// Rebuilds the state from |0...0⟩ by re-applying the history.
+    ///
+    /// With a seed, the generator is reseeded and measurements are drawn again. The
+    /// history is deterministic, so they repeat the recorded outcomes and leave the
+    /// generator where an uninterrupted session would have it. Without a seed the
+    /// recorded outcomes are forced.
+    fn replay(&mut self) {
+        self.sim = QuantumCircuit::new(self.num_qubits);
+        if let Some(seed) = self.seed {
+            self.sim.set_seed(seed);
+        }
+        self.clbits.iter_mut().for_each(|b| *b = false);
+        for step in &self.history {
+            match (&step.inst.op, step.outcome, self.seed) {
+                (Operation::Measure { qubit, clbit }, Some(recorded), seed) => {
+                    let outcome = match seed {
+                        Some(_) => self.sim.measure_qubit(*qubit),
+                        None => {
+                            self.sim.collapse(*qubit, recorded);
+                            recorded
+                        }
+                    };
+                    if let Some(bit) = self.clbits.get_mut(*clbit) {
+                        *bit = outcome == 1;
+                    }
+                }
+                (Operation::Reset(qubit), Some(_), Some(_)) => {
+                    self.sim.reset(*qubit);
+                }
+                (Operation::Reset(qubit), Some(recorded), None) => {
+                    self.sim.collapse(*qubit, recorded);
+                    if recorded == 1 {
+                        self.sim.apply_x(*qubit);
+                    }
+                }
+                _ => self.sim.apply_instruction(&step.inst, &mut self.clbits),
+            }
+        }
+    }
+
+    /// Parses `name(params) q0 q1 ...` by handing it to the OpenQASM 3 parser, so
+    /// every standard gate and parameter expression is available.
+    fn parse_gate(&self, line: &str) -> Result<Vec<Instruction>, String> {
+        let split = line.rfind(')').map(|i| i + 1)
+            .unwrap_or_else(|| line.find(char::is_whitespace).unwrap_or(line.len()));
+        let (gate, operands) = line.split_at(split);
+        let qubits = operands.split_whitespace()
+            .map(|q| self.qubit(q).map(|q| format!("q[{}]", q)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let source = format!(
+            "OPENQASM 3.0;\ninclude \"stdgates.inc\";\nqubit[{}] q;\n{} {};\n",
+            self.num_qubits, gate.trim(), qubits.join(", "),
+        );
+        parse_qasm3(&source).map(|c| c.instructions).map_err(|e| e.message)
+    }
+
+    fn qubit(&self, text: &str) -> Result<usize, String> {
+        match text.parse::<usize>() {
+            Ok(q) if q < self.num_qubits => Ok(q),
+            Ok(q) => Err(format!("qubit {} is out of range for {} qubit(s)", q, self.num_qubits)),
+            Err(_) => Err(format!("expected a qubit index, got '{}'", text)),
+        }
+    }
+
+    fn after_step(&self) -> Result<String, String> {
+        self.render()?;
+        Ok(self.summary())
+    }
+
+    /// Sparse state, node counts and position in the loaded circuit.
+    fn summary(&self) -> String {
+        let mut lines: Vec<String> = sparse_state(&self.sim).iter()
+            .map(|(label, amp)| format!("  |{}⟩: {:.6}", label, amp))
+            .collect();
+        let (terminal, nonterminal) = self.sim.count_nodes();
+        lines.push(format!("nodes: {} terminal, {} non-terminal", terminal, nonterminal));
+        if !self.circuit.is_empty() {
+            lines.push(format!("step {}/{} of the circuit", self.cursor, self.circuit.len()));
+        }
+        lines.join("\n")
+    }
+
+    fn render(&self) -> Result<(), String> {
+        let Some(path) = &self.watch else { return Ok(()) };
+        let dot = self.sim.to_graphviz_dot();
+        let written = if path.ends_with(".png") { draw_dot(&dot, path) } else { std::fs::write(path, dot) };
+        written.map_err(|e| format!("could not write {}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amplitudes(session: &Session) -> Vec<(f64, f64)> {
+        session.sim.get_state_vector().iter().map(|a| (a.re, a.im)).collect()
+    }
+
+    #[test]
+    fn test_typed_gates() {
+        let mut session = Session::new(Circuit::new(2, 0), Some(1));
+        session.execute("h 1").unwrap();
+        let out = session.execute("cx 1 0").unwrap();
+        assert!(out.contains("|00⟩: 0.707107") && out.contains("|11⟩: 0.707107"), "{}", out);
+        assert!(session.execute("rz(pi/2) 0").is_ok());
+        assert!(session.execute("h 5").unwrap_err().contains("out of range"));
+        assert!(session.execute("foo 0").is_err());
+        let out = session.execute("measure 0").unwrap();
+        assert!(out.starts_with("measured"));
+    }
+
+    #[test]
+    fn test_step_forward_and_back() {
+        let mut circuit = Circuit::new(3, 1);
+        circuit.h(0).cx(0, 1).measure(1, 0).t(2).h(2);
+        let mut session = Session::new(circuit, Some(4));
+        session.execute("next 2").unwrap();
+        let entangled = amplitudes(&session);
+        session.execute("run").unwrap();
+        let end = amplitudes(&session);
+        let clbits = session.clbits.clone();
+
+        // Undo and redo the tail: the recorded measurement outcome is reused.
+        session.execute("back 2").unwrap();
+        assert_eq!(session.cursor, 3);
+        session.execute("n 2").unwrap();
+        assert_eq!(session.clbits, clbits);
+        for (a, b) in amplitudes(&session).iter().zip(&end) {
+            assert!((a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9);
+        }
+
+        session.execute("b 3").unwrap();
+        assert_eq!(amplitudes(&session), entangled);
+        assert!(session.execute("back 9").is_err());
+        session.execute("restart").unwrap();
+        assert_eq!(session.cursor, 0);
+        assert_eq!(amplitudes(&session)[0], (1.0, 0.0));
+    }
+
+    #[test]
+    fn test_seed_survives_stepping_back() {
+        let measure_all = |session: &mut Session| -> Vec<String> {
+            (0..8).map(|q| session.execute(&format!("measure {}", q)).unwrap()[..10].to_string()).collect()
+        };
+        let mut plain = Session::new(Circuit::new(8, 0), Some(11));
+        for q in 0..8 {
+            plain.execute(&format!("h {}", q)).unwrap();
+        }
+        let expected = measure_all(&mut plain);
+
+        let mut session = Session::new(Circuit::new(8, 0), Some(11));
+        for q in 0..8 {
+            session.execute(&format!("h {}", q)).unwrap();
+        }
+        measure_all(&mut session);
+        session.execute("back 8").unwrap();
+        assert_eq!(measure_all(&mut session), expected);
+        session.execute("reset 0").unwrap();
+        session.execute("back 5").unwrap();
+        let mut redone: Vec<String> = expected[..4].to_vec();
+        redone.extend((4..8).map(|q| session.execute(&format!("measure {}", q)).unwrap()[..10].to_string()));
+        assert_eq!(redone, expected);
+    }
+}
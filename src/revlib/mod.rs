@@ -0,0 +1,334 @@
+// RevLib `.real` front end: reversible circuits of multi-controlled Toffoli, Fredkin,
+// Peres and V gates, as used by the RevLib and MQT benchmark suites.
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+use std::fmt;
+use crate::{Circuit, Gate, Instruction, Operation};
+
+/// Parse error with the 1-based line it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RealError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl RealError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        RealError { line, message: message.into() }
+    }
+}
+
+impl fmt::Display for RealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for RealError {}
+
+/// A parsed `.real` file: the circuit plus the line metadata of the header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RealCircuit {
+    /// Variable `i` (in `.variables` order) is qubit `i`. Lines with constant input 1
+    /// start with an X gate, so running from |0...0⟩ gives the intended input.
+    pub circuit: Circuit,
+    pub variables: Vec<String>,
+    /// `Some(value)` for constant input lines, `None` for primary inputs.
+    pub constants: Vec<Option<bool>>,
+    /// Whether each line's output is garbage.
+    pub garbage: Vec<bool>,
+}
+
+/// Parses a RevLib `.real` file.
+///
+/// `tN` gates (last line is the target, the others controls) become X, or X with
+/// controls; `fN` (controlled swap of the last two lines) and `pN` (Peres) are
+/// expanded into multi-controlled X, and `vN`/`v+N` into controlled sqrt(X) and its
+/// inverse. A control written `-a` is negative (active on |0⟩).
+pub fn parse_real(source: &str) -> Result<RealCircuit, RealError> {
+    let mut numvars = None;
+    let mut variables: Vec<String> = Vec::new();
+    let mut constants = None;
+    let mut garbage = None;
+    let mut body = None;
+    let mut begin_line = 0;
+    let mut ended = false;
+
+    for (i, raw) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let line = raw.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        if ended {
+            return Err(RealError::new(line_no, "content after .end"));
+        }
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap();
+        let rest: Vec<&str> = words.collect();
+
+        if let Some(ops) = body.as_mut() {
+            if first == ".end" {
+                ended = true;
+            } else {
+                parse_gate(first, &rest, &variables, line_no, ops)?;
+            }
+            continue;
+        }
+        match first {
+            ".numvars" => {
+                let n = rest.first().and_then(|n| n.parse::<usize>().ok())
+                    .ok_or_else(|| RealError::new(line_no, "expected a number after .numvars"))?;
+                numvars = Some(n);
+            }
+            ".variables" => {
+                variables = rest.iter().map(|v| v.to_string()).collect();
+                if let Some(dup) = variables.iter().enumerate().find(|(i, v)| variables[..*i].contains(v)) {
+                    return Err(RealError::new(line_no, format!("variable '{}' declared twice", dup.1)));
+                }
+            }
+            ".constants" => constants = Some(parse_column(&rest, line_no, ".constants", |c| match c {
+                '-' => Some(None),
+                '0' => Some(Some(false)),
+                '1' => Some(Some(true)),
+                _ => None,
+            })?),
+            ".garbage" => garbage = Some(parse_column(&rest, line_no, ".garbage", |c| match c {
+                '-' => Some(false),
+                '1' => Some(true),
+                _ => None,
+            })?),
+            ".begin" => {
+                if variables.is_empty() {
+                    return Err(RealError::new(line_no, "missing .variables before .begin"));
+                }
+                if numvars.is_some_and(|n| n != variables.len()) {
+                    return Err(RealError::new(line_no, format!(
+                        ".numvars is {} but {} variables are declared", numvars.unwrap(), variables.len()
+                    )));
+                }
+                body = Some(Vec::new());
+                begin_line = line_no;
+            }
+            // Header lines that do not affect the circuit.
+            ".version" | ".inputs" | ".outputs" | ".model" | ".inputbus" | ".outputbus" | ".state" | ".module" => {}
+            other => return Err(RealError::new(line_no, format!("unknown directive '{}'", other))),
+        }
+    }
+
+    let ops = body.ok_or_else(|| RealError::new(source.lines().count(), "missing .begin"))?;
+    if !ended {
+        return Err(RealError::new(source.lines().count(), "missing .end"));
+    }
+    let n = variables.len();
+    let constants = constants.unwrap_or_else(|| vec![None; n]);
+    let garbage = garbage.unwrap_or_else(|| vec![false; n]);
+    if constants.len() != n || garbage.len() != n {
+        return Err(RealError::new(begin_line, format!("expected {} entries in .constants and .garbage", n)));
+    }
+
+    let mut circuit = Circuit::new(n, 0);
+    for (q, c) in constants.iter().enumerate() {
+        if *c == Some(true) {
+            circuit.x(q);
+        }
+    }
+    for op in ops {
+        circuit.push(Instruction::new(op));
+    }
+    Ok(RealCircuit { circuit, variables, constants, garbage })
+}
+
+/// Parses a `.constants`/`.garbage` column string, one character per variable.
+fn parse_column<T>(
+    rest: &[&str],
+    line_no: usize,
+    directive: &str,
+    parse: impl Fn(char) -> Option<T>,
+) -> Result<Vec<T>, RealError> {
+    let text = rest.concat();
+    text.chars().map(|c| {
+        parse(c).ok_or_else(|| RealError::new(line_no, format!("unexpected '{}' in {}", c, directive)))
+    }).collect()
+}
+
+fn parse_gate(
+    name: &str,
+    args: &[&str],
+    variables: &[String],
+    line_no: usize,
+    ops: &mut Vec<Operation>,
+) -> Result<(), RealError> {
+    let kind_len = name.find(|c: char| c.is_ascii_digit()).unwrap_or(name.len());
+    let (kind, size) = name.split_at(kind_len);
+    if !size.is_empty() && size.parse::<usize>().ok() != Some(args.len()) {
+        return Err(RealError::new(line_no, format!("gate '{}' expects {} lines, got {}", name, size, args.len())));
+    }
+
+    let index: HashMap<&str, usize> = variables.iter().enumerate().map(|(i, v)| (v.as_str(), i)).collect();
+    let mut lines = Vec::new();
+    for arg in args {
+        let (negative, var) = match arg.strip_prefix('-') {
+            Some(var) => (true, var),
+            None => (false, *arg),
+        };
+        let q = *index.get(var).ok_or_else(|| RealError::new(line_no, format!("unknown variable '{}'", var)))?;
+        if lines.iter().any(|&(_, other)| other == q) {
+            return Err(RealError::new(line_no, format!("variable '{}' used twice in one gate", var)));
+        }
+        lines.push((negative, q));
+    }
+
+    let targets = match kind {
+        "t" | "v" | "v+" => 1,
+        "f" | "p" => 2,
+        _ => return Err(RealError::new(line_no, format!("unsupported gate '{}'", name))),
+    };
+    if lines.len() < targets {
+        return Err(RealError::new(line_no, format!("gate '{}' needs at least {} lines", name, targets)));
+    }
+    let (control_lines, target_lines) = lines.split_at(lines.len() - targets);
+    if let Some(&(true, _)) = target_lines.iter().find(|(negative, _)| *negative) {
+        return Err(RealError::new(line_no, "targets cannot be negated"));
+    }
+    let controls: Vec<usize> = control_lines.iter().filter(|(neg, _)| !neg).map(|&(_, q)| q).collect();
+    let neg_controls: Vec<usize> = control_lines.iter().filter(|(neg, _)| *neg).map(|&(_, q)| q).collect();
+    let mcx = |controls: &[usize], target: usize| mcx(controls, &neg_controls, target);
+
+    match kind {
+        "t" => ops.push(mcx(&controls, target_lines[0].1)),
+        "f" => {
+            // Controlled swap as three multi-controlled X gates.
+            let (a, b) = (target_lines[0].1, target_lines[1].1);
+            let mut with_b = controls.clone();
+            with_b.push(b);
+            ops.push(Operation::Controlled { gate: Gate::X, controls: vec![a], neg_controls: vec![], target: b });
+            ops.push(mcx(&with_b, a));
+            ops.push(Operation::Controlled { gate: Gate::X, controls: vec![a], neg_controls: vec![], target: b });
+        }
+        "p" => {
+            // Peres: Toffoli onto the last line, then the controls flip the second to last.
+            let (a, b) = (target_lines[0].1, target_lines[1].1);
+            let mut with_a = controls.clone();
+            with_a.push(a);
+            ops.push(mcx(&with_a, b));
+            ops.push(mcx(&controls, a));
+        }
+        _ => {
+            // sqrt(X) = exp(i*pi/4) U(pi/2, -pi/2, pi/2); the phase is applied where the
+            // controls hold, as a controlled phase on one of the controls.
+            let target = target_lines[0].1;
+            let (gate, phase) = if kind == "v" {
+                (Gate::U(FRAC_PI_2, -FRAC_PI_2, FRAC_PI_2), FRAC_PI_4)
+            } else {
+                (Gate::U(-FRAC_PI_2, -FRAC_PI_2, FRAC_PI_2), -FRAC_PI_4)
+            };
+            ops.push(Operation::Controlled {
+                gate,
+                controls: controls.clone(),
+                neg_controls: neg_controls.clone(),
+                target,
+            });
+            ops.extend(controlled_phase(&controls, &neg_controls, phase));
+        }
+    }
+    Ok(())
+}
+
+fn mcx(controls: &[usize], neg_controls: &[usize], target: usize) -> Operation {
+    if controls.is_empty() && neg_controls.is_empty() {
+        return Operation::Gate { gate: Gate::X, qubits: vec![target] };
+    }
+    Operation::Controlled { gate: Gate::X, controls: controls.to_vec(), neg_controls: neg_controls.to_vec(), target }
+}
+
+/// Phase exp(i*theta) on the states where all controls hold.
+fn controlled_phase(controls: &[usize], neg_controls: &[usize], theta: f64) -> Vec<Operation> {
+    if let Some((&target, rest)) = controls.split_last() {
+        return vec![Operation::Controlled {
+            gate: Gate::P(theta),
+            controls: rest.to_vec(),
+            neg_controls: neg_controls.to_vec(),
+            target,
+        }];
+    }
+    match neg_controls.split_last() {
+        None => vec![Operation::GlobalPhase(theta)],
+        Some((&target, rest)) => {
+            let flip = Operation::Gate { gate: Gate::X, qubits: vec![target] };
+            vec![
+                flip.clone(),
+                Operation::Controlled { gate: Gate::P(theta), controls: vec![], neg_controls: rest.to_vec(), target },
+                flip,
+            ]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QuantumCircuit;
+
+    const TOFFOLI_CHAIN: &str = "
+        # small example
+        .version 1.0
+        .numvars 4
+        .variables a b c d
+        .inputs a b c d
+        .outputs a b c d
+        .constants 11-0
+        .garbage ---1
+        .begin
+        t3 a b c
+        t2 c d
+        f3 a c d
+        t1 b
+        .end
+    ";
+
+    #[test]
+    fn test_header_and_gates() {
+        let real = parse_real(TOFFOLI_CHAIN).unwrap();
+        assert_eq!(real.variables, vec!["a", "b", "c", "d"]);
+        assert_eq!(real.constants, vec![Some(true), Some(true), None, Some(false)]);
+        assert_eq!(real.garbage, vec![false, false, false, true]);
+
+        let ops: Vec<Operation> = real.circuit.instructions.iter().map(|i| i.op.clone()).collect();
+        assert_eq!(ops[..3], [
+            Operation::Gate { gate: Gate::X, qubits: vec![0] },
+            Operation::Gate { gate: Gate::X, qubits: vec![1] },
+            Operation::Controlled { gate: Gate::X, controls: vec![0, 1], neg_controls: vec![], target: 2 },
+        ]);
+        assert_eq!(real.circuit.len(), 2 + 2 + 3 + 1);
+    }
+
+    #[test]
+    fn test_runs_as_permutation() {
+        let real = parse_real(TOFFOLI_CHAIN).unwrap();
+        let mut sim = QuantumCircuit::new(4);
+        sim.run(&real.circuit);
+        // a=b=1 sets c, c sets d, the swap on (c, d) is a no-op, then b is cleared.
+        let state = sim.get_state_vector();
+        assert!((state[0b1101].re - 1.0).abs() < 1e-9, "{:?}", state);
+    }
+
+    #[test]
+    fn test_v_gates_square_to_toffoli() {
+        let src = ".numvars 3\n.variables a b c\n.constants 11-\n.begin\nv3 a -b c\nv a -b c\nt1 b\nv+ a -b c\nv+ a -b c\n.end\n";
+        let real = parse_real(src).unwrap();
+        let mut sim = QuantumCircuit::new(3);
+        sim.run(&real.circuit);
+        let state = sim.get_state_vector();
+        assert!((state[0b101].re - 1.0).abs() < 1e-9, "{:?}", state);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = parse_real(".variables a b\n.begin\nt2 a x\n.end").unwrap_err();
+        assert_eq!(err, RealError::new(3, "unknown variable 'x'"));
+        let err = parse_real(".variables a b\n.begin\nt3 a b\n.end").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(parse_real(".variables a\n.begin\nt1 a\n").is_err());
+    }
+}
@@ -0,0 +1,492 @@
+// Stim front end: the circuit text format of the Stim stabilizer simulator, so that
+// error-correction benchmarks (with their noise, detectors and observables) run on the DD.
+use std::fmt;
+use rand::Rng;
+use crate::{Condition, Gate, Instruction, Operation, QuantumCircuit};
+
+/// Parse error with the 1-based line it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StimError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl StimError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        StimError { line, message: message.into() }
+    }
+}
+
+impl fmt::Display for StimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for StimError {}
+
+/// Pauli noise channels, applied by sampling one Pauli per qubit (or pair) per shot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseChannel {
+    XError(f64),
+    YError(f64),
+    ZError(f64),
+    /// X, Y or Z, each with probability p/3.
+    Depolarize1(f64),
+    /// One of the 15 non-identity two-qubit Paulis, each with probability p/15.
+    Depolarize2(f64),
+    /// X, Y and Z with the given probabilities.
+    PauliChannel1(f64, f64, f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StimOp {
+    /// Gates, measurements and resets. Measurement `k` writes clbit `k`.
+    Instruction(Instruction),
+    /// A noise channel on each qubit, or each consecutive pair for `Depolarize2`.
+    Noise { channel: NoiseChannel, qubits: Vec<usize> },
+    /// Flips measurement record `record` with the given probability, from `M(p)`.
+    RecordFlip { record: usize, probability: f64 },
+}
+
+/// A Stim circuit with `REPEAT` blocks unrolled and `rec[-k]` targets resolved to
+/// absolute measurement indices.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StimCircuit {
+    pub num_qubits: usize,
+    pub num_measurements: usize,
+    pub ops: Vec<StimOp>,
+    /// Measurement indices whose parity forms each detector.
+    pub detectors: Vec<Vec<usize>>,
+    /// Measurement indices whose parity forms each logical observable.
+    pub observables: Vec<Vec<usize>>,
+}
+
+/// Result of one shot of a `StimCircuit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StimSample {
+    pub measurements: Vec<bool>,
+    /// Parity of each detector's measurements. Stim reports detection events relative
+    /// to a noiseless reference; for the usual circuits, where every detector is 0
+    /// without noise, the two agree.
+    pub detectors: Vec<bool>,
+    pub observables: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Qubit(usize),
+    /// `!q`: measure `q` and record the inverted result.
+    Inverted(usize),
+    /// `rec[-k]`, already turned into an absolute measurement index.
+    Rec(usize),
+}
+
+/// Parses a Stim circuit.
+///
+/// Supported: `H`, `S`, `S_DAG`, `X`, `Y`, `Z`, `I`, `CX` (`CNOT`), `CY`, `CZ`, `SWAP`
+/// (with `rec[-k]` controls for classical feedback), `M`, `MX`, `MR`, `MRX`, `R`, `RX`
+/// (with `!` inverted targets and `M(p)` flip noise), `X_ERROR`, `Y_ERROR`, `Z_ERROR`,
+/// `DEPOLARIZE1`, `DEPOLARIZE2`, `PAULI_CHANNEL_1`, `DETECTOR`, `OBSERVABLE_INCLUDE` and
+/// `REPEAT` blocks. `TICK` and coordinate annotations are ignored.
+pub fn parse_stim(source: &str) -> Result<StimCircuit, StimError> {
+    let lines: Vec<(usize, &str)> = source.lines().enumerate()
+        .map(|(i, l)| (i + 1, l.split('#').next().unwrap().trim()))
+        .filter(|(_, l)| !l.is_empty())
+        .collect();
+    let mut circuit = StimCircuit::default();
+    parse_block(&mut circuit, &lines, 0, false)?;
+    Ok(circuit)
+}
+
+/// Parses lines from `start` until the end (or the `}` closing a nested block) and
+/// returns the index after it. `REPEAT` bodies are parsed once per iteration, so
+/// `rec[-k]` resolves against the measurements made so far.
+fn parse_block(circuit: &mut StimCircuit, lines: &[(usize, &str)], start: usize, nested: bool) -> Result<usize, StimError> {
+    let mut i = start;
+    while i < lines.len() {
+        let (line_no, text) = lines[i];
+        if text == "}" {
+            if nested {
+                return Ok(i + 1);
+            }
+            return Err(StimError::new(line_no, "unmatched '}'"));
+        }
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words[0].eq_ignore_ascii_case("REPEAT") {
+            let count = match words[..] {
+                [_, count, "{"] => count.parse::<usize>().ok().filter(|&c| c > 0),
+                _ => None,
+            }.ok_or_else(|| StimError::new(line_no, "expected 'REPEAT <count> {' with a positive count"))?;
+            let mut end = i + 1;
+            for _ in 0..count {
+                end = parse_block(circuit, lines, i + 1, true)?;
+            }
+            i = end;
+            continue;
+        }
+        parse_instruction(circuit, line_no, text)?;
+        i += 1;
+    }
+    if nested {
+        return Err(StimError::new(lines.last().map_or(0, |l| l.0), "missing '}' closing a REPEAT block"));
+    }
+    Ok(i)
+}
+
+const INSTRUCTIONS: [&str; 38] = [
+    "TICK", "QUBIT_COORDS", "SHIFT_COORDS", "I", "X", "Y", "Z", "H", "H_XZ", "S", "SQRT_Z", "S_DAG", "SQRT_Z_DAG",
+    "CX", "CNOT", "ZCX", "CY", "ZCY", "CZ", "ZCZ", "SWAP", "M", "MZ", "MX", "MR", "MRZ", "MRX", "R", "RZ", "RX",
+    "X_ERROR", "Y_ERROR", "Z_ERROR", "DEPOLARIZE1", "DEPOLARIZE2", "PAULI_CHANNEL_1", "DETECTOR", "OBSERVABLE_INCLUDE",
+];
+
+fn parse_instruction(circuit: &mut StimCircuit, line_no: usize, text: &str) -> Result<(), StimError> {
+    let err = |msg: String| StimError::new(line_no, msg);
+    let name_end = text.find(|c: char| c == '(' || c.is_whitespace()).unwrap_or(text.len());
+    let name = text[..name_end].to_ascii_uppercase();
+    if !INSTRUCTIONS.contains(&name.as_str()) {
+        return Err(err(format!("unsupported instruction '{}'", name)));
+    }
+    let mut rest = &text[name_end..];
+
+    let mut args = Vec::new();
+    if let Some(inner) = rest.trim_start().strip_prefix('(') {
+        let close = inner.find(')').ok_or_else(|| err("missing ')'".to_string()))?;
+        for arg in inner[..close].split(',').filter(|a| !a.trim().is_empty()) {
+            args.push(arg.trim().parse::<f64>().map_err(|_| err(format!("invalid argument '{}'", arg.trim())))?);
+        }
+        rest = &inner[close + 1..];
+    }
+
+    let mut targets = Vec::new();
+    for word in rest.split_whitespace() {
+        let target = if let Some(k) = word.strip_prefix("rec[-").and_then(|w| w.strip_suffix(']')) {
+            let k: usize = k.parse().map_err(|_| err(format!("invalid target '{}'", word)))?;
+            if k == 0 || k > circuit.num_measurements {
+                return Err(err(format!("'{}' refers to a measurement that has not happened", word)));
+            }
+            Target::Rec(circuit.num_measurements - k)
+        } else if let Some(q) = word.strip_prefix('!') {
+            Target::Inverted(q.parse().map_err(|_| err(format!("invalid target '{}'", word)))?)
+        } else {
+            Target::Qubit(word.parse().map_err(|_| err(format!("invalid target '{}'", word)))?)
+        };
+        if let Target::Qubit(q) | Target::Inverted(q) = target {
+            circuit.num_qubits = circuit.num_qubits.max(q + 1);
+        }
+        targets.push(target);
+    }
+
+    let qubits = |allow_inverted: bool| -> Result<Vec<(usize, bool)>, StimError> {
+        targets.iter().map(|t| match *t {
+            Target::Qubit(q) => Ok((q, false)),
+            Target::Inverted(q) if allow_inverted => Ok((q, true)),
+            _ => Err(err(format!("{} only takes qubit targets", name))),
+        }).collect()
+    };
+    let probability = |index: usize| -> Result<f64, StimError> {
+        match args.get(index) {
+            Some(&p) if (0.0..=1.0).contains(&p) => Ok(p),
+            Some(&p) => Err(err(format!("probability {} is outside [0, 1]", p))),
+            None => Err(err(format!("{} needs a probability argument", name))),
+        }
+    };
+    let push = |circuit: &mut StimCircuit, op: Operation| circuit.ops.push(StimOp::Instruction(Instruction::new(op)));
+
+    match name.as_str() {
+        "TICK" | "QUBIT_COORDS" | "SHIFT_COORDS" | "I" => {}
+        "X" | "Y" | "Z" | "H" | "H_XZ" | "S" | "SQRT_Z" | "S_DAG" | "SQRT_Z_DAG" => {
+            let gate = match name.as_str() {
+                "X" => Gate::X,
+                "Y" => Gate::Y,
+                "Z" => Gate::Z,
+                "H" | "H_XZ" => Gate::H,
+                "S" | "SQRT_Z" => Gate::S,
+                _ => Gate::Sdg,
+            };
+            for (q, _) in qubits(false)? {
+                push(circuit, Operation::Gate { gate, qubits: vec![q] });
+            }
+        }
+        "CX" | "CNOT" | "ZCX" | "CY" | "ZCY" | "CZ" | "ZCZ" | "SWAP" => {
+            if !targets.len().is_multiple_of(2) {
+                return Err(err(format!("{} needs an even number of targets", name)));
+            }
+            for pair in targets.chunks(2) {
+                parse_pair_gate(circuit, &name, pair[0], pair[1]).map_err(err)?;
+            }
+        }
+        "M" | "MZ" | "MX" | "MR" | "MRZ" | "MRX" => {
+            let flip = if args.is_empty() { 0.0 } else { probability(0)? };
+            let x_basis = name.ends_with('X');
+            for (q, inverted) in qubits(true)? {
+                let record = circuit.num_measurements;
+                circuit.num_measurements += 1;
+                if x_basis {
+                    push(circuit, Operation::Gate { gate: Gate::H, qubits: vec![q] });
+                }
+                // Recording !q is the same as flipping q around the measurement.
+                if inverted {
+                    push(circuit, Operation::Gate { gate: Gate::X, qubits: vec![q] });
+                }
+                push(circuit, Operation::Measure { qubit: q, clbit: record });
+                if inverted {
+                    push(circuit, Operation::Gate { gate: Gate::X, qubits: vec![q] });
+                }
+                if flip > 0.0 {
+                    circuit.ops.push(StimOp::RecordFlip { record, probability: flip });
+                }
+                if name.starts_with("MR") {
+                    push(circuit, Operation::Reset(q));
+                }
+                if x_basis {
+                    push(circuit, Operation::Gate { gate: Gate::H, qubits: vec![q] });
+                }
+            }
+        }
+        "R" | "RZ" | "RX" => {
+            for (q, _) in qubits(false)? {
+                push(circuit, Operation::Reset(q));
+                if name == "RX" {
+                    push(circuit, Operation::Gate { gate: Gate::H, qubits: vec![q] });
+                }
+            }
+        }
+        "X_ERROR" | "Y_ERROR" | "Z_ERROR" | "DEPOLARIZE1" | "DEPOLARIZE2" | "PAULI_CHANNEL_1" => {
+            let channel = match name.as_str() {
+                "X_ERROR" => NoiseChannel::XError(probability(0)?),
+                "Y_ERROR" => NoiseChannel::YError(probability(0)?),
+                "Z_ERROR" => NoiseChannel::ZError(probability(0)?),
+                "DEPOLARIZE1" => NoiseChannel::Depolarize1(probability(0)?),
+                "DEPOLARIZE2" => NoiseChannel::Depolarize2(probability(0)?),
+                _ => {
+                    let (px, py, pz) = (probability(0)?, probability(1)?, probability(2)?);
+                    if px + py + pz > 1.0 {
+                        return Err(err("PAULI_CHANNEL_1 probabilities add up to more than 1".to_string()));
+                    }
+                    NoiseChannel::PauliChannel1(px, py, pz)
+                }
+            };
+            let qubits: Vec<usize> = qubits(false)?.into_iter().map(|(q, _)| q).collect();
+            if name == "DEPOLARIZE2" && !qubits.len().is_multiple_of(2) {
+                return Err(err("DEPOLARIZE2 needs an even number of targets".to_string()));
+            }
+            circuit.ops.push(StimOp::Noise { channel, qubits });
+        }
+        "DETECTOR" | "OBSERVABLE_INCLUDE" => {
+            let records = targets.iter().map(|t| match *t {
+                Target::Rec(r) => Ok(r),
+                _ => Err(err(format!("{} only takes rec[-k] targets", name))),
+            }).collect::<Result<Vec<_>, _>>()?;
+            if name == "DETECTOR" {
+                circuit.detectors.push(records);
+            } else {
+                let index = match args[..] {
+                    [k] if k >= 0.0 && k.fract() == 0.0 => k as usize,
+                    _ => return Err(err("OBSERVABLE_INCLUDE needs an observable index".to_string())),
+                };
+                if circuit.observables.len() <= index {
+                    circuit.observables.resize(index + 1, Vec::new());
+                }
+                circuit.observables[index].extend(records);
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// One pair of a two-qubit gate. A `rec[-k]` control makes the Pauli on the other
+/// target conditional on that measurement.
+fn parse_pair_gate(circuit: &mut StimCircuit, name: &str, a: Target, b: Target) -> Result<(), String> {
+    let pauli = match name {
+        "CX" | "CNOT" | "ZCX" => Gate::X,
+        "CY" | "ZCY" => Gate::Y,
+        "CZ" | "ZCZ" => Gate::Z,
+        _ => {
+            let (Target::Qubit(a), Target::Qubit(b)) = (a, b) else {
+                return Err(format!("{} only takes qubit targets", name));
+            };
+            for (c, t) in [(a, b), (b, a), (a, b)] {
+                circuit.ops.push(StimOp::Instruction(Instruction::new(Operation::Gate { gate: Gate::CX, qubits: vec![c, t] })));
+            }
+            return Ok(());
+        }
+    };
+    let inst = match (a, b) {
+        (Target::Qubit(c), Target::Qubit(t)) if c == t => return Err(format!("{} on qubit {} with itself", name, c)),
+        (Target::Qubit(c), Target::Qubit(t)) => Instruction::new(match pauli {
+            Gate::X => Operation::Gate { gate: Gate::CX, qubits: vec![c, t] },
+            Gate::Z => Operation::Gate { gate: Gate::CZ, qubits: vec![c, t] },
+            gate => Operation::Controlled { gate, controls: vec![c], neg_controls: vec![], target: t },
+        }),
+        (Target::Rec(r), Target::Qubit(t)) | (Target::Qubit(t), Target::Rec(r)) if pauli == Gate::Z || matches!(a, Target::Rec(_)) => {
+            Instruction { op: Operation::Gate { gate: pauli, qubits: vec![t] }, condition: Some(Condition::new(&[r], 1)) }
+        }
+        _ => return Err(format!("invalid targets for {}", name)),
+    };
+    circuit.ops.push(StimOp::Instruction(inst));
+    Ok(())
+}
+
+impl QuantumCircuit {
+    /// Runs one shot of a Stim circuit from the current state, sampling noise and
+    /// measurements with the simulator's generator (see `set_seed`).
+    pub fn run_stim(&mut self, program: &StimCircuit) -> StimSample {
+        assert!(program.num_qubits <= self.num_qubits,
+                "Circuit uses {} qubits but the simulator has {}", program.num_qubits, self.num_qubits);
+        let mut records = vec![false; program.num_measurements];
+        for op in &program.ops {
+            match op {
+                StimOp::Instruction(inst) => self.apply_instruction(inst, &mut records),
+                StimOp::Noise { channel, qubits } => self.apply_noise(*channel, qubits),
+                StimOp::RecordFlip { record, probability } => {
+                    if self.rng.random::<f64>() < *probability {
+                        records[*record] = !records[*record];
+                    }
+                }
+            }
+        }
+        let parity = |indices: &Vec<usize>| indices.iter().fold(false, |acc, &r| acc ^ records[r]);
+        StimSample {
+            detectors: program.detectors.iter().map(parity).collect(),
+            observables: program.observables.iter().map(parity).collect(),
+            measurements: records,
+        }
+    }
+
+    /// Samples and applies one Pauli per qubit (per pair for `Depolarize2`).
+    pub fn apply_noise(&mut self, channel: NoiseChannel, qubits: &[usize]) {
+        if let NoiseChannel::Depolarize2(p) = channel {
+            for pair in qubits.chunks(2) {
+                if self.rng.random::<f64>() < p {
+                    let k = self.rng.random_range(1..16);
+                    self.apply_pauli(k % 4, pair[0]);
+                    self.apply_pauli(k / 4, pair[1]);
+                }
+            }
+            return;
+        }
+        let (px, py, pz) = match channel {
+            NoiseChannel::XError(p) => (p, 0.0, 0.0),
+            NoiseChannel::YError(p) => (0.0, p, 0.0),
+            NoiseChannel::ZError(p) => (0.0, 0.0, p),
+            NoiseChannel::Depolarize1(p) => (p / 3.0, p / 3.0, p / 3.0),
+            NoiseChannel::PauliChannel1(px, py, pz) => (px, py, pz),
+            NoiseChannel::Depolarize2(_) => unreachable!(),
+        };
+        for &q in qubits {
+            let r = self.rng.random::<f64>();
+            if r < px {
+                self.apply_pauli(1, q);
+            } else if r < px + py {
+                self.apply_pauli(2, q);
+            } else if r < px + py + pz {
+                self.apply_pauli(3, q);
+            }
+        }
+    }
+
+    /// 0 = I, 1 = X, 2 = Y, 3 = Z.
+    fn apply_pauli(&mut self, pauli: usize, qubit: usize) {
+        match pauli {
+            1 => self.apply_x(qubit),
+            2 => self.apply_y(qubit),
+            3 => self.apply_z(qubit),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Distance-3 repetition code memory experiment with two rounds.
+    const REPETITION: &str = "
+        R 0 1 2 3 4
+        TICK
+        REPEAT 2 {
+            CX 0 1 2 3
+            CX 2 1 4 3
+            MR 1 3
+            DETECTOR(1, 0) rec[-2] rec[-4]
+            DETECTOR(3, 0) rec[-1] rec[-3]
+        }
+        M 0 2 4
+        DETECTOR rec[-2] rec[-3] rec[-5]
+        OBSERVABLE_INCLUDE(0) rec[-1]
+    ";
+
+    #[test]
+    fn test_repeat_unrolls_and_resolves_records() {
+        // The first round's detectors look back past the start; give it a reference round.
+        let src = format!("M 1 3\n{}", REPETITION);
+        let circ = parse_stim(&src).unwrap();
+        assert_eq!(circ.num_qubits, 5);
+        assert_eq!(circ.num_measurements, 2 + 2 * 2 + 3);
+        assert_eq!(circ.detectors[..2], [vec![2, 0], vec![3, 1]]);
+        assert_eq!(circ.detectors[2..4], [vec![4, 2], vec![5, 3]]);
+        assert_eq!(circ.detectors[4], vec![7, 6, 4]);
+        assert_eq!(circ.observables, vec![vec![8]]);
+    }
+
+    #[test]
+    fn test_noiseless_detectors_are_zero() {
+        let circ = parse_stim(&format!("M 1 3\n{}", REPETITION)).unwrap();
+        let mut sim = QuantumCircuit::new(circ.num_qubits);
+        sim.set_seed(1);
+        let sample = sim.run_stim(&circ);
+        assert!(sample.detectors.iter().all(|&d| !d));
+        assert_eq!(sample.observables, vec![false]);
+    }
+
+    #[test]
+    fn test_certain_error_fires_detectors() {
+        let src = format!("M 1 3\n{}", REPETITION.replace("TICK", "X_ERROR(1) 4"));
+        let circ = parse_stim(&src).unwrap();
+        let mut sim = QuantumCircuit::new(circ.num_qubits);
+        sim.set_seed(2);
+        let sample = sim.run_stim(&circ);
+        assert_eq!(sample.detectors, vec![false, true, false, false, false]);
+        assert_eq!(sample.observables, vec![true]);
+    }
+
+    #[test]
+    fn test_x_stabilizer_with_feedback() {
+        // Measures XX on a Bell pair through an ancilla, then uses the result to flip a qubit.
+        let src = "
+            RX 0
+            CX 0 1
+            REPEAT 3 {
+                R 2
+                H 2
+                CX 2 0 2 1
+                H 2
+                M 2
+                DETECTOR rec[-1]
+            }
+            M !0
+            CX rec[-1] 3
+            M 3 1
+            DETECTOR rec[-3] rec[-2]
+            DETECTOR rec[-2] rec[-1]
+        ";
+        let circ = parse_stim(src).unwrap();
+        for seed in 0..5 {
+            let mut sim = QuantumCircuit::new(circ.num_qubits);
+            sim.set_seed(seed);
+            let sample = sim.run_stim(&circ);
+            // The last detector compares q3 (set to !q0 by feedback) with q1 (equal to q0).
+            assert_eq!(sample.detectors, vec![false, false, false, false, true]);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse_stim("H 0\nM 0\nDETECTOR rec[-2]").unwrap_err().line, 3);
+        assert_eq!(parse_stim("REPEAT 2 {\nH 0\n").unwrap_err().line, 2);
+        assert!(parse_stim("X_ERROR(1.5) 0").unwrap_err().message.contains("outside"));
+        assert!(parse_stim("MPP X0*X1").unwrap_err().message.contains("unsupported"));
+    }
+}